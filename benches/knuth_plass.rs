@@ -0,0 +1,54 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use text_layout::{Item, KnuthPlass, ParagraphLayout};
+
+const TEXT: &str =
+    "Far out in the uncharted backwaters of the unfashionable end of the western spiral \
+                    arm of the Galaxy lies a small unregarded yellow sun.";
+
+fn glue(width: f32, stretch: f32) -> Item {
+    Item::glue(width, stretch, 0.0, ())
+}
+
+/// Returns the items for a short paragraph followed by `spaces` trailing spaces, each of which is
+/// preceded by a break opportunity. Every feasible break within the trailing spaces discards the
+/// rest of them from the start of the next line.
+fn trailing_glue(spaces: usize) -> Vec<Item> {
+    let mut items = Vec::new();
+    for c in TEXT.chars() {
+        items.push(if c == ' ' {
+            glue(1.0, 1.0)
+        } else {
+            Item::Box {
+                width: 1.0,
+                data: (),
+            }
+        });
+    }
+    for _ in 0..spaces {
+        items.push(Item::penalty(0.0, ()));
+        items.push(glue(1.0, 1.0));
+    }
+    items.push(glue(0.0, f32::INFINITY));
+    items.push(Item::forced_break(()));
+    items
+}
+
+fn bench_knuth_plass(c: &mut Criterion) {
+    let knuth_plass = KnuthPlass::new()
+        .with_threshold(f32::INFINITY)
+        .with_rigid_glue_fallback(true);
+
+    let items = trailing_glue(0);
+    c.bench_function("knuth_plass_paragraph", |b| {
+        b.iter(|| knuth_plass.layout_paragraph(black_box(&items), 20.0))
+    });
+
+    // A wide line lets many of the trailing breaks be reached from the paragraph's text.
+    let items = trailing_glue(2_000);
+    c.bench_function("knuth_plass_trailing_glue", |b| {
+        b.iter(|| knuth_plass.layout_paragraph(black_box(&items), 1000.0))
+    });
+}
+
+criterion_group!(benches, bench_knuth_plass);
+criterion_main!(benches);
@@ -1,6 +1,5 @@
 extern crate text_layout;
-use std::fmt::{self, Write};
-use text_layout::{FirstFit, Item, ParagraphLayout};
+use text_layout::{render_framed, FirstFit, Item, ParagraphLayout, PenaltyFlags};
 
 fn layout_paragraph<'a, P: ParagraphLayout>(
     paragraph: &'a str,
@@ -11,12 +10,7 @@ fn layout_paragraph<'a, P: ParagraphLayout>(
     let mut items = Vec::new();
     for c in paragraph.chars() {
         items.push(if c.is_whitespace() && items.len() != 0 {
-            Item::Glue {
-                width: 1.0,
-                stretch: 1.0,
-                shrink: 0.0,
-                data: (),
-            }
+            Item::glue(1.0, 1.0, 0.0, ())
         } else {
             Item::Box {
                 width: 1.0,
@@ -24,16 +18,11 @@ fn layout_paragraph<'a, P: ParagraphLayout>(
             }
         });
     }
-    items.push(Item::Glue {
-        width: 0.0,
-        stretch: 100000.0,
-        shrink: 0.0,
-        data: (),
-    });
+    items.push(Item::glue(0.0, 100000.0, 0.0, ()));
     items.push(Item::Penalty {
         width: 0.0,
         cost: f32::NEG_INFINITY,
-        flagged: true,
+        flags: PenaltyFlags::AVOID_CONSECUTIVE,
         data: (),
     });
 
@@ -55,25 +44,17 @@ fn layout_paragraph<'a, P: ParagraphLayout>(
     lines
 }
 
-fn layout_text() -> Result<String, fmt::Error> {
+fn layout_text() -> String {
     let text = "FaroutintheunchartedbackwatersoftheunfashionableendofthewesternspiralarmoftheGalaxy lies a small unregarded yellow sun. Orbiting this at a distance of roughly ninety-two million miles is an utterly insignificant little blue-green planet whose ape-descended life forms are so amazingly primitive that they still think digital watches are a pretty neat idea.";
     let first_fit = FirstFit::new()
         .with_threshold(f32::INFINITY)
         .allow_overflow(true);
     let lines = layout_paragraph(&text, &first_fit, 80);
-    let mut result = String::new();
-    writeln!(&mut result, "┏{}┓", "━".repeat(80))?;
-    for l in lines {
-        let pad = 80_usize.saturating_sub(l.chars().count());
-        writeln!(&mut result, "┃{}{}┃", l, " ".repeat(pad))?;
-    }
-    writeln!(&mut result, "┗{}┛", "━".repeat(80))?;
-    Ok(result)
+    render_framed(&lines, 80)
 }
 
-fn main() -> Result<(), fmt::Error> {
-    print!("{}", layout_text()?);
-    Ok(())
+fn main() {
+    print!("{}", layout_text());
 }
 
 #[cfg(test)]
@@ -90,7 +71,7 @@ mod tests {
 ┃digital watches are a pretty neat idea.                                         ┃
 ┗━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┛
 "#;
-        let actual = layout_text().unwrap();
+        let actual = layout_text();
         assert!(actual == expected);
     }
 }
@@ -2,26 +2,16 @@ extern crate fixed;
 extern crate text_layout;
 
 use fixed::types::I16F16;
-use std::fmt::{self, Write};
-use text_layout::{Fixed, Item, KnuthPlass, ParagraphLayout};
+use text_layout::{render_framed, Fixed, Item, KnuthPlass, ParagraphLayout, PenaltyFlags};
 
 type F = Fixed<I16F16>;
 
-fn layout_paragraph<'a, P: ParagraphLayout<(), (), (), F>>(
-    paragraph: &'a str,
-    layout: &P,
-    max_width: F,
-) -> Vec<&'a str> {
+fn paragraph_items(paragraph: &str) -> Vec<Item<(), (), (), F>> {
     // Process the paragraph into its items.
     let mut items = Vec::new();
     for c in paragraph.chars() {
         items.push(if c.is_whitespace() && items.len() != 0 {
-            Item::Glue {
-                width: F::from_num(1),
-                stretch: F::from_num(1),
-                shrink: F::from_num(0),
-                data: (),
-            }
+            Item::glue(F::from_num(1), F::from_num(1), F::from_num(0), ())
         } else {
             Item::Box {
                 width: F::from_num(1),
@@ -29,21 +19,24 @@ fn layout_paragraph<'a, P: ParagraphLayout<(), (), (), F>>(
             }
         });
     }
-    items.push(Item::Glue {
-        width: F::from_num(0),
-        stretch: F::MAX,
-        shrink: F::from_num(0),
-        data: (),
-    });
+    items.push(Item::glue(F::from_num(0), F::MAX, F::from_num(0), ()));
     items.push(Item::Penalty {
         width: F::from_num(0),
         cost: F::MIN,
-        flagged: true,
+        flags: PenaltyFlags::AVOID_CONSECUTIVE,
         data: (),
     });
 
+    items
+}
+
+fn layout_paragraph<'a, P: ParagraphLayout<(), (), (), F>>(
+    paragraph: &'a str,
+    layout: &P,
+    max_width: F,
+) -> Vec<&'a str> {
     // Calculate the paragraph's breaks.
-    let breaks = layout.layout_paragraph(&items, max_width);
+    let breaks = layout.layout_paragraph(&paragraph_items(paragraph), max_width);
 
     // Render the laid-out paragraph using the break positions.
     let mut cursor = 0;
@@ -60,40 +53,32 @@ fn layout_paragraph<'a, P: ParagraphLayout<(), (), (), F>>(
     lines
 }
 
-fn layout_text() -> Result<String, fmt::Error> {
-    let text = "  Far out in the uncharted backwaters of the unfashionable end of the western spiral arm of the Galaxy lies a small unregarded yellow sun. Orbiting this at a distance of roughly ninety-two million miles is an utterly insignificant little blue-green planet whose ape-descended life forms are so amazingly primitive that they still think digital watches are a pretty neat idea.";
+const TEXT: &str = "  Far out in the uncharted backwaters of the unfashionable end of the western spiral arm of the Galaxy lies a small unregarded yellow sun. Orbiting this at a distance of roughly ninety-two million miles is an utterly insignificant little blue-green planet whose ape-descended life forms are so amazingly primitive that they still think digital watches are a pretty neat idea.";
+
+fn layout_text() -> String {
     let knuth_plass = KnuthPlass::new().with_threshold(F::MAX);
-    let lines = layout_paragraph(&text, &knuth_plass, F::from_num(80));
-    let mut result = String::new();
-    writeln!(&mut result, "┏{}┓", "━".repeat(80))?;
-    for l in lines {
-        let pad = 80 - l.chars().count();
-        writeln!(&mut result, "┃{}{}┃", l, " ".repeat(pad))?;
-    }
-    writeln!(&mut result, "┗{}┛", "━".repeat(80))?;
-    Ok(result)
+    let lines = layout_paragraph(TEXT, &knuth_plass, F::from_num(80));
+    render_framed(&lines, 80)
 }
 
-fn main() -> Result<(), fmt::Error> {
-    print!("{}", layout_text()?);
-    Ok(())
+fn main() {
+    print!("{}", layout_text());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Asserting on break positions rather than rendered output keeps this test independent of
+    // how the lines are rendered.
     #[test]
     fn fixed() {
-        let expected = r#"┏━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┓
-┃  Far out in the uncharted backwaters of the unfashionable end of the western   ┃
-┃spiral arm of the Galaxy lies a small unregarded yellow sun. Orbiting this at a ┃
-┃distance of roughly ninety-two million miles is an utterly insignificant little ┃
-┃blue-green planet whose ape-descended life forms are so amazingly primitive that┃
-┃they still think digital watches are a pretty neat idea.                        ┃
-┗━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┛
-"#;
-        let actual = layout_text().unwrap();
-        assert!(actual == expected);
+        let breaks: Vec<usize> = KnuthPlass::new()
+            .with_threshold(F::MAX)
+            .layout_paragraph(&paragraph_items(TEXT), F::from_num(80))
+            .iter()
+            .map(|l| l.break_at)
+            .collect();
+        assert_eq!(breaks, [77, 157, 237, 318, 376]);
     }
 }
@@ -1,22 +1,12 @@
 extern crate text_layout;
-use std::fmt::{self, Write};
-use text_layout::{Item, KnuthPlass, ParagraphLayout};
+use text_layout::{render_framed, Item, KnuthPlass, ParagraphLayout, PenaltyFlags};
 
-fn layout_paragraph<'a, P: ParagraphLayout>(
-    paragraph: &'a str,
-    layout: &P,
-    max_width: usize,
-) -> Vec<&'a str> {
+fn paragraph_items(paragraph: &str) -> Vec<Item> {
     // Process the paragraph into its items.
     let mut items = Vec::new();
     for c in paragraph.chars() {
         items.push(if c.is_whitespace() && items.len() != 0 {
-            Item::Glue {
-                width: 1.0,
-                stretch: 1.0,
-                shrink: 0.0,
-                data: (),
-            }
+            Item::glue(1.0, 1.0, 0.0, ())
         } else {
             Item::Box {
                 width: 1.0,
@@ -24,21 +14,24 @@ fn layout_paragraph<'a, P: ParagraphLayout>(
             }
         });
     }
-    items.push(Item::Glue {
-        width: 0.0,
-        stretch: 100000.0,
-        shrink: 0.0,
-        data: (),
-    });
+    items.push(Item::glue(0.0, 100000.0, 0.0, ()));
     items.push(Item::Penalty {
         width: 0.0,
         cost: f32::NEG_INFINITY,
-        flagged: true,
+        flags: PenaltyFlags::AVOID_CONSECUTIVE,
         data: (),
     });
 
+    items
+}
+
+fn layout_paragraph<'a, P: ParagraphLayout>(
+    paragraph: &'a str,
+    layout: &P,
+    max_width: usize,
+) -> Vec<&'a str> {
     // Calculate the paragraph's breaks.
-    let breaks = layout.layout_paragraph(&items, max_width as f32);
+    let breaks = layout.layout_paragraph(&paragraph_items(paragraph), max_width as f32);
 
     // Render the laid-out paragraph using the break positions.
     let mut cursor = 0;
@@ -55,40 +48,32 @@ fn layout_paragraph<'a, P: ParagraphLayout>(
     lines
 }
 
-fn layout_text() -> Result<String, fmt::Error> {
-    let text = "  Far out in the uncharted backwaters of the unfashionable end of the western spiral arm of the Galaxy lies a small unregarded yellow sun. Orbiting this at a distance of roughly ninety-two million miles is an utterly insignificant little blue-green planet whose ape-descended life forms are so amazingly primitive that they still think digital watches are a pretty neat idea.";
+const TEXT: &str = "  Far out in the uncharted backwaters of the unfashionable end of the western spiral arm of the Galaxy lies a small unregarded yellow sun. Orbiting this at a distance of roughly ninety-two million miles is an utterly insignificant little blue-green planet whose ape-descended life forms are so amazingly primitive that they still think digital watches are a pretty neat idea.";
+
+fn layout_text() -> String {
     let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
-    let lines = layout_paragraph(&text, &knuth_plass, 80);
-    let mut result = String::new();
-    writeln!(&mut result, "┏{}┓", "━".repeat(80))?;
-    for l in lines {
-        let pad = 80 - l.chars().count();
-        writeln!(&mut result, "┃{}{}┃", l, " ".repeat(pad))?;
-    }
-    writeln!(&mut result, "┗{}┛", "━".repeat(80))?;
-    Ok(result)
+    let lines = layout_paragraph(TEXT, &knuth_plass, 80);
+    render_framed(&lines, 80)
 }
 
-fn main() -> Result<(), fmt::Error> {
-    print!("{}", layout_text()?);
-    Ok(())
+fn main() {
+    print!("{}", layout_text());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Asserting on break positions rather than rendered output keeps this test independent of
+    // how the lines are rendered.
     #[test]
     fn readme() {
-        let expected = r#"┏━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┓
-┃  Far out in the uncharted backwaters of the unfashionable end of the western   ┃
-┃spiral arm of the Galaxy lies a small unregarded yellow sun. Orbiting this at a ┃
-┃distance of roughly ninety-two million miles is an utterly insignificant little ┃
-┃blue-green planet whose ape-descended life forms are so amazingly primitive that┃
-┃they still think digital watches are a pretty neat idea.                        ┃
-┗━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┛
-"#;
-        let actual = layout_text().unwrap();
-        assert!(actual == expected);
+        let breaks: Vec<usize> = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&paragraph_items(TEXT), 80.0)
+            .iter()
+            .map(|l| l.break_at)
+            .collect();
+        assert_eq!(breaks, [77, 157, 237, 318, 376]);
     }
 }
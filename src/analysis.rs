@@ -0,0 +1,1850 @@
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+use core::cmp::Reverse;
+use core::fmt::{self, Debug, Display};
+use core::ops::Range;
+
+use crate::justify::infinite_order_widths;
+use crate::math::Num;
+use crate::region::{line_height, vertical_metrics};
+use crate::{
+    badness, range_adjustment_ratio, sum_metrics, BoxMetrics, FirstFit, Fitness, GlueMetrics, Item,
+    Justifier, KnuthPlass, Line, ParagraphLayout,
+};
+
+/// Checks that a previously-computed set of lines is still feasible for the given items and line
+/// width, e.g. after a font change has altered the items' widths. A line is feasible if it breaks
+/// at an item that follows the previous line's break and its adjustment ratio lies within
+/// `[-1, threshold]`.
+///
+/// Returns the index of the first infeasible line, if any.
+pub fn validate_layout<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+    line_width: N,
+    threshold: N,
+) -> Result<(), usize> {
+    let mut start = 0;
+    for (i, line) in lines.iter().enumerate() {
+        if line.break_at < start || line.break_at >= items.len() {
+            return Err(i);
+        }
+        let r = range_adjustment_ratio(items, start, line.break_at, line_width);
+        if r < N::from(-1) || r > threshold {
+            return Err(i);
+        }
+        start = line.break_at + 1;
+    }
+    Ok(())
+}
+
+/// Returns the width actually occupied by a layout, i.e. the greatest width of any of its lines
+/// when set without stretching. A shrink-to-fit container may be sized to this width for ragged
+/// text, or the paragraph may be laid out again at this width to remove trailing space.
+///
+/// The natural width of a line includes the width of a penalty at which it breaks, but not the
+/// width of discardable glue at which it breaks. Lines that are shrunk to fit are measured at
+/// `line_width`.
+pub fn used_width<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+    line_width: N,
+) -> N {
+    let mut used = N::from(0);
+    for (line, mut width) in lines.iter().zip(break_columns(items, lines)) {
+        if width > line_width && line.adjustment_ratio < N::from(0) {
+            width = line_width;
+        }
+        if width > used {
+            used = width;
+        }
+    }
+    used
+}
+
+/// Returns the column at which each line ends, i.e. the natural width of the line's items from
+/// its start up to its break. For monospace text in which every character is an item of width 1,
+/// this is the number of characters on the line, e.g. to pad each line when rendering it to a
+/// terminal. Like `used_width`, this includes the width of a penalty at which the line breaks,
+/// but not the width of discardable glue at which it breaks.
+pub fn break_columns<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+) -> Vec<N> {
+    let mut start = 0;
+    lines
+        .iter()
+        .map(|line| {
+            let (mut width, _, _) = sum_metrics(items, start..line.break_at);
+            width += items[line.break_at].break_metrics().0;
+            start = line.break_at + 1;
+            width
+        })
+        .collect()
+}
+
+/// Returns the height of a block of laid-out lines whose heights are given by the vertical
+/// metrics of their boxes, with `leading` added between consecutive lines. This is the vertical
+/// counterpart of `used_width`, e.g. to size a container for text that mixes sizes. As when a
+/// paragraph is laid out into a region, the height of each line is the sum of the greatest height
+/// and the greatest depth of its boxes, after each box is shifted by its vertical shift, and lines
+/// without boxes have no height.
+pub fn block_height<Box: BoxMetrics<N>, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+    leading: N,
+) -> N {
+    let metrics = vertical_metrics(items);
+    let mut height = N::from(0);
+    for (i, (start, l)) in line_starts(lines).into_iter().zip(lines).enumerate() {
+        if i != 0 {
+            height += leading;
+        }
+        height += line_height(&metrics[start..l.break_at]);
+    }
+    height
+}
+
+/// Returns the height of a document whose paragraphs were laid out into the given lines, e.g. by
+/// `ParagraphLayout::layout_document`, when every line is `line_height` tall and consecutive
+/// paragraphs are separated by `para_spacing`. Paragraphs without lines take up no space and are
+/// not separated from their neighbors.
+pub fn document_height<N: Num>(paragraphs: &[Vec<Line<N>>], line_height: N, para_spacing: N) -> N {
+    let mut height = N::from(0);
+    let mut first = true;
+    for lines in paragraphs.iter().filter(|lines| !lines.is_empty()) {
+        if !first {
+            height += para_spacing;
+        }
+        first = false;
+        for _ in lines {
+            height += line_height;
+        }
+    }
+    height
+}
+
+/// Returns the index of the first item of each line. The first line starts at item 0, and every
+/// other line starts at the item that follows the previous line's break, as the break item itself
+/// belongs to neither line.
+pub fn line_starts<N: Num>(lines: &[Line<N>]) -> Vec<usize> {
+    let mut start = 0;
+    lines
+        .iter()
+        .map(|l| {
+            let s = start;
+            start = l.break_at + 1;
+            s
+        })
+        .collect()
+}
+
+/// The items of a laid-out line, as returned by `line_items`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineItems {
+    /// The items that are rendered on the line, including the item at which the line breaks if
+    /// it contributes to the line's width.
+    pub rendered: Range<usize>,
+    /// The index of the item at which the line breaks if the break consumes it, i.e. if it is
+    /// rendered on neither line.
+    pub consumed: Option<usize>,
+}
+
+/// Returns the items that are rendered on each line and the break item that each line consumes,
+/// e.g. so that a renderer or hit-tester can skip the space at which a line breaks. A line
+/// renders the items from the previous line's break up to its own break. The break item is
+/// consumed if it is discardable glue; any other break item, e.g. a penalty that adds a hyphen or
+/// glue that is not discardable, is rendered at the end of the line.
+pub fn line_items<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+) -> Vec<LineItems> {
+    line_starts(lines)
+        .into_iter()
+        .zip(lines)
+        .map(|(start, l)| match items[l.break_at] {
+            Item::Glue {
+                discardable: true, ..
+            } => LineItems {
+                rendered: start..l.break_at,
+                consumed: Some(l.break_at),
+            },
+            _ => LineItems {
+                rendered: start..l.break_at + 1,
+                consumed: None,
+            },
+        })
+        .collect()
+}
+
+/// Returns the number of glue items on each line, excluding the glue at which the line breaks.
+/// This is the `glue_count` that `Line::glue_width_with` expects for
+/// `GlueDistribution::Uniform`. The finishing glue of the last line is counted like any other
+/// glue.
+pub fn line_glue_counts<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+) -> Vec<usize> {
+    line_starts(lines)
+        .into_iter()
+        .zip(lines)
+        .map(|(start, l)| {
+            items[start..l.break_at]
+                .iter()
+                .filter(|item| matches!(item, Item::Glue { .. }))
+                .count()
+        })
+        .collect()
+}
+
+/// Returns the badness of each line, i.e. `100 * |r|³` where `r` is the line's adjustment ratio.
+/// This is the same badness from which Knuth-Plass computes demerits, so it can be used to flag
+/// poorly-set lines, e.g. across a corpus of documents. Lines with an infinite adjustment ratio
+/// have infinite badness.
+pub fn line_badness<N: Num>(lines: &[Line<N>]) -> Vec<N> {
+    lines.iter().map(|l| badness(l.adjustment_ratio)).collect()
+}
+
+/// Returns the adjustment ratio of each line, e.g. to find the lines that come closest to the
+/// threshold of a layout.
+pub fn line_ratios<N: Num>(lines: &[Line<N>]) -> Vec<N> {
+    lines.iter().map(|l| l.adjustment_ratio).collect()
+}
+
+/// Recomputes the adjustment ratio of each line from scratch as if the lines were set at
+/// `line_width`, e.g. to see which lines would be stressed if a previously-computed layout were
+/// reused at a different width. Unlike the ratios stored in the lines, the recomputed ratios do not
+/// account for indents, trimmed leading glue, or any fallback applied by the layout algorithm.
+pub fn recompute_line_ratios<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+    line_width: N,
+) -> Vec<N> {
+    let mut start = 0;
+    lines
+        .iter()
+        .map(|l| {
+            let r = range_adjustment_ratio(items, start, l.break_at, line_width);
+            start = l.break_at + 1;
+            r
+        })
+        .collect()
+}
+
+/// Returns the amount by which the widest unbreakable run of items exceeds `line_width`, or 0 if
+/// every run fits. A run spans the items between two consecutive legal breakpoints and is measured
+/// like a line, i.e. fully shrunk, including the width of a penalty at which it ends but not the
+/// width of discardable glue at which it ends. This is the amount by which the line width must
+/// grow before the paragraph can be laid out without overflow, e.g. to tell a user to widen a
+/// column.
+pub fn overflow_amount<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    line_width: N,
+) -> N {
+    let mut overflow = N::from(0);
+    let (mut width, mut shrink) = (N::from(0), N::from(0));
+    for (i, item) in items.iter().enumerate() {
+        let (w, _, z, is_legal) = item.is_legal_breakpoint((i != 0).then(|| &items[i - 1]));
+        let z = z.value();
+        let run_width = if is_legal {
+            let (break_width, _, break_shrink) = item.break_metrics();
+            width - shrink + break_width - break_shrink
+        } else if i + 1 == items.len() {
+            width + w - (shrink + z)
+        } else {
+            width += w;
+            shrink += z;
+            continue;
+        };
+        if run_width - line_width > overflow {
+            overflow = run_width - line_width;
+        }
+        (width, shrink) = (N::from(0), N::from(0));
+    }
+    overflow
+}
+
+/// Describes why a numeric type cannot represent the metrics of a paragraph reliably, as reported
+/// by `check_precision`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrecisionError<N> {
+    /// The total width of the paragraph or the line width saturates to the largest representable
+    /// value, which stands in for infinity, so widths and adjustment ratios will be wrong.
+    Saturated,
+    /// The total width of the paragraph exceeds a quarter of the largest representable value, so
+    /// intermediate sums may saturate during layout.
+    NearLimit {
+        /// The total width of the paragraph.
+        total: N,
+    },
+}
+
+impl<N: Debug> Display for PrecisionError<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrecisionError::Saturated => write!(f, "the paragraph's width saturates"),
+            PrecisionError::NearLimit { total } => write!(
+                f,
+                "the paragraph's total width {total:?} is too close to the largest representable value"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<N: Debug> std::error::Error for PrecisionError<N> {}
+
+/// Checks that a numeric type has enough range to lay out a paragraph, e.g. before laying it out
+/// with a small fixed-point type. The total width of the paragraph's items, counting each width's
+/// magnitude, must stay well below `N::INFINITY`, which saturating types also use as their
+/// largest value. Stretch and shrink are not checked, as they are legitimately infinite in
+/// finishing glue.
+pub fn check_precision<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    line_width: N,
+) -> Result<(), PrecisionError<N>> {
+    let mut total = N::from(0);
+    for item in items {
+        let width = match *item {
+            Item::Box { width, .. }
+            | Item::Glue { width, .. }
+            | Item::Penalty { width, .. }
+            | Item::Kern { width }
+            | Item::Tab { width }
+            | Item::BreakGlue {
+                break_width: width, ..
+            } => width,
+        };
+        total += width.abs();
+    }
+
+    if total >= N::INFINITY || line_width.abs() >= N::INFINITY {
+        return Err(PrecisionError::Saturated);
+    }
+    if total > N::INFINITY / N::from(4) || line_width.abs() > N::INFINITY / N::from(4) {
+        return Err(PrecisionError::NearLimit { total });
+    }
+    Ok(())
+}
+
+/// Options that control how `layout_positions` treats glue and indentation at the edges of each
+/// line, and how it expands tabs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PositionOptions<'a, N = f32> {
+    /// If true, glue that precedes the first box on a line takes up no space, and the line's
+    /// adjustment ratio is recomputed so that the line still fills the line width.
+    pub trim_leading_glue: bool,
+    /// If true, the glue at which a line is broken is retained at the end of the line and is
+    /// assigned an offset. Otherwise it is dropped, unless it is not discardable.
+    pub retain_trailing_glue: bool,
+    /// The indents of the first line and of every other line, as passed to `with_hanging_indent`.
+    /// Each line's offsets begin at its indent, and the indent is subtracted from the line width
+    /// when a line is respaced.
+    pub hanging_indent: (N, N),
+    /// The tab stops, as ascending offsets from the left edge of the paragraph. Each tab extends
+    /// to the first stop that lies at least its minimum width past the tab's offset, or to its
+    /// minimum width if there is no such stop.
+    pub tab_stops: &'a [N],
+    /// The direction in which the items of each line are set. Lines are broken and measured the
+    /// same way in either direction.
+    pub direction: Direction,
+}
+
+impl<'a, N> PositionOptions<'a, N> {
+    /// Sets the direction in which the items of each line are set.
+    pub fn with_direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+}
+
+/// The direction in which the items of a line are set, as used by `layout_positions` and
+/// `line_extents`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Each line's first item is at its left edge.
+    #[default]
+    Ltr,
+    /// Each line's first item is at its right edge. The offset of each item is the line width
+    /// less the offset at which its right edge would be set left-to-right, so the glue at which a
+    /// line breaks is still trimmed at the logical end of the line, which is its left edge.
+    Rtl,
+}
+
+/// Computes the x-offset of each item on each line of a laid-out paragraph, relative to the left
+/// edge of the paragraph, so that each line begins at its indent. This is the reference for
+/// rendering a layout: glue is adjusted by its line's adjustment ratio, with higher-priority glue
+/// absorbing the slack first, glue at the start of a line is optionally trimmed, and the glue at
+/// which a line breaks is optionally retained.
+///
+/// The offsets for each line correspond to the items from the previous line's break up to, but
+/// not including, the line's own break, followed by the break itself if it is a penalty or
+/// retained glue. Lines whose adjustment ratio is infinite are set at their natural width. If the
+/// stretch or shrink of a line is dominated by an infinite `GlueOrder`, the glue of that order
+/// absorbs all of the line's slack in proportion to its amount and other glue keeps its natural
+/// width. Each
+/// offset is that of the item's left edge, so if lines are set right-to-left, each line begins at
+/// its indent from the right edge of the paragraph.
+pub fn layout_positions<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+    line_width: N,
+    opts: PositionOptions<'_, N>,
+) -> Vec<Vec<N>> {
+    resolve_positions(items, lines, line_width, opts, |content, line, slack| {
+        if let Some(widths) = infinite_order_widths(slack, &glue_metrics(content)) {
+            return widths;
+        }
+        let ratios = priority_ratios(content, line.adjustment_ratio);
+        content
+            .iter()
+            .filter_map(|item| match *item {
+                Item::Glue {
+                    width,
+                    stretch,
+                    shrink,
+                    priority,
+                    ..
+                } => {
+                    let adjustment_ratio = ratios
+                        .iter()
+                        .find(|&&(p, _)| p == priority)
+                        .map_or(line.adjustment_ratio, |&(_, r)| r);
+                    let line = Line {
+                        break_at: line.break_at,
+                        adjustment_ratio,
+                        truncated: false,
+                    };
+                    Some(line.glue_width(width, stretch, shrink))
+                }
+                _ => None,
+            })
+            .collect()
+    })
+}
+
+/// Computes the x-offset of each item on each line of a laid-out paragraph like
+/// `layout_positions`, but distributes each line's slack among its glue using `justifier`. The
+/// slack of a line is the total amount by which its glue would be adjusted in proportion to its
+/// stretch or shrink, so e.g. the last line of a paragraph whose finishing glue is infinitely
+/// stretchable has no slack. Glue priorities are left to the justifier, which does not see them.
+pub fn layout_positions_with<Box, Glue, Penalty, N: Num, J: Justifier<N> + ?Sized>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+    line_width: N,
+    opts: PositionOptions<'_, N>,
+    justifier: &J,
+) -> Vec<Vec<N>> {
+    resolve_positions(items, lines, line_width, opts, |content, line, slack| {
+        let glue = glue_metrics(content);
+        let slack = if infinite_order_widths(slack, &glue).is_some() {
+            slack
+        } else {
+            glue.iter().fold(N::from(0), |slack, g| {
+                slack + line.glue_width(N::from(0), g.stretch, g.shrink)
+            })
+        };
+        justifier.distribute(slack, &glue)
+    })
+}
+
+/// Returns the metrics of the glue items among the given line content.
+fn glue_metrics<Box, Glue, Penalty, N: Num>(
+    content: &[Item<Box, Glue, Penalty, N>],
+) -> Vec<GlueMetrics<N>> {
+    content
+        .iter()
+        .filter_map(|item| match *item {
+            Item::Glue {
+                width,
+                stretch,
+                stretch_order,
+                shrink,
+                shrink_order,
+                ..
+            } => Some(GlueMetrics {
+                width,
+                stretch,
+                stretch_order,
+                shrink,
+                shrink_order,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Computes the x-offset of each item on each line of a laid-out paragraph, given a function that
+/// returns the width of each glue item among a line's content, excluding trimmed leading glue,
+/// when the line is set with the given adjustment ratio. The function is also given the line's
+/// slack, i.e. the difference between the line width and the line's natural width.
+fn resolve_positions<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+    line_width: N,
+    opts: PositionOptions<'_, N>,
+    glue_widths: impl Fn(&[Item<Box, Glue, Penalty, N>], Line<N>, N) -> Vec<N>,
+) -> Vec<Vec<N>> {
+    let mut positions = Vec::with_capacity(lines.len());
+    let mut start = 0;
+    for (l, line) in lines.iter().enumerate() {
+        let indent = if l == 0 {
+            opts.hanging_indent.0
+        } else {
+            opts.hanging_indent.1
+        };
+        let content = &items[start..line.break_at];
+        let at = &items[line.break_at];
+
+        let leading = if opts.trim_leading_glue {
+            content
+                .iter()
+                .position(|item| matches!(item, Item::Box { .. } | Item::Tab { .. }))
+                .unwrap_or(content.len())
+        } else {
+            0
+        };
+        let adjustment_ratio = if opts.trim_leading_glue {
+            let (width, stretch, shrink) = sum_metrics(content, leading..content.len());
+            at.adjustment_ratio(width, stretch.value(), shrink.value(), line_width - indent)
+        } else {
+            line.adjustment_ratio
+        };
+        let line = Line {
+            break_at: line.break_at,
+            adjustment_ratio: if adjustment_ratio == N::INFINITY
+                || adjustment_ratio == N::NEG_INFINITY
+            {
+                N::from(0)
+            } else {
+                adjustment_ratio
+            },
+            truncated: false,
+        };
+
+        let (natural_width, _, _) = sum_metrics(content, leading..content.len());
+        let slack = line_width - indent - natural_width - at.break_metrics().0;
+        let mut glue_widths = glue_widths(&content[leading..], line, slack).into_iter();
+
+        let mut x = indent;
+        let mut offsets = vec![];
+        let mut widths = vec![];
+        for (i, item) in content.iter().enumerate() {
+            let width = match *item {
+                Item::Box { width, .. } | Item::Penalty { width, .. } | Item::Kern { width } => {
+                    width
+                }
+                Item::Tab { width } => {
+                    let min = x + width;
+                    let stop = opts.tab_stops.iter().find(|&&stop| stop >= min);
+                    stop.map_or(min, |&stop| stop) - x
+                }
+                Item::Glue { .. } if i < leading => N::from(0),
+                Item::BreakGlue { .. } => N::from(0),
+                Item::Glue { width, .. } => glue_widths.next().unwrap_or(width),
+            };
+            offsets.push(x);
+            widths.push(width);
+            x += width;
+        }
+        match at {
+            Item::Penalty { .. } | Item::BreakGlue { .. } => {
+                let (width, stretch, shrink) = at.break_metrics();
+                offsets.push(x);
+                widths.push(line.glue_width(width, stretch, shrink));
+            }
+            Item::Glue {
+                width, discardable, ..
+            } if opts.retain_trailing_glue || !discardable => {
+                offsets.push(x);
+                widths.push(*width);
+            }
+            _ => {}
+        }
+
+        // Mirror each item within the line width, so that the line's logical start is on the
+        // right.
+        if opts.direction == Direction::Rtl {
+            for (x, width) in offsets.iter_mut().zip(widths) {
+                *x = line_width - *x - width;
+            }
+        }
+
+        positions.push(offsets);
+        start = line.break_at + 1;
+    }
+    positions
+}
+
+/// The horizontal alignment of lines within the line width, as used by `line_extents`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    /// Lines start at the left edge and their glue is adjusted by their adjustment ratios.
+    #[default]
+    Justify,
+    /// Lines start at the left edge and are set at their natural width.
+    Left,
+    /// Lines are set at their natural width and centered within the line width.
+    Center,
+    /// Lines are set at their natural width and end at the right edge.
+    Right,
+}
+
+/// Returns the horizontal extent of the content of each line as `(left, right)`, e.g. to draw
+/// underlines or selection rectangles. The extent starts at the line's alignment offset and spans
+/// the line's items as they are set, including the width of a penalty at which the line breaks but
+/// excluding glue at the end of the line, such as the finishing glue of the last line.
+///
+/// Unless lines are justified, their glue is set at its natural width, or shrunk if the line's
+/// adjustment ratio is negative. Lines whose adjustment ratio is infinite are set at their natural
+/// width. Glue of an infinite order absorbs the slack of its line as in `layout_positions`. Justified lines start at the left edge if they are set left-to-right and end at the
+/// right edge if they are set right-to-left, so that their trailing glue is excluded at their
+/// logical end.
+pub fn line_extents<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+    line_width: N,
+    alignment: Alignment,
+    direction: Direction,
+) -> Vec<(N, N)> {
+    line_starts(lines)
+        .into_iter()
+        .zip(lines)
+        .map(|(start, l)| {
+            let r = l.adjustment_ratio;
+            let line = Line {
+                adjustment_ratio: if r == N::INFINITY || r == N::NEG_INFINITY {
+                    N::from(0)
+                } else if alignment == Alignment::Justify || r < N::from(0) {
+                    r
+                } else {
+                    N::from(0)
+                },
+                ..*l
+            };
+
+            // Glue of an infinite order absorbs the slack of a justified or overfull line.
+            let content = &items[start..l.break_at];
+            let at = &items[l.break_at];
+            let (natural_width, _, _) = sum_metrics(content, 0..content.len());
+            let slack = line_width - natural_width - at.break_metrics().0;
+            let mut infinite_order = (alignment == Alignment::Justify || slack < N::from(0))
+                .then(|| infinite_order_widths(slack, &glue_metrics(content)))
+                .flatten()
+                .map(Vec::into_iter);
+
+            // Measure up to the end of the last item that is not glue.
+            let (mut x, mut width) = (N::from(0), N::from(0));
+            for item in content {
+                match *item {
+                    Item::Glue {
+                        width,
+                        stretch,
+                        shrink,
+                        ..
+                    } => {
+                        x += infinite_order
+                            .as_mut()
+                            .and_then(Iterator::next)
+                            .unwrap_or_else(|| line.glue_width(width, stretch, shrink))
+                    }
+                    Item::Box { width: w, .. }
+                    | Item::Kern { width: w }
+                    | Item::Tab { width: w } => {
+                        x += w;
+                        width = x;
+                    }
+                    Item::Penalty { .. } | Item::BreakGlue { .. } => {}
+                }
+            }
+            if !matches!(at, Item::Glue { .. }) {
+                let (w, y, z) = at.break_metrics();
+                if w != N::from(0) {
+                    width = x + line.glue_width(w, y, z);
+                }
+            }
+
+            let left = match (alignment, direction) {
+                (Alignment::Justify, Direction::Ltr) | (Alignment::Left, _) => N::from(0),
+                (Alignment::Center, _) => (line_width - width) / N::from(2),
+                (Alignment::Justify, Direction::Rtl) | (Alignment::Right, _) => line_width - width,
+            };
+            (left, left + width)
+        })
+        .collect()
+}
+
+/// Returns the adjustment ratio of each glue priority on a line whose glue is adjusted by
+/// `adjustment_ratio` in aggregate. The slack that the line's glue would absorb in proportion to
+/// its stretch or shrink is instead handed out from the highest priority down, and each priority
+/// absorbs up to its full stretch or shrink before the next one participates. Once every priority
+/// has been fully adjusted, any remaining slack is distributed in proportion to the stretch or
+/// shrink of all of the line's glue.
+fn priority_ratios<Box, Glue, Penalty, N: Num>(
+    content: &[Item<Box, Glue, Penalty, N>],
+    adjustment_ratio: N,
+) -> Vec<(u8, N)> {
+    let stretching = adjustment_ratio > N::from(0);
+    let mut tiers: Vec<(u8, N)> = Vec::new();
+    for item in content {
+        if let Item::Glue {
+            stretch,
+            shrink,
+            priority,
+            ..
+        } = *item
+        {
+            let flex = if stretching { stretch } else { shrink };
+            match tiers.iter_mut().find(|(p, _)| *p == priority) {
+                Some((_, total)) => *total += flex,
+                None => tiers.push((priority, flex)),
+            }
+        }
+    }
+
+    let signed = |r: N| if stretching { r } else { N::from(0) - r };
+    let magnitude = signed(adjustment_ratio);
+    let total = tiers.iter().fold(N::from(0), |sum, &(_, flex)| sum + flex);
+    if magnitude == N::from(0) || magnitude >= N::from(1) || total >= N::INFINITY {
+        return tiers
+            .into_iter()
+            .map(|(p, _)| (p, adjustment_ratio))
+            .collect();
+    }
+
+    tiers.sort_by_key(|&(p, _)| Reverse(p));
+    let mut slack = magnitude * total;
+    tiers
+        .into_iter()
+        .map(|(p, flex)| {
+            let ratio = if flex == N::from(0) || slack >= flex {
+                N::from(1)
+            } else {
+                slack / flex
+            };
+            slack -= ratio * flex;
+            (p, signed(ratio))
+        })
+        .collect()
+}
+
+/// Returns a minimal adjustment ratio threshold at which the paragraph can be laid out, as
+/// determined by a greedy scan that places as much material as possible on each line. This is
+/// useful for suggesting a tolerance when a layout fails at the current threshold. Because the
+/// greedy breaks are feasible at the returned threshold, the Knuth-Plass algorithm is guaranteed
+/// to succeed with it as well.
+///
+/// Returns `None` if no threshold suffices, e.g. because a box is wider than the line.
+pub fn min_feasible_threshold<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    line_width: N,
+) -> Option<N> {
+    let mut threshold: Option<N> = None;
+    let mut commit = |ratio: N| {
+        threshold = match threshold {
+            Some(t) if t >= ratio => Some(t),
+            _ => Some(ratio),
+        };
+    };
+
+    let mut start = 0;
+    let mut last_fit: Option<(usize, N)> = None;
+    for (b, item) in items.iter().enumerate() {
+        let (_, _, _, is_legal) = item.is_legal_breakpoint((b != 0).then(|| &items[b - 1]));
+        if !is_legal {
+            continue;
+        }
+
+        let mut ratio = range_adjustment_ratio(items, start, b, line_width);
+        if ratio < N::from(-1) {
+            // Break at the last breakpoint that fit and start a new line there.
+            let (at, fit_ratio) = last_fit?;
+            commit(fit_ratio);
+            start = at + 1;
+            ratio = range_adjustment_ratio(items, start, b, line_width);
+            if ratio < N::from(-1) {
+                return None;
+            }
+        }
+        last_fit = Some((b, ratio));
+
+        if item.is_forced() {
+            commit(ratio);
+            start = b + 1;
+            last_fit = None;
+        }
+    }
+    if let Some((_, ratio)) = last_fit {
+        commit(ratio);
+    }
+    threshold
+}
+
+/// Converts a paragraph's items from one numeric type to another by mapping every width, stretch,
+/// shrink, and cost through `f`. Infinite values are mapped to the target type's infinities
+/// without consulting `f`, so forced and forbidden breaks are preserved even if `f` cannot
+/// represent them, e.g. `Fixed::from_num`.
+pub fn convert_items<Box: Clone, Glue: Clone, Penalty: Clone, A: Num, B: Num>(
+    items: &[Item<Box, Glue, Penalty, A>],
+    f: impl Fn(A) -> B,
+) -> Vec<Item<Box, Glue, Penalty, B>> {
+    let f = |x: A| {
+        if x == A::INFINITY {
+            B::INFINITY
+        } else if x == A::NEG_INFINITY {
+            B::NEG_INFINITY
+        } else {
+            f(x)
+        }
+    };
+    items
+        .iter()
+        .map(|item| match item {
+            Item::Box { width, data } => Item::Box {
+                width: f(*width),
+                data: data.clone(),
+            },
+            Item::Glue {
+                width,
+                stretch,
+                stretch_order,
+                shrink,
+                shrink_order,
+                priority,
+                discardable,
+                data,
+            } => Item::Glue {
+                width: f(*width),
+                stretch: f(*stretch),
+                stretch_order: *stretch_order,
+                shrink: f(*shrink),
+                shrink_order: *shrink_order,
+                priority: *priority,
+                discardable: *discardable,
+                data: data.clone(),
+            },
+            Item::Penalty {
+                width,
+                cost,
+                flags,
+                data,
+            } => Item::Penalty {
+                width: f(*width),
+                cost: f(*cost),
+                flags: *flags,
+                data: data.clone(),
+            },
+            Item::Kern { width } => Item::Kern { width: f(*width) },
+            Item::Tab { width } => Item::Tab { width: f(*width) },
+            Item::BreakGlue {
+                break_width,
+                stretch,
+                shrink,
+            } => Item::BreakGlue {
+                break_width: f(*break_width),
+                stretch: f(*stretch),
+                shrink: f(*shrink),
+            },
+        })
+        .collect()
+}
+
+/// Merges each run of adjacent penalty items into a single penalty, so that the run offers one
+/// breakpoint rather than several. The merged penalty has the lowest cost in the run (i.e. the
+/// most desirable break), the union of the run's flags, the sum of the run's widths, and the data
+/// of the lowest-cost penalty.
+///
+/// Returns the normalized items along with a map from the index of each original item to the
+/// index of the item that replaces it.
+#[allow(clippy::type_complexity)]
+pub fn normalize_penalties<Box: Clone, Glue: Clone, Penalty: Clone, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+) -> (Vec<Item<Box, Glue, Penalty, N>>, Vec<usize>) {
+    let mut normalized: Vec<Item<Box, Glue, Penalty, N>> = Vec::with_capacity(items.len());
+    let mut remap = Vec::with_capacity(items.len());
+    for (i, item) in items.iter().enumerate() {
+        let merged = match (normalized.last_mut(), item) {
+            (
+                Some(Item::Penalty {
+                    width,
+                    cost,
+                    flags,
+                    data,
+                }),
+                Item::Penalty {
+                    width: w,
+                    cost: c,
+                    flags: f,
+                    data: d,
+                },
+            ) if matches!(items[i - 1], Item::Penalty { .. }) => {
+                *width += *w;
+                *flags |= *f;
+                if *c < *cost {
+                    *cost = *c;
+                    *data = d.clone();
+                }
+                true
+            }
+            _ => false,
+        };
+        if !merged {
+            normalized.push(item.clone());
+        }
+        remap.push(normalized.len() - 1);
+    }
+    (normalized, remap)
+}
+
+/// Describes how a line of a new layout relates to the lines of an old layout, as reported by
+/// `diff_layouts`.
+#[derive(Debug, Clone, Copy)]
+pub enum LineChange<N: Num = f32> {
+    /// The line at this index of the new layout is identical to a line of the old layout.
+    Unchanged(usize),
+    /// The line at index `line` of the new layout breaks at the same item as the `old` line, but
+    /// either begins at a different item or has a different adjustment ratio.
+    Changed {
+        /// The index of the line in the new layout.
+        line: usize,
+        /// The line in the old layout.
+        old: Line<N>,
+        /// The line in the new layout.
+        new: Line<N>,
+    },
+    /// The line at this index of the new layout has no counterpart in the old layout.
+    Added(usize),
+    /// The line at this index of the old layout has no counterpart in the new layout.
+    Removed(usize),
+}
+
+/// Compares two layouts of the same paragraph and reports which lines changed, e.g. to repaint
+/// only those lines after a re-layout. Lines are matched by their break positions using a longest
+/// common subsequence, and matched lines are unchanged if they also begin at the same item and
+/// have the same adjustment ratio.
+///
+/// The changes are reported in order, with removed lines reported before the lines that replace
+/// them.
+pub fn diff_layouts<N: Num>(old: &[Line<N>], new: &[Line<N>]) -> Vec<LineChange<N>> {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..].
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i].break_at == new[j].break_at {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let start = |lines: &[Line<N>], i: usize| if i == 0 { 0 } else { lines[i - 1].break_at + 1 };
+    let mut changes = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i].break_at == new[j].break_at {
+            if start(old, i) == start(new, j) && old[i].adjustment_ratio == new[j].adjustment_ratio
+            {
+                changes.push(LineChange::Unchanged(j));
+            } else {
+                changes.push(LineChange::Changed {
+                    line: j,
+                    old: old[i],
+                    new: new[j],
+                });
+            }
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            changes.push(LineChange::Removed(i));
+            i += 1;
+        } else {
+            changes.push(LineChange::Added(j));
+            j += 1;
+        }
+    }
+    changes
+}
+
+/// Lays out a paragraph using both the first-fit and Knuth-Plass algorithms with their default
+/// parameters and returns the resulting `(first_fit, knuth_plass)` lines. This is the canonical
+/// entry point for tools that compare greedy and optimal layouts side by side.
+pub fn compare_layouts<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    line_width: N,
+) -> (Vec<Line<N>>, Vec<Line<N>>) {
+    (
+        FirstFit::new().layout_paragraph(items, line_width),
+        KnuthPlass::new().layout_paragraph(items, line_width),
+    )
+}
+
+/// A line in a C-compatible representation, e.g. for passing layouts across an FFI boundary. See
+/// `to_flat`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FlatLine {
+    /// The index of the item at which the line breaks.
+    pub break_at: u32,
+    /// The adjustment ratio of the line.
+    pub adjustment_ratio: f32,
+    /// The line's flags: `HYPHENATED`, `OVERFLOWED`, and the line's fitness class, which is
+    /// stored in the bits of `FITNESS_MASK`.
+    pub flags: u32,
+}
+
+impl FlatLine {
+    /// The line breaks at a flagged penalty other than a forced break, e.g. a hyphenation point.
+    pub const HYPHENATED: u32 = 1;
+    /// The line cannot be shrunk enough to fit within the line width.
+    pub const OVERFLOWED: u32 = 2;
+    /// The bits that hold the line's fitness class, from 0 for tight lines to 3 for very loose
+    /// lines.
+    pub const FITNESS_MASK: u32 = 3 << Self::FITNESS_SHIFT;
+    /// The position of the lowest bit of the fitness class.
+    pub const FITNESS_SHIFT: u32 = 2;
+
+    /// Returns the fitness class of the line.
+    pub fn fitness(&self) -> Fitness {
+        match (self.flags & Self::FITNESS_MASK) >> Self::FITNESS_SHIFT {
+            0 => Fitness::Zero,
+            1 => Fitness::One,
+            2 => Fitness::Two,
+            _ => Fitness::Three,
+        }
+    }
+}
+
+/// Converts laid-out lines to their C-compatible representation. Whether or not each line is
+/// hyphenated or overflows is determined from the items and the line width, as the lines alone do
+/// not record it. A line overflows if its natural width less its shrink exceeds `line_width`; this
+/// is detected even if the layout reported the line with a clamped adjustment ratio.
+pub fn to_flat<Box, Glue, Penalty>(
+    items: &[Item<Box, Glue, Penalty, f32>],
+    lines: &[Line<f32>],
+    line_width: f32,
+) -> Vec<FlatLine> {
+    let mut start = 0;
+    lines
+        .iter()
+        .map(|l| {
+            let at = &items[l.break_at];
+            let mut flags = (Fitness::of(l.adjustment_ratio) as u32) << FlatLine::FITNESS_SHIFT;
+            if !at.is_forced() && at.is_flagged() {
+                flags |= FlatLine::HYPHENATED;
+            }
+            if range_adjustment_ratio(items, start, l.break_at, line_width) < -1.0 {
+                flags |= FlatLine::OVERFLOWED;
+            }
+            start = l.break_at + 1;
+
+            FlatLine {
+                break_at: l.break_at as u32,
+                adjustment_ratio: l.adjustment_ratio,
+                flags,
+            }
+        })
+        .collect()
+}
+
+/// Computes a fingerprint of the given items, e.g. to key a cache of layouts. The fingerprint
+/// covers the kind of each item and its numeric fields, glue orders and priorities, and penalty
+/// flags, but not the data attached to items. Numeric fields are quantized to thousandths of a unit
+/// before they are hashed, so differences smaller than 0.0005 units, such as those introduced by
+/// floating-point error, usually do not change the fingerprint. Infinite values saturate.
+///
+/// The fingerprint is stable across platforms and releases of this crate with the same major
+/// version, but it is not cryptographically secure.
+pub fn items_fingerprint<Box, Glue, Penalty, N: Num>(items: &[Item<Box, Glue, Penalty, N>]) -> u64 {
+    // FNV-1a over the little-endian bytes of each word.
+    fn write(hash: &mut u64, word: u64) {
+        for byte in word.to_le_bytes() {
+            *hash = (*hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    let quantize = |x: N| (x * N::from(1000)).round_to_i64() as u64;
+
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for item in items {
+        let words: [u64; 8] = match *item {
+            Item::Box { width, .. } => [0, quantize(width), 0, 0, 0, 0, 0, 0],
+            Item::Glue {
+                width,
+                stretch,
+                stretch_order,
+                shrink,
+                shrink_order,
+                priority,
+                discardable,
+                ..
+            } => [
+                1,
+                quantize(width),
+                quantize(stretch),
+                stretch_order as u64,
+                quantize(shrink),
+                shrink_order as u64,
+                priority.into(),
+                discardable.into(),
+            ],
+            Item::Penalty {
+                width, cost, flags, ..
+            } => [
+                2,
+                quantize(width),
+                quantize(cost),
+                flags.0.into(),
+                0,
+                0,
+                0,
+                0,
+            ],
+            Item::Kern { width } => [3, quantize(width), 0, 0, 0, 0, 0, 0],
+            Item::Tab { width } => [4, quantize(width), 0, 0, 0, 0, 0, 0],
+            Item::BreakGlue {
+                break_width,
+                stretch,
+                shrink,
+            } => [
+                5,
+                quantize(break_width),
+                quantize(stretch),
+                quantize(shrink),
+                0,
+                0,
+                0,
+                0,
+            ],
+        };
+        for word in words {
+            write(&mut hash, word);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{monospace, split};
+    use crate::{Fixed, GlueOrder, PenaltyFlags, Proportional};
+    use alloc::vec;
+    use fixed::types::{I32F32, I8F8};
+
+    #[test]
+    fn validate_after_width_change() {
+        let mut items = monospace("The quick brown fox jumps over the lazy dog.");
+        let lines = KnuthPlass::new().layout_paragraph(&items, 20.0);
+        assert_eq!(validate_layout(&items, &lines, 20.0, 1.0), Ok(()));
+
+        // Widen "lazy" so that the second line no longer fits.
+        for item in &mut items[35..39] {
+            if let Item::Box { width, .. } = item {
+                *width = 2.0;
+            }
+        }
+        assert_eq!(validate_layout(&items, &lines, 20.0, 1.0), Err(1));
+    }
+
+    #[test]
+    fn min_feasible_threshold() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        assert!(KnuthPlass::new().layout_paragraph(&items, 11.0).is_empty());
+
+        let threshold = super::min_feasible_threshold(&items, 11.0).unwrap();
+        let lines = KnuthPlass::new()
+            .with_threshold(threshold)
+            .layout_paragraph(&items, 11.0);
+        assert!(!lines.is_empty());
+        assert_eq!(validate_layout(&items, &lines, 11.0, threshold), Ok(()));
+
+        assert_eq!(super::min_feasible_threshold(&items, 4.0), None);
+    }
+
+    #[test]
+    fn used_width() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let lines = KnuthPlass::new().layout_paragraph(&items, 20.0);
+        assert_eq!(super::used_width(&items, &lines, 20.0), 19.0);
+
+        let lines = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&items, 28.0);
+        assert_eq!(super::used_width(&items, &lines, 28.0), 25.0);
+    }
+
+    #[test]
+    fn break_columns() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let lines = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&items, 20.0);
+        let columns = super::break_columns(&items, &lines);
+        assert_eq!(columns, [19.0, 19.0, 4.0]);
+        for (line, columns) in split(text, &lines).iter().zip(columns) {
+            assert_eq!(line.chars().count() as f32, columns);
+        }
+    }
+
+    #[test]
+    fn line_starts() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let lines = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&items, 20.0);
+        let starts = super::line_starts(&lines);
+        assert_eq!(starts, [0, 20, 40]);
+        for (line, start) in split(text, &lines).iter().zip(starts) {
+            assert!(text[start..].starts_with(line));
+        }
+        assert!(super::line_starts::<f32>(&[]).is_empty());
+    }
+
+    #[test]
+    fn line_items() {
+        // "aa bb-cc dd", where "bbcc" may be hyphenated.
+        let mut items = monospace("aa bbcc dd");
+        items.insert(5, Item::flagged_penalty(1.0, 50.0, true, ()));
+        let lines = [
+            Line {
+                break_at: 2,
+                adjustment_ratio: 0.0,
+                truncated: false,
+            },
+            Line {
+                break_at: 5,
+                adjustment_ratio: 0.0,
+                truncated: false,
+            },
+            Line {
+                break_at: items.len() - 1,
+                adjustment_ratio: 0.0,
+                truncated: false,
+            },
+        ];
+        let line_items = super::line_items(&items, &lines);
+        assert_eq!(
+            line_items,
+            [
+                LineItems {
+                    rendered: 0..2,
+                    consumed: Some(2),
+                },
+                LineItems {
+                    rendered: 3..6,
+                    consumed: None,
+                },
+                LineItems {
+                    rendered: 6..items.len(),
+                    consumed: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn block_height() {
+        struct Glyph(f32, f32);
+
+        impl BoxMetrics<f32> for Glyph {
+            fn height(&self) -> f32 {
+                self.0
+            }
+
+            fn depth(&self) -> f32 {
+                self.1
+            }
+        }
+
+        // "Ab cd ef", where "A" is tall and "c" descends below the baseline.
+        let glyph = |height, depth| Item::Box {
+            width: 1.0,
+            data: Glyph(height, depth),
+        };
+        let space = || Item::glue(1.0, 1.0, 0.0, ());
+        let items: Vec<Item<Glyph>> = vec![
+            glyph(2.0, 0.0),
+            glyph(1.0, 0.0),
+            space(),
+            glyph(1.0, 0.5),
+            glyph(1.0, 0.0),
+            space(),
+            glyph(1.0, 0.0),
+            glyph(1.0, 0.0),
+            Item::glue(0.0, f32::INFINITY, 0.0, ()),
+            Item::forced_break(()),
+        ];
+        let lines = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&items, 2.0);
+        assert_eq!(
+            lines.iter().map(|l| l.break_at).collect::<Vec<_>>(),
+            [2, 5, 9]
+        );
+        assert_eq!(super::block_height(&items, &lines, 0.25), 5.0);
+        assert_eq!(super::block_height(&items, &lines[..1], 0.25), 2.0);
+        assert_eq!(super::block_height(&items, &[], 0.25), 0.0);
+    }
+
+    #[test]
+    fn line_extents() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let lines = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&items, 22.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+        let extents =
+            |alignment| super::line_extents(&items, &lines, 22.0, alignment, Direction::Ltr);
+        assert_eq!(
+            extents(Alignment::Justify),
+            [(0.0, 22.0), (0.0, 22.0), (0.0, 4.0)]
+        );
+        assert_eq!(
+            extents(Alignment::Left),
+            [(0.0, 19.0), (0.0, 19.0), (0.0, 4.0)]
+        );
+        assert_eq!(
+            extents(Alignment::Center),
+            [(1.5, 20.5), (1.5, 20.5), (9.0, 13.0)]
+        );
+        assert_eq!(
+            extents(Alignment::Right),
+            [(3.0, 22.0), (3.0, 22.0), (18.0, 22.0)]
+        );
+        assert_eq!(
+            super::line_extents(&items, &lines, 22.0, Alignment::Justify, Direction::Rtl),
+            [(0.0, 22.0), (0.0, 22.0), (18.0, 22.0)]
+        );
+    }
+
+    #[test]
+    fn line_glue_counts() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let lines = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+        assert_eq!(super::line_glue_counts(&items, &lines), [3, 3, 1]);
+    }
+
+    #[test]
+    fn line_badness() {
+        let lines = [0.0, 0.5, -1.0, f32::INFINITY].map(|adjustment_ratio| Line {
+            break_at: 0,
+            adjustment_ratio,
+            truncated: false,
+        });
+        assert_eq!(
+            super::line_badness(&lines),
+            [0.0, 12.5, 100.0, f32::INFINITY]
+        );
+    }
+
+    #[test]
+    fn line_ratios() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let lines = KnuthPlass::new().layout_paragraph(&items, 20.0);
+        let ratios = super::line_ratios(&lines);
+        assert_eq!(recompute_line_ratios(&items, &lines, 20.0), ratios);
+
+        // "The quick brown fox" has three spaces, so each unit of width adds 1/3 to its ratio.
+        let wider = recompute_line_ratios(&items, &lines, 21.0);
+        assert_eq!(ratios[0], 1.0 / 3.0);
+        assert_eq!(wider[0], 2.0 / 3.0);
+    }
+
+    #[test]
+    fn layout_positions() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let lines = KnuthPlass::new().layout_paragraph(&items, 20.0);
+
+        // "The quick brown fox" has one unit of slack shared by three spaces.
+        let positions = super::layout_positions(&items, &lines, 20.0, PositionOptions::default());
+        assert_eq!(positions[0].len(), 19);
+        assert_eq!(&positions[0][..4], [0.0, 1.0, 2.0, 3.0]);
+        assert!((positions[0][4] - (4.0 + 1.0 / 3.0)).abs() < 1e-5);
+        assert!((positions[0][18] - 19.0).abs() < 1e-5);
+
+        let opts = PositionOptions {
+            retain_trailing_glue: true,
+            ..Default::default()
+        };
+        let positions = super::layout_positions(&items, &lines, 20.0, opts);
+        assert_eq!(positions[0].len(), 20);
+        assert!((positions[0][19] - 20.0).abs() < 1e-5);
+
+        // Leading glue takes up no space once trimmed, and the line is respaced to compensate.
+        let text = "  The quick brown fox jumps over the lazy dog.";
+        let mut items = monospace(text);
+        items[0] = Item::glue(1.0, 1.0, 0.0, ());
+        let lines = KnuthPlass::new()
+            .with_trim_leading_glue(true)
+            .layout_paragraph(&items, 20.0);
+        let opts = PositionOptions {
+            trim_leading_glue: true,
+            ..Default::default()
+        };
+        let positions = super::layout_positions(&items, &lines, 20.0, opts);
+        assert_eq!(&positions[0][..3], [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn rtl_positions() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let lines = KnuthPlass::new().layout_paragraph(&items, 20.0);
+        let opts = PositionOptions {
+            hanging_indent: (2.0, 0.0),
+            ..Default::default()
+        };
+        let ltr = super::layout_positions(&items, &lines, 20.0, opts);
+        let rtl =
+            super::layout_positions(&items, &lines, 20.0, opts.with_direction(Direction::Rtl));
+
+        // Each item's right edge is as far from the right edge of the paragraph right-to-left as
+        // its left edge is from the left edge left-to-right, i.e. where the next item starts.
+        assert_eq!(rtl.len(), ltr.len());
+        for (ltr, rtl) in ltr.iter().zip(&rtl) {
+            assert_eq!(rtl.len(), ltr.len());
+            for (next, &x) in ltr[1..].iter().zip(rtl) {
+                assert!((20.0 - next - x).abs() < 1e-5);
+            }
+        }
+        assert_eq!(&rtl[0][..2], [17.0, 16.0]);
+    }
+
+    #[test]
+    fn glue_order_positions() {
+        // The first glue stretches infinitely but only shrinks by a finite amount, and the glue
+        // before the forced break stretches finitely.
+        let items = [
+            Item::Box {
+                width: 3.0,
+                data: (),
+            },
+            Item::Glue {
+                width: 2.0,
+                stretch: 1.0,
+                stretch_order: GlueOrder::Fil,
+                shrink: 1.0,
+                shrink_order: GlueOrder::Normal,
+                priority: 0,
+                discardable: true,
+                data: (),
+            },
+            Item::Box {
+                width: 3.0,
+                data: (),
+            },
+            Item::glue(0.0, 1.0, 0.0, ()),
+            Item::forced_break(()),
+        ];
+        let opts = PositionOptions::default();
+        for (line_width, expected) in [
+            // The infinitely stretchable glue absorbs all of the slack...
+            (12.0, [0.0, 3.0, 9.0, 12.0, 12.0]),
+            // ...but shrinks like finite glue.
+            (7.0, [0.0, 3.0, 4.0, 7.0, 7.0]),
+        ] {
+            let lines = KnuthPlass::new().layout_paragraph(&items, line_width);
+            assert_eq!(lines.len(), 1);
+            assert_eq!(
+                super::layout_positions(&items, &lines, line_width, opts),
+                [expected]
+            );
+            assert_eq!(
+                layout_positions_with(&items, &lines, line_width, opts, &Proportional),
+                [expected]
+            );
+            assert_eq!(
+                super::line_extents(
+                    &items,
+                    &lines,
+                    line_width,
+                    Alignment::Justify,
+                    Direction::Ltr
+                ),
+                [(0.0, expected[3])]
+            );
+        }
+    }
+
+    #[test]
+    fn glue_priority() {
+        // The space after the period has a higher priority than the space between words.
+        let mut items = monospace("a. b c");
+        items[2] = Item::Glue {
+            width: 1.0,
+            stretch: 1.0,
+            stretch_order: GlueOrder::Normal,
+            shrink: 0.0,
+            shrink_order: GlueOrder::Normal,
+            priority: 1,
+            discardable: true,
+            data: (),
+        };
+        let positions = |line_width: f32| {
+            let adjustment_ratio = (line_width - 6.0) / 2.0;
+            let lines = [Line {
+                break_at: 6,
+                adjustment_ratio,
+                truncated: false,
+            }];
+            super::layout_positions(&items, &lines, line_width, PositionOptions::default())
+        };
+
+        // The period's space absorbs all of the slack until it has stretched by its full stretch.
+        assert_eq!(positions(7.0), [[0.0, 1.0, 2.0, 4.0, 5.0, 6.0]]);
+        assert_eq!(positions(7.5), [[0.0, 1.0, 2.0, 4.0, 5.0, 6.5]]);
+        // Beyond that, both spaces stretch in proportion to their stretch.
+        assert_eq!(positions(10.0), [[0.0, 1.0, 2.0, 5.0, 6.0, 9.0]]);
+    }
+
+    #[test]
+    fn non_discardable_glue() {
+        let text = "aa bb cc";
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+
+        // The space at which the first line breaks is dropped, so the space between "aa" and "bb"
+        // stretches to fill the line.
+        let items = monospace(text);
+        let lines = knuth_plass.layout_paragraph(&items, 6.0);
+        assert_eq!(split(text, &lines), ["aa bb", "cc"]);
+        assert_eq!(super::break_columns(&items, &lines), [5.0, 2.0]);
+        let positions = super::layout_positions(&items, &lines, 6.0, PositionOptions::default());
+        assert_eq!(positions[0], [0.0, 1.0, 2.0, 4.0, 5.0]);
+
+        // Once the spaces are not discardable, the breaking space keeps its width at the end of
+        // the line, while the space within the line is set at its natural width.
+        let mut items = monospace(text);
+        for item in &mut items {
+            if let Item::Glue { discardable, .. } = item {
+                *discardable = false;
+            }
+        }
+        let lines = knuth_plass.layout_paragraph(&items, 6.0);
+        assert_eq!(split(text, &lines), ["aa bb", "cc"]);
+        assert_eq!(super::break_columns(&items, &lines), [6.0, 2.0]);
+        let positions = super::layout_positions(&items, &lines, 6.0, PositionOptions::default());
+        assert_eq!(positions[0], [0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn check_precision() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        assert_eq!(super::check_precision(&items, 20.0), Ok(()));
+
+        // I8F8 represents values below 128, so these paragraphs are 44, 88, and 132 wide.
+        let small =
+            |items: &[Item]| super::convert_items(items, Fixed::<I8F8>::saturating_from_num);
+        let items = small(&monospace("The quick brown fox jumps over the lazy dog."));
+        let line_width = Fixed::from_num(20);
+        assert_eq!(
+            super::check_precision(&items, line_width),
+            Err(PrecisionError::NearLimit {
+                total: Fixed::from_num(44)
+            })
+        );
+        let items = small(&monospace("The quick brown fox jumps."));
+        assert_eq!(super::check_precision(&items, line_width), Ok(()));
+
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(3);
+        let items = small(&monospace(&text));
+        assert_eq!(
+            super::check_precision(&items, line_width),
+            Err(PrecisionError::Saturated)
+        );
+    }
+
+    #[test]
+    fn tab_stops() {
+        // Two rows of a table whose columns are separated by tabs.
+        let cell = |width: f32| Item::Box { width, data: () };
+        let mut items = vec![cell(4.0), Item::Tab { width: 1.0 }, cell(3.0)];
+        items.push(Item::forced_break(()));
+        items.extend([cell(9.0), Item::Tab { width: 1.0 }, cell(2.0)]);
+        items.extend(monospace(""));
+
+        let lines = FirstFit::new()
+            .with_rigid_glue_fallback(true)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(lines.iter().map(|l| l.break_at).collect::<Vec<_>>(), [3, 8]);
+
+        // Without stops, each tab is set at its minimum width.
+        let positions = super::layout_positions(&items, &lines, 20.0, PositionOptions::default());
+        assert_eq!(positions[0], [0.0, 4.0, 5.0, 8.0]);
+
+        // With stops, the second column aligns to the first stop past each tab's minimum width.
+        let opts = PositionOptions {
+            tab_stops: &[8.0, 16.0],
+            ..Default::default()
+        };
+        let positions = super::layout_positions(&items, &lines, 20.0, opts);
+        assert_eq!(positions[0], [0.0, 4.0, 8.0, 11.0]);
+        assert_eq!(positions[1][..3], [0.0, 9.0, 16.0]);
+    }
+
+    #[test]
+    fn hanging_indent() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let expected = ["The quick brown fox", "jumps over the", "lazy dog."];
+
+        let first_fit = FirstFit::new()
+            .with_threshold(f32::INFINITY)
+            .with_hanging_indent(0.0, 4.0);
+        let lines = first_fit.layout_paragraph(&items, 20.0);
+        assert_eq!(split(text, &lines), expected);
+
+        let knuth_plass = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .with_hanging_indent(0.0, 4.0);
+        let lines = knuth_plass.layout_paragraph(&items, 20.0);
+        assert_eq!(split(text, &lines), expected);
+
+        // Every line after the first begins at the indent, and the second line is stretched to end
+        // at the right edge.
+        let opts = PositionOptions {
+            hanging_indent: (0.0, 4.0),
+            ..Default::default()
+        };
+        let positions = super::layout_positions(&items, &lines, 20.0, opts);
+        assert_eq!(
+            positions.iter().map(|p| p[0]).collect::<Vec<_>>(),
+            [0.0, 4.0, 4.0]
+        );
+        assert!((positions[1].last().unwrap() + 1.0 - 20.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn diff_layouts() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let old = knuth_plass.layout_paragraph(&items, 20.0);
+        let new = knuth_plass.layout_paragraph(&items, 25.0);
+        assert_eq!(
+            split(text, &old),
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+        assert_eq!(
+            split(text, &new),
+            ["The quick brown fox jumps", "over the lazy dog."]
+        );
+
+        // The first two lines are replaced by one, and the last line is extended.
+        let changes = super::diff_layouts(&old, &new);
+        assert_eq!(changes.len(), 4);
+        assert!(matches!(changes[0], LineChange::Removed(0)));
+        assert!(matches!(changes[1], LineChange::Removed(1)));
+        assert!(matches!(changes[2], LineChange::Added(0)));
+        assert!(matches!(
+            changes[3],
+            LineChange::Changed { line: 1, old, new } if old.break_at == 45 && new.break_at == 45
+        ));
+
+        let changes = super::diff_layouts(&old, &old);
+        assert!(changes
+            .iter()
+            .enumerate()
+            .all(|(i, c)| matches!(c, LineChange::Unchanged(j) if *j == i)));
+    }
+
+    #[test]
+    fn compare_layouts() {
+        // The interword glue is flexible enough to satisfy the default first-fit threshold.
+        let text = "aaaaaa bb c dd e ff ggg";
+        let mut items = monospace(text);
+        let n = items.len();
+        for item in &mut items[..n - 2] {
+            if let Item::Glue {
+                stretch, shrink, ..
+            } = item
+            {
+                (*stretch, *shrink) = (3.0, 1.0);
+            }
+        }
+
+        let (first_fit, knuth_plass) = super::compare_layouts(&items, 6.0);
+        assert_eq!(split(text, &first_fit), ["aaaaaa", "bb c dd e", "ff ggg"]);
+        assert_eq!(
+            split(text, &knuth_plass),
+            ["aaaaaa", "bb c", "dd e ff", "ggg"]
+        );
+
+        // Both layouts set the first line alike. Knuth-Plass splits the greedily shrunk second
+        // line in two, and the last line ends at the same break with a different ratio.
+        let changes = super::diff_layouts(&first_fit, &knuth_plass);
+        assert_eq!(changes.len(), 5);
+        assert!(matches!(changes[0], LineChange::Unchanged(0)));
+        assert!(matches!(changes[1], LineChange::Removed(1)));
+        assert!(matches!(changes[2], LineChange::Added(1)));
+        assert!(matches!(changes[3], LineChange::Added(2)));
+        assert!(matches!(
+            changes[4],
+            LineChange::Changed { line: 3, old, new }
+                if old.break_at == 24 && old.adjustment_ratio == -0.5 && new.break_at == 24
+        ));
+    }
+
+    #[test]
+    fn convert_items() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let fixed = super::convert_items(&items, Fixed::<I32F32>::from_num);
+        assert!(matches!(
+            fixed.last(),
+            Some(Item::Penalty { cost, .. }) if *cost == Fixed::NEG_INFINITY
+        ));
+
+        let expected = KnuthPlass::new().layout_paragraph(&items, 20.0);
+        let lines = KnuthPlass::new().layout_paragraph(&fixed, Fixed::from_num(20));
+        assert_eq!(
+            lines.iter().map(|l| l.break_at).collect::<Vec<_>>(),
+            expected.iter().map(|l| l.break_at).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn normalize_penalties() {
+        let items: Vec<Item<(), (), u32>> = vec![
+            Item::Box {
+                width: 1.0,
+                data: (),
+            },
+            Item::Penalty {
+                width: 1.0,
+                cost: 50.0,
+                flags: PenaltyFlags::AVOID_CONSECUTIVE,
+                data: 0,
+            },
+            Item::Penalty {
+                width: 0.0,
+                cost: 10.0,
+                flags: PenaltyFlags::NONE,
+                data: 1,
+            },
+            Item::Box {
+                width: 1.0,
+                data: (),
+            },
+            Item::Penalty {
+                width: 0.0,
+                cost: 0.0,
+                flags: PenaltyFlags::NONE,
+                data: 2,
+            },
+        ];
+
+        let (normalized, remap) = super::normalize_penalties(&items);
+        assert_eq!(normalized.len(), 4);
+        assert_eq!(remap, [0, 1, 1, 2, 3]);
+        assert!(matches!(
+            normalized[1],
+            Item::Penalty {
+                width: 1.0,
+                cost: 10.0,
+                flags: PenaltyFlags::AVOID_CONSECUTIVE,
+                data: 1,
+            }
+        ));
+        assert!(matches!(normalized[3], Item::Penalty { data: 2, .. }));
+    }
+
+    #[test]
+    fn to_flat() {
+        // "hyphen-ated extraordinarily", where "extraordinarily" cannot fit on a line.
+        let items: Vec<Item> = vec![
+            Item::Box {
+                width: 6.0,
+                data: (),
+            },
+            Item::flagged_penalty(1.0, 50.0, true, ()),
+            Item::Box {
+                width: 3.0,
+                data: (),
+            },
+            Item::glue(1.0, 1.0, 0.0, ()),
+            Item::Box {
+                width: 15.0,
+                data: (),
+            },
+            Item::glue(0.0, f32::INFINITY, 0.0, ()),
+            Item::forced_break(()),
+        ];
+        let lines = FirstFit::new()
+            .with_threshold(f32::INFINITY)
+            .allow_overflow(true)
+            .layout_paragraph(&items, 8.0);
+        let flat = super::to_flat(&items, &lines, 8.0);
+        assert_eq!(
+            flat.iter().map(|l| l.break_at).collect::<Vec<_>>(),
+            [1, 3, 5, 6]
+        );
+        let flags = |l: &FlatLine| l.flags & !FlatLine::FITNESS_MASK;
+        assert_eq!(flags(&flat[0]), FlatLine::HYPHENATED);
+        assert_eq!(flags(&flat[1]), 0);
+        assert_eq!(flags(&flat[2]), FlatLine::OVERFLOWED);
+        assert_eq!(flat[0].fitness(), Fitness::Three);
+        assert_eq!(flat[2].fitness(), Fitness::One);
+    }
+
+    #[test]
+    fn overflow_amount() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        assert_eq!(super::overflow_amount(&items, 4.0), 1.0);
+        assert_eq!(super::overflow_amount(&items, 5.0), 0.0);
+
+        // A word without any breakpoints after it is measured to the end of the paragraph.
+        assert_eq!(super::overflow_amount(&items[..3], 1.0), 2.0);
+    }
+
+    #[test]
+    fn items_fingerprint() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let fingerprint = super::items_fingerprint(&items);
+        assert_eq!(super::items_fingerprint(&items.clone()), fingerprint);
+
+        // Differences below the quantization granularity do not change the fingerprint.
+        let mut noisy = items.clone();
+        if let Item::Box { width, .. } = &mut noisy[0] {
+            *width += 1e-5;
+        }
+        assert_eq!(super::items_fingerprint(&noisy), fingerprint);
+
+        let mut wider = items.clone();
+        if let Item::Box { width, .. } = &mut wider[0] {
+            *width += 0.01;
+        }
+        assert_ne!(super::items_fingerprint(&wider), fingerprint);
+
+        let mut rigid = items.clone();
+        if let Item::Glue { discardable, .. } = &mut rigid[3] {
+            *discardable = false;
+        }
+        assert_ne!(super::items_fingerprint(&rigid), fingerprint);
+        assert_ne!(super::items_fingerprint(&items[1..]), fingerprint);
+
+        // Glue of one fil is not glue of one unit, whether it stretches or shrinks.
+        let mut fil = items.clone();
+        if let Item::Glue { stretch_order, .. } = &mut fil[3] {
+            *stretch_order = GlueOrder::Fil;
+        }
+        assert_ne!(super::items_fingerprint(&fil), fingerprint);
+        let mut fil = items.clone();
+        if let Item::Glue { shrink_order, .. } = &mut fil[3] {
+            *shrink_order = GlueOrder::Fil;
+        }
+        assert_ne!(super::items_fingerprint(&fil), fingerprint);
+    }
+}
@@ -0,0 +1,108 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use crate::math::Num;
+use crate::{range_adjustment_ratio, sum_metrics, Item, Line, ParagraphLayout};
+
+/// The number of bisection steps used to search for the width at which to re-break the tail.
+pub(crate) const BISECTION_STEPS: usize = 16;
+
+/// Wraps another paragraph layout algorithm and re-breaks the last two lines of its output so
+/// that they are of roughly equal length. This avoids lopsided endings where a full line is
+/// followed by a nearly-empty one.
+///
+/// The tail of the paragraph is re-laid-out by the wrapped algorithm at the narrowest width that
+/// still fits it onto two lines. The adjustment ratios of the re-broken lines are then recomputed
+/// relative to the paragraph's line width.
+pub struct BalanceTail<A> {
+    inner: A,
+}
+
+impl<A> BalanceTail<A> {
+    /// Creates a new BalanceTail layout that post-processes the output of the given layout.
+    pub fn new(inner: A) -> Self {
+        BalanceTail { inner }
+    }
+}
+
+impl<Box, Glue, Penalty, N: Num, A: ParagraphLayout<Box, Glue, Penalty, N>>
+    ParagraphLayout<Box, Glue, Penalty, N> for BalanceTail<A>
+{
+    fn layout_paragraph(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+    ) -> Vec<Line<N>> {
+        let mut lines = self.inner.layout_paragraph(items, line_width);
+        let n = lines.len();
+        if n < 2 {
+            return lines;
+        }
+
+        // The tail begins immediately after the break that ends the third-to-last line.
+        let start = if n == 2 { 0 } else { lines[n - 3].break_at + 1 };
+        let end = lines[n - 1].break_at;
+        let tail = &items[start..=end];
+
+        // Search for the narrowest width at which the tail still fits onto two lines. The tail
+        // cannot fit onto two lines that are narrower than half of its natural width.
+        let (natural_width, _, _) = sum_metrics(tail, 0..tail.len());
+        let (mut lo, mut hi) = (natural_width / N::from(2), line_width);
+        let mut best = None;
+        for _ in 0..BISECTION_STEPS {
+            let mid = lo + (hi - lo) / N::from(2);
+            let tail_lines = self.inner.layout_paragraph(tail, mid);
+            match tail_lines.len() {
+                1 => hi = mid,
+                2 => {
+                    hi = mid;
+                    best = Some(tail_lines);
+                }
+                _ => lo = mid,
+            };
+        }
+
+        // Splice the re-broken tail back into the paragraph.
+        if let Some(tail_lines) = best {
+            let break_at = start + tail_lines[0].break_at;
+            lines[n - 2] = Line {
+                break_at,
+                adjustment_ratio: range_adjustment_ratio(items, start, break_at, line_width),
+                truncated: false,
+            };
+            lines[n - 1] = Line {
+                break_at: end,
+                adjustment_ratio: range_adjustment_ratio(items, break_at + 1, end, line_width),
+                truncated: false,
+            };
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{monospace, split};
+    use crate::KnuthPlass;
+
+    #[test]
+    fn balances_last_two_lines() {
+        let text = "The quick brown fox jumps over the lazy dog and naps.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+
+        let lopsided = split(text, &knuth_plass.layout_paragraph(&items, 40.0));
+        assert_eq!(
+            lopsided,
+            ["The quick brown fox jumps over the lazy", "dog and naps."]
+        );
+
+        let balanced = BalanceTail::new(knuth_plass);
+        let lines = split(text, &balanced.layout_paragraph(&items, 40.0));
+        assert_eq!(
+            lines,
+            ["The quick brown fox jumps", "over the lazy dog and naps."]
+        );
+    }
+}
@@ -2,12 +2,27 @@ extern crate alloc;
 use alloc::vec::Vec;
 
 use crate::math::Num;
-use crate::{Item, Line, ParagraphLayout};
+use crate::region::{line_height, split_lines, vertical_metrics};
+use crate::{
+    diagnose, exceeds_max_glue_stretch, first_content, follows_visible_box, is_trailing_empty_line,
+    leading_glue, rigid_glue_fallback, sum_metrics, BoxMetrics, Breakpoint, GlueSum, Item,
+    ItemSource, JustifiedLastLine, LayoutError, Line, ParagraphLayout, PenaltyFlags,
+    PreparedParagraph, Region,
+};
 
 /// Runs the first-fit line-breaking algorithm to calculate the break points for a paragraph.
 pub struct FirstFit<N> {
     threshold: N,
+    feasibility_epsilon: N,
     allow_overflow: bool,
+    trim_leading_glue: bool,
+    rigid_glue_fallback: bool,
+    hanging_indent: (N, N),
+    max_glue_stretch: Option<N>,
+    trailing_empty_line: bool,
+    glue_breaks: bool,
+    ignore_zero_width_boxes: bool,
+    justify_last_line: bool,
 }
 
 impl<N: Num> FirstFit<N> {
@@ -15,7 +30,16 @@ impl<N: Num> FirstFit<N> {
     pub fn new() -> Self {
         FirstFit {
             threshold: N::from(1),
+            feasibility_epsilon: N::feasibility_epsilon(),
             allow_overflow: false,
+            trim_leading_glue: false,
+            rigid_glue_fallback: false,
+            hanging_indent: (N::from(0), N::from(0)),
+            max_glue_stretch: None,
+            trailing_empty_line: true,
+            glue_breaks: true,
+            ignore_zero_width_boxes: false,
+            justify_last_line: false,
         }
     }
 
@@ -26,12 +50,306 @@ impl<N: Num> FirstFit<N> {
         self
     }
 
+    /// Sets the tolerance for comparisons of adjustment ratios against the bounds of feasibility,
+    /// so that a line whose adjustment ratio lies within `epsilon` below -1 or above the threshold,
+    /// e.g. due to rounding error in running totals, is still feasible. Defaults to
+    /// `N::feasibility_epsilon()`, which is 1e-5 for `f32` and 0 for fixed-point numbers.
+    pub fn with_feasibility_epsilon(mut self, epsilon: N) -> Self {
+        self.feasibility_epsilon = epsilon;
+        self
+    }
+
     /// Configures the layout to allow lines that exceed the maximum line with if the layout would
     /// fail otherwise.
     pub fn allow_overflow(mut self, allow_overflow: bool) -> Self {
         self.allow_overflow = allow_overflow;
         self
     }
+
+    /// Configures the layout to ignore the width, stretch, and shrink of glue items that precede
+    /// the first box on each line, including the glue at which the previous line was broken. This
+    /// matches TeX's behavior of discarding glue at the start of a line. Defaults to false.
+    pub fn with_trim_leading_glue(mut self, trim_leading_glue: bool) -> Self {
+        self.trim_leading_glue = trim_leading_glue;
+        self
+    }
+
+    /// Configures the layout to accept lines that are shorter than the line width but contain no
+    /// stretchable glue, e.g. justified monospace text with fixed-width spaces. Such lines would
+    /// otherwise have an infinite adjustment ratio. Instead, they are given an adjustment ratio of
+    /// 0 and the leftover space is left at the end of the line. Defaults to false.
+    pub fn with_rigid_glue_fallback(mut self, rigid_glue_fallback: bool) -> Self {
+        self.rigid_glue_fallback = rigid_glue_fallback;
+        self
+    }
+
+    /// Sets a hanging indent. The first line is indented by `first` and every other line by `rest`,
+    /// and each indent is subtracted from the width that is available to its line, including
+    /// widths given by a region. Defaults to no indent.
+    pub fn with_hanging_indent(mut self, first: N, rest: N) -> Self {
+        self.hanging_indent = (first, rest);
+        self
+    }
+
+    /// Limits how far any single glue item may be stretched. A line is treated as too loose if
+    /// setting it would stretch any glue beyond `factor` times its natural width, just as if its
+    /// adjustment ratio exceeded the threshold. Glue with no natural width is not limited. By
+    /// default, glue stretch is only limited by the threshold.
+    pub fn with_max_glue_stretch(mut self, factor: N) -> Self {
+        self.max_glue_stretch = Some(factor);
+        self
+    }
+
+    /// Configures whether a paragraph that ends with a forced break, e.g. an explicit newline,
+    /// produces a trailing empty line. If so, the line that follows the forced break and contains
+    /// only the paragraph's finishing items is emitted as an empty `Line`; otherwise, the
+    /// paragraph's last line ends at the forced break. Defaults to true.
+    pub fn with_trailing_empty_line(mut self, trailing_empty_line: bool) -> Self {
+        self.trailing_empty_line = trailing_empty_line;
+        self
+    }
+
+    /// Configures whether glue that follows a box is a legal breakpoint. If glue breaks are
+    /// disabled, lines only break at penalties, e.g. to preserve the indentation and alignment of
+    /// preformatted text; glue still contributes its width, stretch, and shrink to each line.
+    /// Defaults to true.
+    pub fn with_glue_breaks(mut self, glue_breaks: bool) -> Self {
+        self.glue_breaks = glue_breaks;
+        self
+    }
+
+    /// Configures whether zero-width boxes, e.g. invisible anchors or markers, are ignored when
+    /// deciding whether glue follows a box. If so, glue is only a legal breakpoint if the nearest
+    /// item before it that is not a zero-width box is a box or a tab, so a marker between two glue
+    /// items does not make the second a breakpoint. Defaults to false.
+    pub fn with_ignore_zero_width_boxes(mut self, ignore_zero_width_boxes: bool) -> Self {
+        self.ignore_zero_width_boxes = ignore_zero_width_boxes;
+        self
+    }
+
+    /// Configures the layout to justify the last line of the paragraph like any other line by
+    /// ignoring the stretch of the paragraph's finishing glue, i.e. the glue that precedes its
+    /// final forced break. By default, the finishing glue leaves the last line ragged.
+    pub fn with_justify_last_line(mut self, justify_last_line: bool) -> Self {
+        self.justify_last_line = justify_last_line;
+        self
+    }
+
+    /// Lays out a paragraph into a region whose width varies with vertical position. The width of
+    /// each line is queried from the region using the total height of the lines that precede it,
+    /// where the height of a line is determined by the vertical metrics of its boxes.
+    pub fn layout_in_region<Box: BoxMetrics<N>, Glue, Penalty>(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        region: &impl Region<N>,
+    ) -> Vec<Line<N>> {
+        let mut l = self.layout(region.width_for(N::from(0)));
+        l.region = Some(region);
+        l.vertical_metrics = vertical_metrics(items);
+
+        let mut lines = Vec::new();
+        if !l.layout_paragraph(items, &mut |line| lines.push(line)) {
+            return Vec::new();
+        }
+        lines
+    }
+
+    /// Lays out a paragraph into a region like `layout_in_region`, but keeps at most `max_lines`
+    /// lines, e.g. because the region runs out of vertical space. Returns the lines that fit and
+    /// the index of the item at which the first remaining line begins, or `None` if every line
+    /// fits. The remainder can be laid out in the next region by passing the items from that index
+    /// on. Returns `None` if the paragraph cannot be laid out.
+    pub fn layout_until<Box: BoxMetrics<N>, Glue, Penalty>(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        region: &impl Region<N>,
+        max_lines: usize,
+    ) -> Option<(Vec<Line<N>>, Option<usize>)> {
+        split_lines(self.layout_in_region(items, region), items.len(), max_lines)
+    }
+
+    /// Lays out a paragraph with the given line width and passes each line to `on_line` as soon as
+    /// it is committed, rather than collecting the lines into a `Vec`. This allows lines to be
+    /// rendered as they are decided.
+    ///
+    /// Returns false if the paragraph could not be laid out. Because lines are committed greedily,
+    /// `on_line` may already have been called for some of the paragraph's lines in this case.
+    pub fn layout_with<Box, Glue, Penalty, F: FnMut(Line<N>)>(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+        mut on_line: F,
+    ) -> bool {
+        self.layout(line_width)
+            .layout_paragraph(items, &mut on_line)
+    }
+
+    /// Lays out a paragraph whose items are produced by the given source with the given line
+    /// width. This allows a paragraph to be laid out without materializing its items as a slice.
+    pub fn layout_source<S: ItemSource<N> + ?Sized>(
+        &self,
+        items: &S,
+        line_width: N,
+    ) -> Vec<Line<N>> {
+        let mut lines = Vec::new();
+        if !self
+            .layout(line_width)
+            .layout_paragraph(items, &mut |line| lines.push(line))
+        {
+            return Vec::new();
+        }
+        lines
+    }
+
+    /// Lays out a paragraph again after its line width has shrunk from `prev_width` to `new_width`,
+    /// e.g. while a window is being resized, given the lines that this layout previously produced
+    /// for the same items at `prev_width`. The lines are kept for as long as they still fit at the
+    /// new width, with their adjustment ratios updated, and the rest of the paragraph is laid out
+    /// afresh from the first line that no longer fits.
+    ///
+    /// This relies on first-fit being monotonic as the line width shrinks: a line that ended at
+    /// `prev_width` because the next breakpoint would have overflowed the line still ends at the
+    /// same breakpoint at any narrower width, as long as it fits. This holds as long as no item has
+    /// a negative width and no line ended because the next breakpoint would have been too loose.
+    /// If `new_width` is wider than `prev_width`, the paragraph is laid out from scratch.
+    pub fn relayout_narrower<Box, Glue, Penalty>(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        prev_width: N,
+        prev_lines: &[Line<N>],
+        new_width: N,
+    ) -> Vec<Line<N>> {
+        if new_width > prev_width {
+            return self.layout_source(items, new_width);
+        }
+
+        let mut lines = Vec::new();
+        if !self
+            .layout(new_width)
+            .relayout(items, prev_lines, &mut |line| lines.push(line))
+        {
+            return Vec::new();
+        }
+        lines
+    }
+
+    /// Returns the range of line widths around `width` for which this layout breaks the paragraph
+    /// at the same items as it does at `width`, e.g. so that a resize handler only lays the
+    /// paragraph out again once the width leaves the range. The breaks are the same for any width
+    /// that is at least the first bound and less than the second.
+    ///
+    /// The range is derived from the slack of each line: the width may shrink until some line can
+    /// no longer shrink to fit, and grow until the breakpoint that follows some line's break fits
+    /// on that line or some breakpoint on a line becomes too loose for the threshold. Lines that
+    /// end at a forced break do not limit how far the width may grow. The maximum glue stretch is
+    /// not taken into account. If the paragraph cannot be laid out at `width`, the range is empty.
+    pub fn stable_width_range<Box, Glue, Penalty>(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        width: N,
+    ) -> (N, N) {
+        let lines = self.layout_source(items, width);
+        if lines.is_empty() {
+            return (width, width);
+        }
+
+        let first_content = first_content(items);
+        let is_breakpoint = |b: usize| {
+            items[b]
+                .is_legal_breakpoint(b.checked_sub(1).map(|p| &items[p]))
+                .3
+                && (self.glue_breaks || !matches!(items[b], Item::Glue { .. }))
+                && (b >= first_content || b + 1 == items.len())
+        };
+        let tolerance = N::from(1) + self.feasibility_epsilon;
+        let (mut min, mut max) = (N::NEG_INFINITY, N::INFINITY);
+        let mut start = 0;
+        for (l, line) in lines.iter().enumerate() {
+            let indent = if l == 0 {
+                self.hanging_indent.0
+            } else {
+                self.hanging_indent.1
+            };
+            // Measures the line from `start` to a break at b, as `adjustment_ratio` does. Like
+            // `begin_line`, this counts the previous line's break towards the line.
+            let measure = |b: usize| {
+                let (mut w, mut y, mut z) = sum_metrics(items, start..b);
+                if self.trim_leading_glue {
+                    let (lw, ly, lz) = leading_glue(items, start..b);
+                    (w, y, z) = (w - lw, y - ly, z - lz);
+                }
+                let (bw, by, bz) = items[b].break_metrics();
+                y += by;
+                z += bz;
+                (indent + w + bw, y.value(), z.value())
+            };
+
+            let (w, _, z) = measure(line.break_at);
+            if w - z * tolerance > min {
+                min = w - z * tolerance;
+            }
+
+            // Every breakpoint up to the line's break must stay tight enough for the threshold.
+            if self.threshold != N::INFINITY {
+                let first = if l == 0 { 0 } else { start + 1 };
+                for b in (first..=line.break_at).filter(|&b| is_breakpoint(b)) {
+                    let (w, y, _) = measure(b);
+                    if self.rigid_glue_fallback && y == N::from(0) {
+                        continue;
+                    }
+                    let stretched = w + y * (self.threshold + self.feasibility_epsilon);
+                    if stretched < max {
+                        max = stretched;
+                    }
+                }
+            }
+
+            // The line grows to the next breakpoint once that breakpoint can be shrunk to fit.
+            if !items[line.break_at].is_forced() {
+                if let Some(next) = (line.break_at + 1..items.len()).find(|&b| is_breakpoint(b)) {
+                    let (w, _, z) = measure(next);
+                    if w - z * tolerance < max {
+                        max = w - z * tolerance;
+                    }
+                }
+            }
+            start = line.break_at;
+        }
+        (min, max)
+    }
+
+    /// Creates a reusable layout with this configuration. The layout keeps its buffers between
+    /// runs, so laying out many paragraphs with it avoids reallocating them for each paragraph.
+    pub fn reusable_layout(&self) -> FirstFitLayout<'static, N> {
+        self.layout(N::from(0))
+    }
+
+    fn layout<'a>(&self, line_width: N) -> FirstFitLayout<'a, N> {
+        FirstFitLayout {
+            line_width,
+            region: None,
+            vertical_metrics: Vec::new(),
+            breakpoints: None,
+            threshold: self.threshold,
+            feasibility_epsilon: self.feasibility_epsilon,
+            allow_overflow: self.allow_overflow,
+            trim_leading_glue: self.trim_leading_glue,
+            rigid_glue_fallback: self.rigid_glue_fallback,
+            hanging_indent: self.hanging_indent,
+            max_glue_stretch: self.max_glue_stretch,
+            trailing_empty_line: self.trailing_empty_line,
+            glue_breaks: self.glue_breaks,
+            ignore_zero_width_boxes: self.ignore_zero_width_boxes,
+            justify_last_line: self.justify_last_line,
+            indent: self.hanging_indent.0,
+            y: N::from(0),
+            line_start: 0,
+            width: N::from(0),
+            stretch: GlueSum::default(),
+            shrink: GlueSum::default(),
+            lines: Vec::new(),
+        }
+    }
 }
 
 impl<N: Num> Default for FirstFit<N> {
@@ -46,94 +364,409 @@ impl<Box, Glue, Penalty, N: Num> ParagraphLayout<Box, Glue, Penalty, N> for Firs
         items: &[Item<Box, Glue, Penalty, N>],
         line_width: N,
     ) -> Vec<Line<N>> {
-        let l = FirstFitLayout {
-            line_width,
-            threshold: self.threshold,
-            allow_overflow: self.allow_overflow,
-            width: N::from(0),
-            stretch: N::from(0),
-            shrink: N::from(0),
-            lines: Vec::new(),
-        };
-        l.layout_paragraph(items)
+        self.layout_source(items, line_width)
+    }
+
+    fn layout_prepared(
+        &self,
+        prepared: &PreparedParagraph<Box, Glue, Penalty, N>,
+        line_width: N,
+    ) -> Vec<Line<N>> {
+        let mut l = self.layout(line_width);
+        l.breakpoints = Some(prepared.breakpoints());
+
+        let mut lines = Vec::new();
+        if !l.layout_paragraph(prepared.items(), &mut |line| lines.push(line)) {
+            return Vec::new();
+        }
+        lines
+    }
+
+    fn try_layout_paragraph(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+    ) -> Result<Vec<Line<N>>, LayoutError<N>> {
+        let lines = self.layout_paragraph(items, line_width);
+        if lines.is_empty() && !items.is_empty() {
+            let (first, rest) = self.hanging_indent;
+            return Err(diagnose(items, (line_width - first, line_width - rest)));
+        }
+        Ok(lines)
+    }
+
+    fn line_counts_over_widths(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        widths: &[N],
+    ) -> Vec<usize> {
+        // Compute the breakpoints once and count the lines at each width without collecting them.
+        let prepared = PreparedParagraph::new(items);
+        widths
+            .iter()
+            .map(|&line_width| {
+                let mut l = self.layout(line_width);
+                l.breakpoints = Some(prepared.breakpoints());
+
+                let mut count = 0;
+                if !l.layout_paragraph(items, &mut |_| count += 1) {
+                    return 0;
+                }
+                count
+            })
+            .collect()
     }
 }
 
+/// Returns true if the item is a penalty with the `LAST_RESORT` flag.
+fn is_last_resort<Box, Glue, Penalty, N: Num>(item: &Item<Box, Glue, Penalty, N>) -> bool {
+    item.penalty_flags().contains(PenaltyFlags::LAST_RESORT)
+}
+
 struct Break<N> {
     width: N,
-    stretch: N,
-    shrink: N,
+    stretch: GlueSum<N>,
+    shrink: GlueSum<N>,
     adjustment_ratio: N,
     is_mandatory: bool,
     at: usize,
 }
 
-struct FirstFitLayout<N: Num> {
+/// The state of a single first-fit layout. A layout that is created by
+/// [`FirstFit::reusable_layout`] can lay out any number of paragraphs using
+/// [`FirstFitLayout::run`].
+pub struct FirstFitLayout<'a, N: Num> {
     line_width: N,
+    region: Option<&'a dyn Region<N>>,
+    vertical_metrics: Vec<(N, N)>,
+    breakpoints: Option<&'a [Breakpoint<N>]>,
 
     threshold: N,
+    feasibility_epsilon: N,
     allow_overflow: bool,
+    trim_leading_glue: bool,
+    rigid_glue_fallback: bool,
+    hanging_indent: (N, N),
+    max_glue_stretch: Option<N>,
+    trailing_empty_line: bool,
+    glue_breaks: bool,
+    ignore_zero_width_boxes: bool,
+    justify_last_line: bool,
+
+    indent: N,
+    y: N,
+    line_start: usize,
 
     width: N,
-    stretch: N,
-    shrink: N,
+    stretch: GlueSum<N>,
+    shrink: GlueSum<N>,
 
     lines: Vec<Line<N>>,
 }
 
-impl<N: Num> FirstFitLayout<N> {
-    fn break_at(&mut self, b: Break<N>) {
-        self.lines.push(Line {
+impl<N: Num> FirstFitLayout<'_, N> {
+    /// Lays out a paragraph with the given line width, reusing the buffers of previous runs.
+    /// Returns the lines of the paragraph, or an empty slice if the paragraph could not be laid
+    /// out. The lines are valid until the next run.
+    pub fn run<Box, Glue, Penalty>(
+        &mut self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+    ) -> &[Line<N>] {
+        self.reset(line_width);
+
+        let mut lines = core::mem::take(&mut self.lines);
+        lines.clear();
+        if !self.layout_paragraph(items, &mut |line| lines.push(line)) {
+            lines.clear();
+        }
+        self.lines = lines;
+        &self.lines
+    }
+
+    /// Resets the state of the layout in preparation for a new paragraph.
+    fn reset(&mut self, line_width: N) {
+        self.line_width = line_width;
+        self.indent = self.hanging_indent.0;
+        self.y = N::from(0);
+        self.line_start = 0;
+        self.width = N::from(0);
+        self.stretch = GlueSum::default();
+        self.shrink = GlueSum::default();
+    }
+
+    /// Calculates the adjustment ratio for a line that ends at the given item.
+    fn adjustment_ratio<Box, Glue, Penalty>(&self, item: &Item<Box, Glue, Penalty, N>) -> N {
+        self.adjustment_ratio_with(item, self.width, self.stretch, self.shrink)
+    }
+
+    /// Calculates the adjustment ratio for a line with the given totals that ends at the given
+    /// item.
+    fn adjustment_ratio_with<Box, Glue, Penalty>(
+        &self,
+        item: &Item<Box, Glue, Penalty, N>,
+        width: N,
+        stretch: GlueSum<N>,
+        shrink: GlueSum<N>,
+    ) -> N {
+        let line_width = self.line_width - self.indent;
+        let r = item.adjustment_ratio(width, stretch.value(), shrink.value(), line_width);
+        if self.rigid_glue_fallback {
+            rigid_glue_fallback(r)
+        } else {
+            r
+        }
+    }
+
+    /// Returns true if a line with the given adjustment ratio would have to shrink by more than its
+    /// glue allows.
+    fn is_too_tight(&self, r: N) -> bool {
+        r < N::from(-1) - self.feasibility_epsilon
+    }
+
+    /// Returns true if the line from the current line start to b is too loose to be set with the
+    /// given adjustment ratio.
+    fn is_too_loose<S: ItemSource<N> + ?Sized>(&self, items: &S, b: usize, r: N) -> bool {
+        r > self.threshold + self.feasibility_epsilon
+            || self.max_glue_stretch.is_some_and(|factor| {
+                exceeds_max_glue_stretch(items, self.line_start..b, r, factor)
+            })
+    }
+
+    fn break_at(&mut self, b: Break<N>, on_line: &mut dyn FnMut(Line<N>)) {
+        on_line(Line {
             break_at: b.at,
             adjustment_ratio: b.adjustment_ratio,
+            truncated: false,
         });
 
         self.width -= b.width;
         self.stretch -= b.stretch;
         self.shrink -= b.shrink;
+
+        if let Some(region) = self.region {
+            self.y += line_height(&self.vertical_metrics[self.line_start..b.at]);
+            self.line_width = region.width_for(self.y);
+        }
+        self.indent = self.hanging_indent.1;
+        self.line_start = b.at + 1;
     }
 
-    fn layout_paragraph<Box, Glue, Penalty>(
-        mut self,
-        items: &[Item<Box, Glue, Penalty, N>],
-    ) -> Vec<Line<N>> {
+    /// Resets the running totals for the line that follows the break at `previous`, or for the
+    /// first line if there is none, as if the break had just been taken. Returns the index of the
+    /// first item whose metrics belong to the line, which is usually the break itself, as its
+    /// metrics are added to the line that follows it. Glue that is not discardable instead belongs
+    /// to the line that ends at it, unless leading glue is trimmed.
+    fn begin_line<S: ItemSource<N> + ?Sized>(
+        &mut self,
+        items: &S,
+        previous: Option<usize>,
+    ) -> usize {
+        let first = match previous {
+            Some(p)
+                if !self.trim_leading_glue
+                    && matches!(
+                        items.item(p),
+                        Item::Glue {
+                            discardable: false,
+                            ..
+                        }
+                    ) =>
+            {
+                p + 1
+            }
+            _ => previous.unwrap_or(0),
+        };
+        self.width = N::from(0);
+        self.stretch = GlueSum::default();
+        self.shrink = GlueSum::default();
+        if self.trim_leading_glue {
+            let (width, stretch, shrink) = leading_glue(items, first..items.len());
+            self.width -= width;
+            self.stretch -= stretch;
+            self.shrink -= shrink;
+        }
+        self.line_start = previous.map_or(0, |p| p + 1);
+        self.indent = match previous {
+            Some(_) => self.hanging_indent.1,
+            None => self.hanging_indent.0,
+        };
+        first
+    }
+
+    fn layout_paragraph<S: ItemSource<N> + ?Sized>(
+        &mut self,
+        items: &S,
+        on_line: &mut dyn FnMut(Line<N>),
+    ) -> bool {
+        let items = &JustifiedLastLine::new(items, self.justify_last_line);
+        self.layout_after(items, None, on_line)
+    }
+
+    /// Lays out a paragraph given the lines previously laid out at a wider line width. Each line
+    /// that still fits is kept with its adjustment ratio updated, and the rest of the paragraph is
+    /// laid out afresh from the first line that does not.
+    fn relayout<S: ItemSource<N> + ?Sized>(
+        &mut self,
+        items: &S,
+        prev_lines: &[Line<N>],
+        on_line: &mut dyn FnMut(Line<N>),
+    ) -> bool {
+        let items = &JustifiedLastLine::new(items, self.justify_last_line);
+        let mut previous = None;
+        for line in prev_lines {
+            let first = self.begin_line(items, previous);
+            for i in first..line.break_at {
+                let (width, stretch, shrink, _) = items.item(i).is_legal_breakpoint(None);
+                self.width += width;
+                self.stretch += stretch;
+                self.shrink += shrink;
+            }
+            let adjustment_ratio = self.adjustment_ratio(&items.item(line.break_at));
+            if self.is_too_tight(adjustment_ratio) {
+                break;
+            }
+            on_line(Line {
+                break_at: line.break_at,
+                adjustment_ratio,
+                truncated: false,
+            });
+            previous = Some(line.break_at);
+        }
+        if previous.is_some_and(|p| p + 1 == items.len()) {
+            return true;
+        }
+        self.layout_after(items, previous, on_line)
+    }
+
+    /// Returns the width, stretch, and shrink of the item at b and indicates whether or not b is a
+    /// legal break. Items before `first_content` and the break at `previous` are not legal breaks.
+    fn breakpoint<S: ItemSource<N> + ?Sized>(
+        &self,
+        items: &JustifiedLastLine<'_, S>,
+        b: usize,
+        first_content: usize,
+        previous: Option<usize>,
+    ) -> Breakpoint<N> {
+        let item = items.item(b);
+        let (width, stretch, shrink, is_legal) = match self.breakpoints {
+            Some(breakpoints) => items.breakpoint(b, breakpoints[b]),
+            None => item.is_legal_breakpoint(b.checked_sub(1).map(|i| items.item(i)).as_ref()),
+        };
+        let is_legal = match item {
+            Item::Glue { .. } if self.ignore_zero_width_boxes => follows_visible_box(items, b),
+            _ => is_legal,
+        };
+        let is_legal = is_legal
+            && (self.glue_breaks || !matches!(item, Item::Glue { .. }))
+            && (b >= first_content || b + 1 == items.len())
+            && Some(b) != previous;
+        (width, stretch, shrink, is_legal)
+    }
+
+    /// Returns true if the current line must end at the last-resort penalty at b, i.e. if the line
+    /// has no earlier candidate break and it would not fit at the next legal break after b that is
+    /// not a last-resort penalty.
+    fn needs_last_resort<S: ItemSource<N> + ?Sized>(
+        &self,
+        items: &JustifiedLastLine<'_, S>,
+        b: usize,
+        first_content: usize,
+        last_breakpoint: Option<&Break<N>>,
+    ) -> bool {
+        if last_breakpoint.is_some_and(|l| !is_last_resort(&items.item(l.at))) {
+            return false;
+        }
+
+        let (mut width, mut stretch, mut shrink) = (self.width, self.stretch, self.shrink);
+        for i in b..items.len() {
+            let (w, y, z, is_legal) = self.breakpoint(items, i, first_content, None);
+            let item = items.item(i);
+            if i > b && is_legal && !is_last_resort(&item) {
+                let r = self.adjustment_ratio_with(&item, width, stretch, shrink);
+                return self.is_too_tight(r) || self.is_too_loose(items, i, r);
+            }
+            width += w;
+            stretch += y;
+            shrink += z;
+        }
+        true
+    }
+
+    /// Lays out the items that follow the break at `previous`, or the entire paragraph if there is
+    /// none.
+    fn layout_after<S: ItemSource<N> + ?Sized>(
+        &mut self,
+        items: &JustifiedLastLine<'_, S>,
+        previous: Option<usize>,
+        on_line: &mut dyn FnMut(Line<N>),
+    ) -> bool {
+        let first = self.begin_line(items, previous);
+        let first_content = match previous {
+            Some(_) => 0,
+            None => first_content(items),
+        };
+
         let mut last_breakpoint: Option<Break<N>> = None;
-        for (b, item) in items.iter().enumerate() {
+        for b in first..items.len() {
+            let item = items.item(b);
             let (width, stretch, shrink, is_legal) =
-                item.is_legal_breakpoint((b != 0).then(|| &items[b - 1]));
+                self.breakpoint(items, b, first_content, previous);
             if is_legal {
-                let adjustment_ratio =
-                    item.adjustment_ratio(self.width, self.stretch, self.shrink, self.line_width);
-                if let Some(b) = last_breakpoint {
-                    if adjustment_ratio < N::from(-1)
-                        || adjustment_ratio > self.threshold
+                let adjustment_ratio = self.adjustment_ratio(&item);
+                if let Some(b) = last_breakpoint.take() {
+                    if self.is_too_tight(adjustment_ratio)
+                        || self.is_too_loose(items, b.at, adjustment_ratio)
                         || b.is_mandatory
                     {
-                        self.break_at(b);
+                        self.break_at(b, on_line);
+                    } else {
+                        last_breakpoint = Some(b);
                     }
                 }
+            }
 
-                let adjustment_ratio =
-                    item.adjustment_ratio(self.width, self.stretch, self.shrink, self.line_width);
+            // A last-resort penalty is only a candidate if the line has no other feasible break.
+            let is_candidate = is_legal
+                && (!is_last_resort(&item)
+                    || self.needs_last_resort(items, b, first_content, last_breakpoint.as_ref()));
+            if is_candidate {
+                let adjustment_ratio = self.adjustment_ratio(&item);
 
-                let adjustment_ratio = if adjustment_ratio < N::from(-1) {
+                let adjustment_ratio = if self.is_too_tight(adjustment_ratio) {
                     if !self.allow_overflow {
-                        return Vec::new();
+                        return false;
                     }
                     N::from(0)
                 } else {
                     adjustment_ratio
                 };
-                if adjustment_ratio > self.threshold {
-                    return Vec::new();
+                if self.is_too_loose(items, b, adjustment_ratio) {
+                    return false;
                 }
 
+                // If leading glue is trimmed, the glue that follows this break belongs to neither
+                // this line nor the next. Glue that is not discardable belongs to this line, as it
+                // keeps its width at the break.
+                let (trim_width, trim_stretch, trim_shrink) = if self.trim_leading_glue {
+                    leading_glue(items, b..items.len())
+                } else if let Item::Glue {
+                    discardable: false, ..
+                } = item
+                {
+                    (width, stretch, shrink)
+                } else {
+                    (N::from(0), GlueSum::default(), GlueSum::default())
+                };
+                let (mut stretch, mut shrink) = (self.stretch, self.shrink);
+                stretch += trim_stretch;
+                shrink += trim_shrink;
                 last_breakpoint = Some(Break {
-                    width: self.width,
-                    stretch: self.stretch,
-                    shrink: self.shrink,
+                    width: self.width + trim_width,
+                    stretch,
+                    shrink,
                     adjustment_ratio,
-                    is_mandatory: item.is_mandatory_break(),
+                    is_mandatory: item.is_forced(),
                     at: b,
                 });
             }
@@ -143,9 +776,364 @@ impl<N: Num> FirstFitLayout<N> {
             self.shrink += shrink;
         }
         if let Some(b) = last_breakpoint {
-            self.break_at(b);
+            let previous = self.line_start.checked_sub(1);
+            if self.trailing_empty_line || !is_trailing_empty_line(items, previous, b.at) {
+                self.break_at(b, on_line);
+            }
         }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{monospace, rigid_monospace, split, tenths};
+
+    #[test]
+    fn trim_leading_glue() {
+        let text = "  The quick brown fox jumps over the lazy dog.";
+        let mut items = monospace(text);
+        items[0] = Item::glue(1.0, 1.0, 0.0, ());
+
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let lines = split(text, &first_fit.layout_paragraph(&items, 19.0));
+        assert_eq!(
+            lines,
+            ["  The quick brown", "fox jumps over the", "lazy dog."]
+        );
+
+        let first_fit = first_fit.with_trim_leading_glue(true);
+        let lines = split(text, &first_fit.layout_paragraph(&items, 19.0));
+        assert_eq!(
+            lines,
+            ["  The quick brown fox", "jumps over the lazy", "dog."]
+        );
+    }
+
+    #[test]
+    fn non_discardable_glue() {
+        let text = "aa bb cc dd ee";
+        let mut items = monospace(text);
+        for item in &mut items {
+            if let Item::Glue { discardable, .. } = item {
+                *discardable = false;
+            }
+        }
+
+        // The breaking space keeps its width at the end of its line, but does not also begin the
+        // next line.
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let lines = first_fit.layout_paragraph(&items, 6.0);
+        assert_eq!(split(text, &lines), ["aa bb", "cc dd", "ee"]);
+        assert_eq!(lines[1].adjustment_ratio, 0.0);
 
-        self.lines
+        // Relaying out from the lines at a wider width measures the kept lines the same way.
+        let wide = first_fit.layout_paragraph(&items, 7.0);
+        assert_eq!(split(text, &wide), ["aa bb", "cc dd", "ee"]);
+        let lines = first_fit.relayout_narrower(&items, 7.0, &wide, 6.0);
+        assert_eq!(split(text, &lines), ["aa bb", "cc dd", "ee"]);
+    }
+
+    #[test]
+    fn last_resort_penalty() {
+        let breaks = |lines: &[Line<f32>]| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+        let last_resort = Item::Penalty {
+            width: 0.0,
+            cost: 50.0,
+            flags: PenaltyFlags::LAST_RESORT,
+            data: (),
+        };
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+
+        // An ordinary penalty is taken as soon as it is the last break that fits.
+        let mut items = monospace("aa aa bbbbcccc");
+        items.insert(10, Item::penalty(50.0, ()));
+        assert_eq!(breaks(&first_fit.layout_paragraph(&items, 10.0)), [10, 16]);
+
+        // A last-resort penalty is skipped in favor of an earlier glue break on the same line.
+        items[10] = last_resort.clone();
+        assert_eq!(breaks(&first_fit.layout_paragraph(&items, 10.0)), [5, 16]);
+
+        // Without a glue break that fits, the penalty is used after all.
+        let mut items = monospace("aaaabbbb cc");
+        items.insert(4, last_resort);
+        assert_eq!(breaks(&first_fit.layout_paragraph(&items, 6.0)), [4, 9, 13]);
+    }
+
+    #[test]
+    fn layout_with() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+
+        let mut streamed = Vec::new();
+        assert!(first_fit.layout_with(&items, 20.0, |line| streamed.push(line.break_at)));
+
+        let lines = first_fit.layout_paragraph(&items, 20.0);
+        assert_eq!(
+            streamed,
+            lines.iter().map(|l| l.break_at).collect::<Vec<_>>()
+        );
+        assert_eq!(streamed.last(), Some(&(items.len() - 1)));
+    }
+
+    #[test]
+    fn reusable_layout() {
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let mut layout = first_fit.reusable_layout();
+        for text in [
+            "The quick brown fox jumps over the lazy dog.",
+            "Pack my box with five dozen liquor jugs.",
+        ] {
+            let items = monospace(text);
+            for line_width in [15.0, 20.0] {
+                let expected = first_fit.layout_paragraph(&items, line_width);
+                assert_eq!(
+                    split(text, layout.run(&items, line_width)),
+                    split(text, &expected)
+                );
+            }
+        }
+
+        // A failed run leaves no lines behind.
+        let items = monospace("supercalifragilistic");
+        assert!(layout.run(&items, 10.0).is_empty());
+    }
+
+    #[test]
+    fn relayout_narrower() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let breaks = |lines: &[Line<f32>]| {
+            lines
+                .iter()
+                .map(|l| (l.break_at, l.adjustment_ratio))
+                .collect::<Vec<_>>()
+        };
+
+        for first_fit in [
+            FirstFit::new().with_threshold(f32::INFINITY),
+            FirstFit::new()
+                .with_threshold(f32::INFINITY)
+                .with_trim_leading_glue(true)
+                .with_hanging_indent(2.0, 0.0),
+        ] {
+            let mut prev_width = 44.0;
+            let mut prev_lines = first_fit.layout_paragraph(&items, prev_width);
+            for new_width in [40.0, 30.0, 25.0, 20.0, 19.0, 15.0, 10.0] {
+                let lines = first_fit.relayout_narrower(&items, prev_width, &prev_lines, new_width);
+                assert_eq!(
+                    breaks(&lines),
+                    breaks(&first_fit.layout_paragraph(&items, new_width))
+                );
+                (prev_width, prev_lines) = (new_width, lines);
+            }
+        }
+
+        // "The quick brown fox" still fits at 19 units, so it is kept without its slack.
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let prev_lines = first_fit.layout_paragraph(&items, 20.0);
+        let lines = first_fit.relayout_narrower(&items, 20.0, &prev_lines, 19.0);
+        assert_eq!(split(text, &lines)[0], "The quick brown fox");
+        assert_eq!(prev_lines[0].adjustment_ratio, 1.0 / 3.0);
+        assert_eq!(lines[0].adjustment_ratio, 0.0);
+    }
+
+    #[test]
+    fn glue_breaks() {
+        // Allow the statement to break after the "=" by inserting a penalty before the space.
+        let text = "    let total = first + second;";
+        let mut items = monospace(text);
+        items.insert(15, Item::penalty(0.0, ()));
+        let breaks = |lines: &[Line<f32>]| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        assert_eq!(breaks(&first_fit.layout_paragraph(&items, 24.0)), [24, 33]);
+
+        let first_fit = first_fit.with_glue_breaks(false);
+        assert_eq!(breaks(&first_fit.layout_paragraph(&items, 24.0)), [15, 33]);
+        assert!(first_fit.layout_paragraph(&items[16..], 10.0).is_empty());
+    }
+
+    #[test]
+    fn ignore_zero_width_boxes() {
+        // A zero-width marker sits between the two spaces that follow "aaa".
+        let mut items = monospace("aaa  bbb cc");
+        items.insert(
+            4,
+            Item::Box {
+                width: 0.0,
+                data: (),
+            },
+        );
+        let breaks = |lines: &[Line<f32>]| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        assert_eq!(breaks(&first_fit.layout_paragraph(&items, 6.0)), [5, 9, 13]);
+
+        // The glue that follows the marker is not a breakpoint once the marker is ignored.
+        let first_fit = first_fit.with_ignore_zero_width_boxes(true);
+        assert_eq!(breaks(&first_fit.layout_paragraph(&items, 6.0)), [3, 9, 13]);
+    }
+
+    #[test]
+    fn justify_last_line() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+
+        // By default, the finishing glue absorbs nearly all of the last line's leftover space.
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let lines = first_fit.layout_paragraph(&items, 20.0);
+        assert_eq!(split(text, &lines)[2], "dog.");
+        assert!(lines[2].adjustment_ratio < 0.001);
+
+        // Once justified, the glue that precedes "dog." must fill the remaining 15 units.
+        let lines = first_fit
+            .with_justify_last_line(true)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(split(text, &lines)[2], "dog.");
+        assert_eq!(lines[2].adjustment_ratio, 15.0);
+    }
+
+    #[test]
+    fn trailing_empty_line() {
+        // End the paragraph with a newline, i.e. finishing glue and a forced break.
+        let mut items = monospace("The quick brown fox jumps over the lazy dog.");
+        let n = items.len();
+        items.splice(n - 2..n - 2, [items[n - 2].clone(), Item::forced_break(())]);
+        let breaks = |lines: &[Line<f32>]| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let lines = first_fit.layout_paragraph(&items, 20.0);
+        assert_eq!(breaks(&lines), [19, 39, 45, 47]);
+
+        let first_fit = first_fit.with_trailing_empty_line(false);
+        let lines = first_fit.layout_paragraph(&items, 20.0);
+        assert_eq!(breaks(&lines), [19, 39, 45]);
+    }
+
+    #[test]
+    fn rigid_glue_fallback() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = rigid_monospace(text);
+
+        let first_fit = FirstFit::new();
+        assert!(first_fit.layout_paragraph(&items, 20.0).is_empty());
+
+        let lines = first_fit
+            .with_rigid_glue_fallback(true)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+        assert!(lines[..2].iter().all(|l| l.adjustment_ratio == 0.0));
+    }
+
+    #[test]
+    fn line_counts_over_widths() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let widths = [4.0, 10.0, 15.0, 20.0, 25.0, 50.0];
+        let expected: Vec<usize> = widths
+            .iter()
+            .map(|&w| first_fit.layout_paragraph(&items, w).len())
+            .collect();
+        assert_eq!(expected, [0, 5, 3, 3, 2, 1]);
+        assert_eq!(first_fit.line_counts_over_widths(&items, &widths), expected);
+    }
+
+    #[test]
+    fn feasibility_epsilon() {
+        // Five words set at exactly their full shrink, which is just infeasible in floating point
+        // without a tolerance, so the last word would be pushed onto a line of its own.
+        let items = tenths(5);
+        let exact = FirstFit::new()
+            .with_threshold(f32::INFINITY)
+            .with_feasibility_epsilon(0.0);
+        assert_eq!(exact.layout_paragraph(&items, 0.5).len(), 2);
+
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let lines = first_fit.layout_paragraph(&items, 0.5);
+        assert_eq!(lines.len(), 1);
+        assert!((lines[0].adjustment_ratio + 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn stable_width_range() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let breaks = |line_width| {
+            first_fit
+                .layout_paragraph(&items, line_width)
+                .iter()
+                .map(|l| l.break_at)
+                .collect::<Vec<_>>()
+        };
+
+        // The second line, " jumps over the lazy", is 20 wide including the glue at which the
+        // first line breaks, and "jumps" fits on the first line once the width reaches 25.
+        let (min, max) = first_fit.stable_width_range(&items, 22.0);
+        assert_eq!((min, max), (20.0, 25.0));
+        assert_eq!(breaks(min), breaks(22.0));
+        assert_eq!(breaks(24.9), breaks(22.0));
+        assert_ne!(breaks(19.9), breaks(22.0));
+        assert_ne!(breaks(max), breaks(22.0));
+
+        let first_fit = first_fit.with_trim_leading_glue(true);
+        assert_eq!(first_fit.stable_width_range(&items, 22.0), (19.0, 24.0));
+    }
+
+    #[test]
+    fn leading_penalty() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        for penalty in [
+            Item::flagged_penalty(0.0, -100.0, true, ()),
+            Item::forced_break(()),
+        ] {
+            let mut items = monospace(text);
+            items.insert(0, penalty);
+            let lines = FirstFit::new()
+                .with_threshold(f32::INFINITY)
+                .layout_paragraph(&items, 20.0);
+            assert_eq!(
+                lines.iter().map(|l| l.break_at).collect::<Vec<_>>(),
+                [20, 40, 46]
+            );
+        }
+
+        // A kern is not content, so a penalty that follows a leading kern is not a breakpoint
+        // either.
+        let mut items = monospace(text);
+        items.splice(0..0, [Item::Kern { width: 0.0 }, Item::forced_break(())]);
+        let lines = FirstFit::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(
+            lines.iter().map(|l| l.break_at).collect::<Vec<_>>(),
+            [21, 41, 47]
+        );
+
+        // Nor does a leading penalty limit the range of stable widths. The glue is flexible enough
+        // to satisfy the default threshold, and the paragraph begins with an indent of stretchable
+        // glue so that its first line can stretch.
+        let mut items = monospace("_aaaa bb c dd e ff ggg");
+        let n = items.len();
+        for item in &mut items[..n - 2] {
+            if let Item::Glue {
+                stretch, shrink, ..
+            } = item
+            {
+                (*stretch, *shrink) = (10.0, 1.0);
+            }
+        }
+        items[0] = Item::glue(0.0, 10.0, 0.0, ());
+        let first_fit = FirstFit::new();
+        let (min, max) = first_fit.stable_width_range(&items, 7.0);
+        assert!(min <= 7.0 && 7.0 < max);
+        items.insert(0, Item::penalty(0.0, ()));
+        assert_eq!(first_fit.stable_width_range(&items, 7.0), (min, max));
     }
 }
@@ -0,0 +1,239 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use crate::math::Num;
+use crate::{GlueDistribution, GlueOrder, Line};
+
+/// The metrics of a glue item on a line, as passed to `Justifier::distribute`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlueMetrics<N = f32> {
+    /// The natural width of the glue.
+    pub width: N,
+    /// The stretch of the glue.
+    pub stretch: N,
+    /// The order of infinity of the stretch.
+    pub stretch_order: GlueOrder,
+    /// The shrink of the glue.
+    pub shrink: N,
+    /// The order of infinity of the shrink.
+    pub shrink_order: GlueOrder,
+}
+
+impl<N: Num> GlueMetrics<N> {
+    /// Returns the stretch of the glue and its order if `slack` is positive, or its shrink and
+    /// order otherwise.
+    fn flex(&self, slack: N) -> (N, GlueOrder) {
+        if slack < N::from(0) {
+            (self.shrink, self.shrink_order)
+        } else {
+            (self.stretch, self.stretch_order)
+        }
+    }
+}
+
+/// Returns the width of each glue item on a line whose stretch, if `slack` is positive, or shrink
+/// otherwise is dominated by an infinite order, or `None` if the line has no such glue. Glue of
+/// the dominant order absorbs all of the slack in proportion to its amount, and glue of lower
+/// orders is set at its natural width. Infinite amounts are ignored, as they cannot be shared.
+pub(crate) fn infinite_order_widths<N: Num>(slack: N, glue: &[GlueMetrics<N>]) -> Option<Vec<N>> {
+    if slack == N::from(0) {
+        return None;
+    }
+    let mut totals = [N::from(0); 4];
+    for g in glue {
+        let (amount, order) = g.flex(slack);
+        if amount.abs() < N::INFINITY {
+            totals[order as usize] += amount;
+        }
+    }
+    let order = [GlueOrder::Filll, GlueOrder::Fill, GlueOrder::Fil]
+        .into_iter()
+        .find(|&order| totals[order as usize] != N::from(0))?;
+    let total = totals[order as usize];
+    Some(
+        glue.iter()
+            .map(|g| match g.flex(slack) {
+                (amount, o) if o == order && amount.abs() < N::INFINITY => {
+                    g.width + slack * amount / total
+                }
+                _ => g.width,
+            })
+            .collect(),
+    )
+}
+
+/// Distributes the slack of a line among its glue items, e.g. when the positions of the items are
+/// resolved by `layout_positions_with`. Implementations decide how a line is justified once its
+/// breaks have been chosen, without affecting break selection.
+pub trait Justifier<N: Num> {
+    /// Returns the final width of each glue item on a line given the metrics of each item. `slack`
+    /// is the total amount by which the glue must grow, or shrink if it is negative, in order to
+    /// fill the line. The returned widths must correspond one-to-one with `glue`.
+    fn distribute(&self, slack: N, glue: &[GlueMetrics<N>]) -> Vec<N>;
+}
+
+/// Distributes slack in proportion to the stretch or shrink of each glue item, like
+/// `GlueDistribution::Proportional`. If the line has glue of an infinite order, the slack is
+/// shared by the glue of the highest such order alone. Otherwise, if any glue on the line is
+/// infinitely stretchable or shrinkable, the slack is shared equally by that glue alone.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Proportional;
+
+impl<N: Num> Justifier<N> for Proportional {
+    fn distribute(&self, slack: N, glue: &[GlueMetrics<N>]) -> Vec<N> {
+        if let Some(widths) = infinite_order_widths(slack, glue) {
+            return widths;
+        }
+        let flex = |g: &GlueMetrics<N>| g.flex(slack).0;
+        let infinite = glue.iter().filter(|g| flex(g) >= N::INFINITY).count();
+        let total = glue.iter().fold(N::from(0), |total, g| total + flex(g));
+        let adjustment_ratio = if infinite != 0 || total == N::from(0) {
+            N::from(0)
+        } else {
+            slack / total
+        };
+        let line = Line::new(0, adjustment_ratio);
+        glue.iter()
+            .map(
+                |g @ &GlueMetrics {
+                     width,
+                     stretch,
+                     shrink,
+                     ..
+                 }| {
+                    if infinite == 0 {
+                        line.glue_width_with(
+                            width,
+                            stretch,
+                            shrink,
+                            GlueDistribution::Proportional,
+                            slack,
+                            0,
+                        )
+                    } else if flex(g) >= N::INFINITY {
+                        line.glue_width_with(
+                            width,
+                            stretch,
+                            shrink,
+                            GlueDistribution::Uniform,
+                            slack,
+                            infinite,
+                        )
+                    } else {
+                        width
+                    }
+                },
+            )
+            .collect()
+    }
+}
+
+/// Distributes slack equally among the glue items on a line regardless of their stretch or
+/// shrink, like `GlueDistribution::Uniform`, so that every space grows or shrinks by the same
+/// amount.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Uniform;
+
+impl<N: Num> Justifier<N> for Uniform {
+    fn distribute(&self, slack: N, glue: &[GlueMetrics<N>]) -> Vec<N> {
+        let line = Line::new(0, N::from(0));
+        glue.iter()
+            .map(
+                |&GlueMetrics {
+                     width,
+                     stretch,
+                     shrink,
+                     ..
+                 }| {
+                    line.glue_width_with(
+                        width,
+                        stretch,
+                        shrink,
+                        GlueDistribution::Uniform,
+                        slack,
+                        glue.len(),
+                    )
+                },
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::monospace;
+    use crate::{
+        layout_positions, layout_positions_with, KnuthPlass, ParagraphLayout, PositionOptions,
+    };
+
+    fn glue(width: f32, stretch: f32, shrink: f32) -> GlueMetrics {
+        GlueMetrics {
+            width,
+            stretch,
+            stretch_order: GlueOrder::Normal,
+            shrink,
+            shrink_order: GlueOrder::Normal,
+        }
+    }
+
+    #[test]
+    fn built_in_justifiers() {
+        let glue_items = [glue(1.0, 1.0, 0.0), glue(1.0, 3.0, 0.0)];
+        assert_eq!(Proportional.distribute(2.0, &glue_items), [1.5, 2.5]);
+        assert_eq!(Uniform.distribute(2.0, &glue_items), [2.0, 2.0]);
+        let glue_items = [glue(1.0, 1.0, 0.0), glue(0.0, f32::INFINITY, 0.0)];
+        assert_eq!(Proportional.distribute(2.0, &glue_items), [1.0, 2.0]);
+
+        // Only glue of the highest order on the side being adjusted absorbs the slack.
+        let fil = GlueMetrics {
+            stretch_order: GlueOrder::Fil,
+            ..glue(1.0, 1.0, 1.0)
+        };
+        let fill = GlueMetrics {
+            stretch_order: GlueOrder::Fill,
+            ..glue(1.0, 3.0, 1.0)
+        };
+        let glue_items = [glue(1.0, 1.0, 1.0), fil, fill, fill];
+        assert_eq!(
+            Proportional.distribute(6.0, &glue_items),
+            [1.0, 1.0, 4.0, 4.0]
+        );
+        assert_eq!(
+            Proportional.distribute(-2.0, &glue_items),
+            [0.5, 0.5, 0.5, 0.5]
+        );
+    }
+
+    #[test]
+    fn custom_justifier() {
+        // Gives all of a line's slack to its last glue item.
+        struct Last;
+
+        impl Justifier<f32> for Last {
+            fn distribute(&self, slack: f32, glue: &[GlueMetrics]) -> Vec<f32> {
+                let n = glue.len();
+                glue.iter()
+                    .enumerate()
+                    .map(|(i, g)| if i + 1 == n { g.width + slack } else { g.width })
+                    .collect()
+            }
+        }
+
+        // "a b c" has one unit of slack.
+        let items = monospace("a b c dd");
+        let lines = KnuthPlass::new().layout_paragraph(&items, 6.0);
+        let positions =
+            layout_positions_with(&items, &lines, 6.0, PositionOptions::default(), &Last);
+        assert_eq!(positions[0], [0.0, 1.0, 2.0, 3.0, 5.0]);
+
+        // The built-in proportional justifier matches the default positions.
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let lines = KnuthPlass::new().layout_paragraph(&items, 20.0);
+        let opts = PositionOptions::default();
+        assert_eq!(
+            layout_positions_with(&items, &lines, 20.0, opts, &Proportional),
+            layout_positions(&items, &lines, 20.0, opts)
+        );
+    }
+}
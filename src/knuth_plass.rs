@@ -1,9 +1,19 @@
 extern crate alloc;
-use alloc::{vec, vec::Vec};
-use bumpalo::Bump;
+use alloc::{boxed, vec, vec::Vec};
+use core::cmp::Ordering;
+use core::mem::size_of;
+use core::ops::Range;
 
+use crate::balance_tail::BISECTION_STEPS;
 use crate::math::Num;
-use crate::{Item, Line, ParagraphLayout};
+use crate::region::{line_height, split_lines, vertical_metrics};
+use crate::{
+    badness, diagnose, exceeds_max_glue_stretch, first_content, follows_visible_box,
+    is_trailing_empty_line, leading_glue, range_adjustment_ratio, rigid_glue_fallback, sum_metrics,
+    truncated_line, BoxMetrics, Breakpoint, FirstFit, GlueSum, Item, ItemMetrics, ItemSource,
+    JustifiedLastLine, LayoutError, Line, MappedItems, ParagraphLayout, PenaltyFlags,
+    PreparedParagraph, Region,
+};
 
 /// Runs the Knuth-Plass line-breaking algorithm to calculate the optimal break points for a
 /// paragraph.
@@ -11,19 +21,125 @@ pub struct KnuthPlass<N> {
     flagged_demerit: N,
     fitness_demerit: N,
     threshold: N,
+    feasibility_epsilon: N,
     looseness: usize,
+    break_penalty: Option<boxed::Box<dyn Fn(usize) -> N>>,
+    line_relation_penalty: Option<boxed::Box<dyn Fn(usize, usize) -> N>>,
+    line_weight: Option<boxed::Box<dyn Fn(usize) -> N>>,
+    fitness_fn: Option<boxed::Box<dyn Fn(N) -> Fitness>>,
+    trim_leading_glue: bool,
+    rigid_glue_fallback: bool,
+    max_rag: Option<N>,
+    short_line_penalty: Option<(usize, N)>,
+    penultimate_penalty: Option<N>,
+    arena_capacity: Option<usize>,
+    work_budget: Option<usize>,
+    hanging_indent: (N, N),
+    objective: Objective,
+    max_glue_stretch: Option<N>,
+    pinned_breaks: Vec<(usize, usize)>,
+    hyphenation_words: Vec<(usize, usize)>,
+    hyphenation_min_edge: usize,
+    content_weights: Vec<N>,
+    trailing_empty_line: bool,
+    glue_breaks: bool,
+    ignore_zero_width_boxes: bool,
+    justify_last_line: bool,
+    no_single_box_last_line: bool,
+    greedy_shortcut: Option<N>,
+    tiebreak_seed: Option<u64>,
+    max_lines: Option<usize>,
+    ellipsis: Option<N>,
+    balance_then_justify: bool,
 }
 
-impl<N: Num> KnuthPlass<N> {
-    /// Creates a new KnuthPlass layout with default parameter values.
-    pub fn new() -> Self {
-        KnuthPlass {
+/// The objective that Knuth-Plass optimizes when choosing among feasible layouts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Objective {
+    /// Minimize the total demerits of the paragraph's lines, as in Knuth-Plass '81. This may
+    /// accept one very bad line in exchange for several good ones.
+    #[default]
+    MinSum,
+    /// Minimize the greatest demerits of any one of the paragraph's lines.
+    MinMax,
+}
+
+/// The core parameters of a Knuth-Plass layout, e.g. as loaded from a configuration file. The
+/// default values match those of `KnuthPlass::new`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct KnuthPlassParams<N> {
+    /// The demerit for flagged penalties, as set by `with_flagged_demerit`.
+    pub flagged_demerit: N,
+    /// The demerit for adjacent lines in different fitness classes, as set by
+    /// `with_fitness_demerit`.
+    pub fitness_demerit: N,
+    /// The adjustment ratio threshold, as set by `with_threshold`.
+    pub threshold: N,
+    /// The looseness parameter, as set by `with_looseness`.
+    pub looseness: usize,
+}
+
+impl<N: Num> Default for KnuthPlassParams<N> {
+    fn default() -> Self {
+        KnuthPlassParams {
             flagged_demerit: N::from(100),
             fitness_demerit: N::from(100),
             threshold: N::from(1),
             looseness: 0,
         }
     }
+}
+
+impl<N: Num> KnuthPlass<N> {
+    /// Creates a new KnuthPlass layout with default parameter values.
+    pub fn new() -> Self {
+        Self::with_params(KnuthPlassParams::default())
+    }
+
+    /// Creates a new KnuthPlass layout with the given core parameters and default values for the
+    /// rest.
+    pub fn with_params(params: KnuthPlassParams<N>) -> Self {
+        KnuthPlass {
+            flagged_demerit: params.flagged_demerit,
+            fitness_demerit: params.fitness_demerit,
+            threshold: params.threshold,
+            feasibility_epsilon: N::feasibility_epsilon(),
+            looseness: params.looseness,
+            break_penalty: None,
+            line_relation_penalty: None,
+            line_weight: None,
+            fitness_fn: None,
+            trim_leading_glue: false,
+            rigid_glue_fallback: false,
+            max_rag: None,
+            short_line_penalty: None,
+            penultimate_penalty: None,
+            arena_capacity: None,
+            work_budget: None,
+            hanging_indent: (N::from(0), N::from(0)),
+            max_glue_stretch: None,
+            pinned_breaks: Vec::new(),
+            hyphenation_words: Vec::new(),
+            hyphenation_min_edge: 0,
+            content_weights: Vec::new(),
+            trailing_empty_line: true,
+            glue_breaks: true,
+            ignore_zero_width_boxes: false,
+            justify_last_line: false,
+            no_single_box_last_line: false,
+            objective: Objective::MinSum,
+            greedy_shortcut: None,
+            tiebreak_seed: None,
+            max_lines: None,
+            ellipsis: None,
+            balance_then_justify: false,
+        }
+    }
 
     /// Sets the demerit for flagged penalties. Defaults to 100. Referred to as 𝛂 in Knuth-Plass
     /// '81.
@@ -39,6 +155,15 @@ impl<N: Num> KnuthPlass<N> {
         self
     }
 
+    /// Sets a function that classifies a line into a fitness class given its adjustment ratio.
+    /// This allows the bucketing of adjustment ratios to be remapped, e.g. for house styles that
+    /// tolerate looser lines, without replacing the demerit calculation. Defaults to the cutoffs
+    /// described by `Fitness`.
+    pub fn with_fitness_fn(mut self, fitness_fn: impl Fn(N) -> Fitness + 'static) -> Self {
+        self.fitness_fn = Some(boxed::Box::new(fitness_fn));
+        self
+    }
+
     /// Sets the adjustment ratio threshold. Lines will not be allowed to break at a given point if
     /// doing so would cause the line's adjustment ratio to exceed this value. Defaults to 1.
     /// Referred to as 𝛒 in Knuth-Plass '81.
@@ -47,62 +172,736 @@ impl<N: Num> KnuthPlass<N> {
         self
     }
 
+    /// Sets the tolerance for comparisons of adjustment ratios against the bounds of feasibility,
+    /// so that a line whose adjustment ratio lies within `epsilon` below -1 or above the threshold,
+    /// e.g. due to rounding error in running totals, is still feasible. Defaults to
+    /// `N::feasibility_epsilon()`, which is 1e-5 for `f32` and 0 for fixed-point numbers.
+    pub fn with_feasibility_epsilon(mut self, epsilon: N) -> Self {
+        self.feasibility_epsilon = epsilon;
+        self
+    }
+
     /// Sets the looseness parameter. The looseness is an integer 𝗾 such that the total number of
     /// lines produced for the paragraph is as close as possible to 𝗾 plus the optimum number,
     /// without violating the conditions of feasibility.
+    ///
+    /// If the paragraph contains forced breaks, the looseness is applied to each of the segments
+    /// that they delimit independently, as each forced break ends every line that precedes it.
+    ///
+    /// A nonzero looseness requires layouts with different numbers of lines to be tracked
+    /// separately, so it increases the number of active breakpoints and the cost of the layout.
     pub fn with_looseness(mut self, looseness: usize) -> Self {
         self.looseness = looseness;
         self
     }
-}
 
-impl<N: Num> Default for KnuthPlass<N> {
-    fn default() -> Self {
-        Self::new()
+    /// Sets a function that computes an additional cost for breaking at the item with the given
+    /// index. The cost is added to the item's own penalty cost when calculating the demerits for
+    /// a line that ends at that item. This allows break preferences to be computed at layout time
+    /// rather than baked into the paragraph's items. The function is not consulted for forced
+    /// breaks, and cannot make an illegal breakpoint legal.
+    pub fn with_break_penalty_fn(mut self, break_penalty: impl Fn(usize) -> N + 'static) -> Self {
+        self.break_penalty = Some(boxed::Box::new(break_penalty));
+        self
     }
-}
 
-impl<Box, Glue, Penalty, N: Num> ParagraphLayout<Box, Glue, Penalty, N> for KnuthPlass<N> {
-    fn layout_paragraph(
+    /// Sets a function that computes additional demerits for a line given the index of the item at
+    /// which the previous line breaks and the index of the item at which the line itself breaks.
+    /// This allows breaks to be judged in relation to each other, e.g. to discourage rivers of
+    /// whitespace by penalizing lines whose spaces would align with those of the previous line.
+    /// The function is not consulted for the first line, which has no previous line.
+    pub fn with_line_relation_penalty(
+        mut self,
+        line_relation_penalty: impl Fn(usize, usize) -> N + 'static,
+    ) -> Self {
+        self.line_relation_penalty = Some(boxed::Box::new(line_relation_penalty));
+        self
+    }
+
+    /// Sets a function that computes a weight for a line given its index, starting at 0. Each
+    /// line's demerits are multiplied by its weight, so lines with greater weights are set more
+    /// carefully at the expense of the others, e.g. to favor the first lines of display type,
+    /// where loose lines are most noticeable.
+    pub fn with_line_weight_fn(mut self, line_weight: impl Fn(usize) -> N + 'static) -> Self {
+        self.line_weight = Some(boxed::Box::new(line_weight));
+        self
+    }
+
+    /// Configures the layout to ignore the width, stretch, and shrink of glue items that precede
+    /// the first box on each line. Glue that follows a break is always discarded by Knuth-Plass, so
+    /// this primarily affects glue at the very start of the paragraph. Defaults to false.
+    pub fn with_trim_leading_glue(mut self, trim_leading_glue: bool) -> Self {
+        self.trim_leading_glue = trim_leading_glue;
+        self
+    }
+
+    /// Configures the layout to accept lines that are shorter than the line width but contain no
+    /// stretchable glue, e.g. justified monospace text with fixed-width spaces. Such lines would
+    /// otherwise have an infinite adjustment ratio. Instead, they are given an adjustment ratio of
+    /// 0 and the leftover space is left at the end of the line. Because every such line is then
+    /// considered perfectly set, the layout tends toward the fewest possible lines. Defaults to
+    /// false.
+    pub fn with_rigid_glue_fallback(mut self, rigid_glue_fallback: bool) -> Self {
+        self.rigid_glue_fallback = rigid_glue_fallback;
+        self
+    }
+
+    /// Sets the maximum rag depth for ragged-right text, i.e. the amount of space that may be left
+    /// at the end of a line before the line is penalized. Lines whose leftover width exceeds the
+    /// depth incur additional demerits that are computed from the excess in the same way that
+    /// badness is computed from the adjustment ratio, which steers the layout toward a more even
+    /// right edge. Lines that end in a forced break are exempt. By default, rag depth is not
+    /// constrained.
+    pub fn with_max_rag(mut self, depth: N) -> Self {
+        self.max_rag = Some(depth);
+        self
+    }
+
+    /// Sets a penalty for lines that contain fewer than `min_words` words. The penalty is added to
+    /// the demerits of each such line, including the last line of the paragraph. Because items do
+    /// not record word boundaries, a word is approximated as a run of boxes that is not interrupted
+    /// by glue. See `with_content_weights` to measure lines by content instead. By default, short
+    /// lines are not penalized.
+    pub fn with_short_line_penalty(mut self, min_words: usize, penalty: N) -> Self {
+        self.short_line_penalty = Some((min_words, penalty));
+        self
+    }
+
+    /// Sets a penalty for a tight second-to-last line, i.e. one whose adjustment ratio is below
+    /// -0.5. The penalty is added to the demerits of the last line of any layout whose previous
+    /// line is tight, which encourages the layout to loosen the second-to-last line and carry more
+    /// words onto the last one rather than leave a single word behind. By default, tight
+    /// second-to-last lines are not penalized.
+    pub fn with_penultimate_penalty(mut self, penalty: N) -> Self {
+        self.penultimate_penalty = Some(penalty);
+        self
+    }
+
+    /// Sets the amount of content in each item, e.g. the number of grapheme clusters that each box
+    /// represents, for heuristics that measure the content of a line. Items beyond the end of
+    /// `weights` have a weight of 1. Once weights are set, the short-line penalty measures each
+    /// line as the total weight of its boxes rather than its number of words, so its minimum is
+    /// then a minimum amount of content. This decouples the heuristics from the granularity of the
+    /// items, as one cluster may span several boxes and one box may represent several clusters.
+    pub fn with_content_weights(mut self, weights: &[N]) -> Self {
+        self.content_weights = weights.to_vec();
+        self
+    }
+
+    /// Configures whether a paragraph that ends with a forced break, e.g. an explicit newline,
+    /// produces a trailing empty line. If so, the line that follows the forced break and contains
+    /// only the paragraph's finishing items is emitted as an empty `Line` with an adjustment ratio
+    /// of 0; otherwise, the paragraph's last line ends at the forced break. Defaults to true.
+    pub fn with_trailing_empty_line(mut self, trailing_empty_line: bool) -> Self {
+        self.trailing_empty_line = trailing_empty_line;
+        self
+    }
+
+    /// Configures whether glue that follows a box is a legal breakpoint. Disabling glue breaks
+    /// restricts the paragraph to break only at its penalties, which suits code and other
+    /// preformatted text whose spacing must be preserved. Glue is still measured as usual.
+    /// Defaults to true.
+    pub fn with_glue_breaks(mut self, glue_breaks: bool) -> Self {
+        self.glue_breaks = glue_breaks;
+        self
+    }
+
+    /// Configures whether zero-width boxes, e.g. invisible anchors or markers, are ignored when
+    /// deciding whether glue follows a box. If so, glue is only a legal breakpoint if the nearest
+    /// item before it that is not a zero-width box is a box or a tab, so a marker between two glue
+    /// items does not make the second a breakpoint. Defaults to false.
+    pub fn with_ignore_zero_width_boxes(mut self, ignore_zero_width_boxes: bool) -> Self {
+        self.ignore_zero_width_boxes = ignore_zero_width_boxes;
+        self
+    }
+
+    /// Configures the layout to justify the last line of the paragraph like any other line. The
+    /// stretch of the paragraph's finishing glue, i.e. the glue that precedes its final forced
+    /// break, is ignored, so the last line must be stretched to the line width rather than left
+    /// ragged. Defaults to false.
+    pub fn with_justify_last_line(mut self, justify_last_line: bool) -> Self {
+        self.justify_last_line = justify_last_line;
+        self
+    }
+
+    /// Keeps at most `max_lines` lines of each paragraph, e.g. to clamp a paragraph to a fixed
+    /// number of lines in a user interface. The paragraph is laid out in full and the lines after
+    /// the first `max_lines` are dropped; the last line that is kept is marked as truncated. By
+    /// default, every line is kept.
+    pub fn with_max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = Some(max_lines);
+        self
+    }
+
+    /// Leaves room for an ellipsis of the given width after the last line of a paragraph that is
+    /// truncated by `with_max_lines`. The line is broken again at the last breakpoint at which it
+    /// fits within the line width less `width`, and is set at its natural width unless it must
+    /// shrink to fit, so the ellipsis can be drawn directly after it. If the line does not fit at
+    /// any breakpoint, e.g. because it consists of a single long word, it keeps its original
+    /// break. Has no effect on paragraphs that are not truncated.
+    pub fn with_ellipsis(mut self, width: N) -> Self {
+        self.ellipsis = Some(width);
+        self
+    }
+
+    /// Forbids layouts whose last line holds exactly one box, e.g. a single character or word that
+    /// is set as one box, so that content is pulled up onto the previous line or pushed down onto
+    /// the last one instead. This is a constraint rather than a penalty. If the paragraph cannot be
+    /// laid out without such a last line, it is laid out as if the option were not set. Defaults to
+    /// false.
+    pub fn with_no_single_box_last_line(mut self, no_single_box_last_line: bool) -> Self {
+        self.no_single_box_last_line = no_single_box_last_line;
+        self
+    }
+
+    /// Limits the work that the algorithm may do to lay out a paragraph, measured as the number of
+    /// break nodes that it creates and deactivates. Once the budget is exhausted, layout is
+    /// abandoned: `layout_paragraph` returns no lines and `try_layout_paragraph` returns
+    /// `LayoutError::BudgetExceeded`. This bounds the time spent on pathological paragraphs, e.g.
+    /// when laying out untrusted content. By default, the work is unlimited.
+    pub fn with_work_budget(mut self, max_node_ops: usize) -> Self {
+        self.work_budget = Some(max_node_ops);
+        self
+    }
+
+    /// Sets the initial capacity, in bytes, of the arena from which break nodes are allocated.
+    /// The arena grows as needed, so this only serves to avoid reallocation during layout for
+    /// paragraphs that produce many feasible breaks, or to avoid over-allocating for tiny ones. By
+    /// default, the arena is sized to hold one node per item in the paragraph.
+    pub fn with_arena_capacity(mut self, bytes: usize) -> Self {
+        self.arena_capacity = Some(bytes);
+        self
+    }
+
+    /// Sets a hanging indent. The first line is indented by `first` and every other line by `rest`,
+    /// and each indent is subtracted from the width that is available to its line, including
+    /// widths given by a region. Pass the same indents to `layout_positions` to place the lines
+    /// when rendering. Defaults to no indent.
+    pub fn with_hanging_indent(mut self, first: N, rest: N) -> Self {
+        self.hanging_indent = (first, rest);
+        self
+    }
+
+    /// Sets the objective that the layout optimizes. With `Objective::MinMax`, the total demerits
+    /// of a layout are the greatest demerits of any of its lines rather than their sum, including
+    /// the demerits reported by `layout_paragraph_with_stats` and `layout_chain`. Defaults to
+    /// `Objective::MinSum`.
+    pub fn with_objective(mut self, objective: Objective) -> Self {
+        self.objective = objective;
+        self
+    }
+
+    /// Limits how far any single glue item may be stretched. A break is infeasible if setting its
+    /// line would stretch any glue beyond `factor` times its natural width, e.g. a factor of 3
+    /// allows no space to be more than three times as wide as normal. Unlike the threshold, which
+    /// bounds the adjustment ratio of the line as a whole, this bounds the width of each space.
+    /// Glue with no natural width is not limited. By default, glue stretch is only limited by the
+    /// threshold.
+    pub fn with_max_glue_stretch(mut self, factor: N) -> Self {
+        self.max_glue_stretch = Some(factor);
+        self
+    }
+
+    /// Requires the line with the given zero-based index to end at the item with the given index,
+    /// e.g. to fix the last line of a column while balancing. Breaks may be pinned to several
+    /// lines. Pinning is a constraint rather than a preference: lines may not skip over a pinned
+    /// item, and a break at the item is only allowed on its line. If no feasible layout satisfies
+    /// every pin, e.g. because a pinned item is not a legal breakpoint, the paragraph cannot be
+    /// laid out.
+    pub fn with_pinned_break(mut self, item_index: usize, line: usize) -> Self {
+        self.pinned_breaks.push((item_index, line));
+        self
+    }
+
+    /// Constrains hyphenation within the given words, each of which is given as the range
+    /// `start..end` of its items. A flagged penalty within a word is only a legal breakpoint if at
+    /// least `min_edge` of the word's boxes precede it and follow it, e.g. so that at least two
+    /// letters are left on either side of a hyphen, and no word may be broken more than once.
+    /// Note that `min_edge` counts boxes rather than items: the penalties, kerns, and other items
+    /// within a word do not count toward it. Words must not overlap. By default, hyphenation is
+    /// only constrained by the flagged demerit.
+    pub fn with_hyphenation_constraints(
+        mut self,
+        words: &[(usize, usize)],
+        min_edge: usize,
+    ) -> Self {
+        self.hyphenation_words = words.to_vec();
+        self.hyphenation_words.sort_unstable();
+        self.hyphenation_min_edge = min_edge;
+        self
+    }
+
+    /// Enables an approximation that skips the optimizer for well-behaved paragraphs. Each
+    /// paragraph is first laid out greedily, as by `FirstFit` with this layout's threshold, indent,
+    /// and glue options; if the absolute adjustment ratio of every resulting line is below
+    /// `tolerance`, that layout is returned as is, and otherwise the paragraph is laid out by
+    /// Knuth-Plass as usual. This speeds up the common case of body text that already sets well.
+    ///
+    /// Because the greedy pass ignores demerits, penalty costs, and the other options of this
+    /// layout, its result is not guaranteed to be optimal, though a small tolerance makes a
+    /// difference unlikely. The shortcut applies to `layout_paragraph`, `layout_prepared`, and
+    /// `layout_source`, and is not taken if any breaks are pinned or the number of lines is
+    /// limited. By default, the optimizer always runs.
+    pub fn with_greedy_shortcut(mut self, tolerance: N) -> Self {
+        self.greedy_shortcut = Some(tolerance);
+        self
+    }
+
+    /// Breaks ties between equally good predecessors of a break pseudo-randomly, as determined by
+    /// `seed`, rather than in favor of the predecessor that ends at the cheaper penalty. Paths with
+    /// fewer flagged breaks are still preferred. The chosen breaks may differ between seeds, but
+    /// the total demerits of the chosen layout should not, so this is useful for shaking out bugs
+    /// that depend on the order in which breaks are considered.
+    #[cfg(feature = "test-util")]
+    pub fn with_tiebreak_seed(mut self, seed: u64) -> Self {
+        self.tiebreak_seed = Some(seed);
+        self
+    }
+
+    /// Configures the layout to balance the lengths of a paragraph's lines and then justify them,
+    /// e.g. for an epigraph. The paragraph is first laid out at the line width to determine its
+    /// number of lines, and then at the narrowest width at which it still takes that many lines,
+    /// which evens out their lengths. The breaks of the narrow layout are kept, and every line,
+    /// including the last, is justified to the full line width. This applies to
+    /// `layout_paragraph` and `layout_source`. Defaults to false.
+    pub fn with_balance_then_justify(mut self, balance_then_justify: bool) -> Self {
+        self.balance_then_justify = balance_then_justify;
+        self
+    }
+
+    /// Lays out a paragraph into a region whose width varies with vertical position. The width of
+    /// each line is queried from the region using the total height of the lines that precede it,
+    /// where the height of a line is determined by the vertical metrics of its boxes.
+    ///
+    /// Because the width of a line depends on the path of breaks that precedes it, each feasible
+    /// break only tracks the height of its best predecessor. The resulting layout is therefore not
+    /// guaranteed to be optimal.
+    pub fn layout_in_region<Box: BoxMetrics<N>, Glue, Penalty>(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        region: &impl Region<N>,
+    ) -> Vec<Line<N>> {
+        let mut layout = self.layout(items, region.width_for(N::from(0)));
+        layout.region = Some(region);
+        layout.vertical_metrics = vertical_metrics(items);
+        layout.run()
+    }
+
+    /// Lays out a paragraph into a region like `layout_in_region`, but keeps at most `max_lines`
+    /// lines, e.g. because the region runs out of vertical space. Returns the lines that fit and
+    /// the index of the item at which the first remaining line begins, or `None` if every line
+    /// fits. The remainder can be laid out in the next region by passing the items from that index
+    /// on. Returns `None` if the paragraph cannot be laid out.
+    ///
+    /// The lines that fit are those of the layout of the whole paragraph, so they are chosen with
+    /// the remaining lines in mind.
+    pub fn layout_until<Box: BoxMetrics<N>, Glue, Penalty>(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        region: &impl Region<N>,
+        max_lines: usize,
+    ) -> Option<(Vec<Line<N>>, Option<usize>)> {
+        split_lines(self.layout_in_region(items, region), items.len(), max_lines)
+    }
+
+    /// Lays out a paragraph in exactly `k` lines, relaxing the adjustment ratio threshold as
+    /// necessary. The configured threshold is tried first and is then repeatedly doubled until a
+    /// feasible layout with `k` lines exists, with the final attempt made at an infinite threshold.
+    /// The looseness parameter is ignored.
+    ///
+    /// Returns `None` if the paragraph cannot be laid out in `k` lines at any threshold, i.e. if
+    /// `k` is below the minimum or above the maximum achievable number of lines.
+    pub fn layout_exact_lines<Box, Glue, Penalty>(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+        k: usize,
+    ) -> Option<Vec<Line<N>>> {
+        let mut threshold = self.threshold;
+        loop {
+            let mut layout = self.layout(items, line_width);
+            layout.threshold = threshold;
+            layout.target_lines = Some(k);
+            // Track the active nodes for each line number separately so that no line count is
+            // pruned in favor of another.
+            layout.first_uniform_line = usize::MAX;
+
+            let lines = layout.run();
+            if !lines.is_empty() {
+                return Some(lines);
+            }
+            if threshold == N::INFINITY {
+                return None;
+            }
+            threshold = if threshold >= N::from(1024) {
+                N::INFINITY
+            } else if threshold < N::from(1) {
+                N::from(1)
+            } else {
+                threshold * N::from(2)
+            };
+        }
+    }
+
+    /// Lays out a paragraph with the given line width so that it fits on as few pages of
+    /// `lines_per_page` lines as possible, and returns the number of pages alongside the lines.
+    /// The paragraph is first laid out as usual; if its last page could be avoided by setting it
+    /// in fewer lines, e.g. because the optimal layout spills a single line onto a new page, the
+    /// layout with the most lines that fits on fewer pages is chosen instead. Each layout respects
+    /// the adjustment ratio threshold, so pages are only saved at acceptable badness. The
+    /// looseness parameter is ignored when pages are saved.
+    ///
+    /// Returns no pages and no lines if the paragraph could not be laid out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lines_per_page` is 0.
+    pub fn min_pages<Box, Glue, Penalty>(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+        lines_per_page: usize,
+    ) -> (usize, Vec<Line<N>>) {
+        let mut lines = self.layout_paragraph(items, line_width);
+        let mut pages = lines.len().div_ceil(lines_per_page);
+        'pages: while pages > 1 {
+            // Try each line count that fits on one page fewer, starting with the loosest.
+            let max_lines = (pages - 1) * lines_per_page;
+            for k in (max_lines + 1 - lines_per_page..=max_lines).rev() {
+                let mut layout = self.layout(items, line_width);
+                layout.target_lines = Some(k);
+                layout.first_uniform_line = usize::MAX;
+                let fewer = layout.run();
+                if !fewer.is_empty() {
+                    lines = fewer;
+                    pages -= 1;
+                    continue 'pages;
+                }
+            }
+            break;
+        }
+        (pages, lines)
+    }
+
+    /// Lays out a paragraph with the given line width and returns statistics about the chosen
+    /// layout alongside its lines. If the paragraph could not be laid out, the statistics are all
+    /// zero.
+    pub fn layout_paragraph_with_stats<Box, Glue, Penalty>(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+    ) -> (Vec<Line<N>>, LayoutStats<N>) {
+        self.layout(items, line_width).run_with_stats()
+    }
+
+    /// Lays out a paragraph with the given line width and returns the chosen breaks along with the
+    /// line number, fitness class, and running total demerits of each, in paragraph order. This
+    /// exposes the chain of break nodes from which the lines are computed, which is useful for
+    /// understanding why a particular break was chosen.
+    pub fn layout_chain<Box, Glue, Penalty>(
         &self,
         items: &[Item<Box, Glue, Penalty, N>],
         line_width: N,
+    ) -> Vec<ChosenBreak<N>> {
+        self.layout(items, line_width).run_chain()
+    }
+
+    /// Lays out a paragraph whose items are produced by the given source with the given line
+    /// width. This allows a paragraph to be laid out without materializing its items as a slice.
+    pub fn layout_source<S: ItemSource<N> + ?Sized>(
+        &self,
+        items: &S,
+        line_width: N,
+    ) -> Vec<Line<N>> {
+        if self.balance_then_justify {
+            return self.balance_then_justify(items, line_width);
+        }
+        if let Some(lines) =
+            self.greedy_shortcut(|first_fit| first_fit.layout_source(items, line_width))
+        {
+            return lines;
+        }
+        self.layout(items, line_width).run()
+    }
+
+    /// Lays out a paragraph at the narrowest width at which it takes as many lines as at
+    /// `line_width`, and then justifies those lines to `line_width`.
+    fn balance_then_justify<S: ItemSource<N> + ?Sized>(
+        &self,
+        items: &S,
+        line_width: N,
+    ) -> Vec<Line<N>> {
+        let mut lines = self.layout(items, line_width).run();
+        if lines.len() < 2 {
+            return lines;
+        }
+
+        // Search for the narrowest width at which the paragraph takes the same number of lines.
+        let (mut lo, mut hi) = (N::from(0), line_width);
+        for _ in 0..BISECTION_STEPS {
+            let mid = lo + (hi - lo) / N::from(2);
+            let narrow = self.layout(items, mid).run();
+            if narrow.len() == lines.len() {
+                hi = mid;
+                lines = narrow;
+            } else {
+                lo = mid;
+            }
+        }
+
+        // Justify every line, including the last, to the full line width.
+        let items = &JustifiedLastLine::new(items, true);
+        let mut start = 0;
+        for (l, line) in lines.iter_mut().enumerate() {
+            let indent = if l == 0 {
+                self.hanging_indent.0
+            } else {
+                self.hanging_indent.1
+            };
+            line.adjustment_ratio =
+                range_adjustment_ratio(items, start, line.break_at, line_width - indent);
+            start = line.break_at + 1;
+        }
+        lines
+    }
+
+    /// Lays out a paragraph whose items are described by the elements of a slice, e.g. the glyphs
+    /// of a shaping buffer, with the given line width. `to_item` returns the metrics of an element
+    /// and is called on demand whenever the algorithm consults the element, so no items are
+    /// materialized. The breaks of the returned lines index into `items`.
+    pub fn layout_by<T>(
+        &self,
+        items: &[T],
+        to_item: impl Fn(&T) -> ItemMetrics<N>,
+        line_width: N,
     ) -> Vec<Line<N>> {
-        let layout = KnuthPlassLayout {
-            bump: Bump::new(),
-            items,
+        self.layout_source(
+            &MappedItems {
+                elements: items,
+                to_item,
+            },
+            line_width,
+        )
+    }
+
+    /// Returns the greedy layout computed by `layout` if the greedy shortcut is enabled and every
+    /// line of the layout is within its tolerance.
+    fn greedy_shortcut(
+        &self,
+        layout: impl FnOnce(&FirstFit<N>) -> Vec<Line<N>>,
+    ) -> Option<Vec<Line<N>>> {
+        let tolerance = self.greedy_shortcut?;
+        if !self.pinned_breaks.is_empty()
+            || !self.hyphenation_words.is_empty()
+            || self.max_lines.is_some()
+        {
+            return None;
+        }
+        let mut first_fit = FirstFit::new()
+            .with_threshold(self.threshold)
+            .with_feasibility_epsilon(self.feasibility_epsilon)
+            .with_trim_leading_glue(self.trim_leading_glue)
+            .with_rigid_glue_fallback(self.rigid_glue_fallback)
+            .with_hanging_indent(self.hanging_indent.0, self.hanging_indent.1)
+            .with_trailing_empty_line(self.trailing_empty_line)
+            .with_glue_breaks(self.glue_breaks)
+            .with_ignore_zero_width_boxes(self.ignore_zero_width_boxes)
+            .with_justify_last_line(self.justify_last_line);
+        if let Some(factor) = self.max_glue_stretch {
+            first_fit = first_fit.with_max_glue_stretch(factor);
+        }
+        let lines = layout(&first_fit);
+        (!lines.is_empty() && lines.iter().all(|l| l.adjustment_ratio.abs() < tolerance))
+            .then_some(lines)
+    }
+
+    fn layout<'a, S: ItemSource<N> + ?Sized>(
+        &'a self,
+        items: &'a S,
+        line_width: N,
+    ) -> KnuthPlassLayout<'a, S, N> {
+        let capacity = match self.arena_capacity {
+            Some(bytes) => bytes / size_of::<Node<N>>(),
+            None => items.len(),
+        };
+        KnuthPlassLayout {
+            nodes: Vec::with_capacity(capacity),
+            items: JustifiedLastLine::new(items, self.justify_last_line),
             line_width,
+            region: None,
+            vertical_metrics: Vec::new(),
+            breakpoints: None,
             flagged_demerit: self.flagged_demerit,
             fitness_demerit: self.fitness_demerit,
             threshold: self.threshold,
+            feasibility_epsilon: self.feasibility_epsilon,
             looseness: self.looseness,
-            first_uniform_line: 0,
+            target_lines: None,
+            break_penalty: self.break_penalty.as_deref(),
+            line_relation_penalty: self.line_relation_penalty.as_deref(),
+            line_weight: self.line_weight.as_deref(),
+            fitness_fn: self.fitness_fn.as_deref(),
+            trim_leading_glue: self.trim_leading_glue,
+            rigid_glue_fallback: self.rigid_glue_fallback,
+            max_rag: self.max_rag,
+            short_line_penalty: self.short_line_penalty,
+            penultimate_penalty: self.penultimate_penalty,
+            hanging_indent: self.hanging_indent,
+            max_glue_stretch: self.max_glue_stretch,
+            pinned_breaks: &self.pinned_breaks,
+            hyphenation_words: &self.hyphenation_words,
+            hyphenation_min_edge: self.hyphenation_min_edge,
+            content_weights: &self.content_weights,
+            trailing_empty_line: self.trailing_empty_line,
+            glue_breaks: self.glue_breaks,
+            ignore_zero_width_boxes: self.ignore_zero_width_boxes,
+            first_content: first_content(items),
+            regular_breaks: Vec::new(),
+            no_single_box_last_line: self.no_single_box_last_line,
+            tiebreak_seed: self.tiebreak_seed,
+            max_lines: self.max_lines,
+            ellipsis: self.ellipsis,
+            objective: self.objective,
+            work_budget: self.work_budget,
+            node_ops: 0,
+            // A nonzero looseness or a pinned break requires layouts with different numbers of
+            // lines to be tracked separately, as otherwise only the best line count survives at
+            // each breakpoint.
+            first_uniform_line: if self.looseness != 0 || !self.pinned_breaks.is_empty() {
+                usize::MAX
+            } else {
+                0
+            },
             total_width: N::from(0),
-            total_stretch: N::from(0),
-            total_shrink: N::from(0),
+            total_stretch: GlueSum::default(),
+            total_shrink: GlueSum::default(),
+            run: 0..0,
+            run_totals: Vec::new(),
             active: None,
-        };
-        unsafe { layout.run() }
+        }
+    }
+}
+
+impl<N: Num> Default for KnuthPlass<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Box, Glue, Penalty, N: Num> ParagraphLayout<Box, Glue, Penalty, N> for KnuthPlass<N> {
+    fn layout_paragraph(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+    ) -> Vec<Line<N>> {
+        self.layout_source(items, line_width)
+    }
+
+    fn layout_prepared(
+        &self,
+        prepared: &PreparedParagraph<Box, Glue, Penalty, N>,
+        line_width: N,
+    ) -> Vec<Line<N>> {
+        if let Some(lines) =
+            self.greedy_shortcut(|first_fit| first_fit.layout_prepared(prepared, line_width))
+        {
+            return lines;
+        }
+        let mut layout = self.layout(prepared.items(), line_width);
+        layout.breakpoints = Some(prepared.breakpoints());
+        layout.run()
+    }
+
+    fn try_layout_paragraph(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+    ) -> Result<Vec<Line<N>>, LayoutError<N>> {
+        let mut layout = self.layout(items, line_width);
+        match layout.select() {
+            Some((b, _)) => Ok(layout.lines(b)),
+            None if layout.is_over_budget() => Err(LayoutError::BudgetExceeded),
+            None => {
+                let (first, rest) = self.hanging_indent;
+                Err(diagnose(items, (line_width - first, line_width - rest)))
+            }
+        }
     }
 }
 
-#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-enum Fitness {
+/// Statistics about a paragraph laid out by Knuth-Plass.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LayoutStats<N> {
+    /// The number of lines in the optimal layout, i.e. the layout with the fewest total demerits.
+    pub optimal_lines: usize,
+    /// The number of lines in the selected layout. This differs from the optimal number of lines
+    /// if a looseness is set.
+    pub selected_lines: usize,
+    /// The total demerits of the selected layout.
+    pub total_demerits: N,
+}
+
+/// A break chosen by Knuth-Plass, as reported by `KnuthPlass::layout_chain`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChosenBreak<N> {
+    /// The position of the break within the paragraph.
+    pub position: usize,
+    /// The number of the line that ends at this break, counting from 1.
+    pub line: usize,
+    /// The fitness class of the line that ends at this break.
+    pub fitness: Fitness,
+    /// The total demerits of the lines up to and including the line that ends at this break.
+    pub total_demerits: N,
+}
+
+/// The fitness class of a line, which describes how much its glue is adjusted. Adjacent lines
+/// whose classes are not adjacent incur the fitness demerit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Fitness {
+    /// A tight line, whose adjustment ratio is below -0.5.
     #[default]
     Zero = 0,
+    /// A decent line, whose adjustment ratio lies within [-0.5, 0.5].
     One = 1,
+    /// A loose line, whose adjustment ratio lies within (0.5, 1].
     Two = 2,
+    /// A very loose line, whose adjustment ratio exceeds 1.
     Three = 3,
 }
 
 impl Fitness {
+    /// Returns the fitness class of a line with the given adjustment ratio.
+    pub(crate) fn of<N: Num>(r: N) -> Fitness {
+        if r < N::rat(-1, 2) {
+            Fitness::Zero
+        } else if r <= N::rat(1, 2) {
+            Fitness::One
+        } else if r <= N::from(1) {
+            Fitness::Two
+        } else {
+            Fitness::Three
+        }
+    }
+
     fn distance(&self, other: &Fitness) -> usize {
         (*self as isize - *other as isize).unsigned_abs()
     }
 }
 
+/// The index of a node within the arena of break nodes.
+type NodeIndex = u32;
+
+/// Decides pseudo-randomly whether the predecessor at `a` should replace an equally good
+/// predecessor of the break at `b`, using the SplitMix64 finalizer to mix the seed and positions.
+fn tiebreak(seed: u64, a: usize, b: usize) -> bool {
+    let mut z = seed ^ (a as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ b as u64;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)) & 1 == 1
+}
+
 /// A Node tracks a feasible line break.
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 struct Node<N> {
     /// The position of the line break within the paragraph.
     position: usize,
@@ -113,30 +912,55 @@ struct Node<N> {
     /// 𝚺𝓌 after position per Knuth-Plass '81.
     total_width: N,
     /// 𝚺𝓎 after position per Knuth-Plass '81.
-    total_stretch: N,
+    total_stretch: GlueSum<N>,
     /// 𝚺𝓏 after position per Knuth-Plass '81.
-    total_shrink: N,
-    /// Minimum total demerits up to this break point.
+    total_shrink: GlueSum<N>,
+    /// Minimum total demerits up to this break point. Under `Objective::MinMax`, this is the
+    /// minimum of the greatest demerits of any line up to this break point.
     total_demerits: N,
-    /// Pointer to the best node for the preceeding break point.
-    previous: Option<*mut Node<N>>,
-    /// Pointer to the next active node.
-    link: Option<*mut Node<N>>,
+    /// The number of breaks at flagged penalties, e.g. hyphenations, up to this break point.
+    flagged_count: usize,
+    /// Total height of the lines up to this break point.
+    y: N,
+    /// Index of the best node for the preceeding break point.
+    previous: Option<NodeIndex>,
+    /// Index of the next active node.
+    link: Option<NodeIndex>,
+}
+
+impl<N: Num> Node<N> {
+    /// Returns true if this node is a better final break than other, i.e. it has fewer total
+    /// demerits, or equal total demerits and fewer breaks at flagged penalties, or equal total
+    /// demerits and flagged breaks and fewer lines.
+    fn is_better_endpoint(&self, other: &Node<N>) -> bool {
+        if self.total_demerits != other.total_demerits {
+            return self.total_demerits < other.total_demerits;
+        }
+        (self.flagged_count, self.line) < (other.flagged_count, other.line)
+    }
 }
 
 /// Holder for the state used by Knuth-Plass. Tracks various configuration parameters plus the
 /// running width, stretch, shrink, and active node.
 ///
-/// Active nodes are allocated using a bump allocator and deallocated en masse once the algorithm
-/// terminates.
-struct KnuthPlassLayout<'a, Box, Glue, Penalty, N> {
-    /// Allocator for break nodes.
-    bump: Bump,
+/// Nodes are allocated from an arena and referred to by index. Deactivated nodes are unlinked
+/// from the active list but remain in the arena, as they may still be referenced as the
+/// predecessors of active nodes. The arena is deallocated en masse once the algorithm terminates.
+struct KnuthPlassLayout<'a, S: ?Sized, N> {
+    /// Arena of break nodes.
+    nodes: Vec<Node<N>>,
 
     /// The paragraph's items.
-    items: &'a [Item<Box, Glue, Penalty, N>],
+    items: JustifiedLastLine<'a, S>,
     /// The line width parameter.
     line_width: N,
+    /// The region into which the paragraph is poured, if any.
+    region: Option<&'a dyn Region<N>>,
+    /// The height and depth of each item. Only populated if the paragraph is poured into a region.
+    vertical_metrics: Vec<(N, N)>,
+    /// The precomputed result of `is_legal_breakpoint` for each item, if the paragraph was
+    /// prepared.
+    breakpoints: Option<&'a [Breakpoint<N>]>,
 
     /// Demerit for flagged penalties. Referred to as 𝛂 in Knuth-Plass '81.
     flagged_demerit: N,
@@ -144,8 +968,63 @@ struct KnuthPlassLayout<'a, Box, Glue, Penalty, N> {
     fitness_demerit: N,
     /// Adjustment ratio threshold.  Referred to as 𝛒 in Knuth-Plass '81.
     threshold: N,
+    /// Tolerance for comparisons of adjustment ratios against -1 and the threshold.
+    feasibility_epsilon: N,
     /// Looseness parameter. Referred to as 𝗾 in Knuth-Plass '81.
     looseness: usize,
+    /// The exact number of lines to produce, if any. Overrides the looseness parameter.
+    target_lines: Option<usize>,
+    /// Additional cost for breaking at a given item.
+    break_penalty: Option<&'a dyn Fn(usize) -> N>,
+    /// Additional demerits for a line given the previous line's break and its own.
+    line_relation_penalty: Option<&'a dyn Fn(usize, usize) -> N>,
+    /// Weight of a line's demerits given its index.
+    line_weight: Option<&'a dyn Fn(usize) -> N>,
+    /// Classifies a line into a fitness class given its adjustment ratio, if not the default.
+    fitness_fn: Option<&'a dyn Fn(N) -> Fitness>,
+    /// Whether or not glue that precedes the first box on a line is ignored.
+    trim_leading_glue: bool,
+    /// Whether or not lines that are short but cannot stretch are accepted.
+    rigid_glue_fallback: bool,
+    /// Maximum leftover width of a line before it is penalized, if any.
+    max_rag: Option<N>,
+    /// Minimum number of words on a line and the penalty for lines with fewer, if any.
+    short_line_penalty: Option<(usize, N)>,
+    /// Penalty for a last line that follows a tight line, if any.
+    penultimate_penalty: Option<N>,
+    /// Indent of the first line and of every other line.
+    hanging_indent: (N, N),
+    /// How the demerits of each line combine into the total demerits of a layout.
+    objective: Objective,
+    /// Maximum factor by which any glue may be stretched, if any.
+    max_glue_stretch: Option<N>,
+    /// Item indices at which lines with the given indices must end.
+    pinned_breaks: &'a [(usize, usize)],
+    /// The item ranges of the words whose hyphenation is constrained, sorted by start.
+    hyphenation_words: &'a [(usize, usize)],
+    /// The number of boxes that must precede and follow a break within a constrained word.
+    hyphenation_min_edge: usize,
+    /// The amount of content in each item, if the short-line penalty measures content by weight.
+    content_weights: &'a [N],
+    /// Whether or not a trailing empty line after a final forced break is kept.
+    trailing_empty_line: bool,
+    /// Whether or not glue that follows a box is a legal breakpoint.
+    glue_breaks: bool,
+    /// Whether or not zero-width boxes are skipped when deciding whether glue follows a box.
+    ignore_zero_width_boxes: bool,
+    /// The index of the first box or tab, before which only the last item is a legal breakpoint.
+    first_content: usize,
+    /// Whether or not a line that starts at each node can end feasibly at a legal breakpoint that
+    /// is not a last-resort penalty, if known.
+    regular_breaks: Vec<Option<bool>>,
+    /// Whether or not the last line of the paragraph may not hold a single box.
+    no_single_box_last_line: bool,
+    /// The seed from which ties between equally good predecessors are broken, if any.
+    tiebreak_seed: Option<u64>,
+    /// The maximum number of lines to keep, if any.
+    max_lines: Option<usize>,
+    /// The width to leave for an ellipsis after the last line of a truncated paragraph, if any.
+    ellipsis: Option<N>,
     /// Index of the first line that begins a block of uniformly-long lines that extends to the end
     /// of the paragraph. 𝒿₀ in Knuth-Plass '81.
     first_uniform_line: usize,
@@ -153,75 +1032,277 @@ struct KnuthPlassLayout<'a, Box, Glue, Penalty, N> {
     /// Total width of all items in the paragraph up to the current item.
     total_width: N,
     /// Total stretch of all items in the paragraph up to the current item.
-    total_stretch: N,
+    total_stretch: GlueSum<N>,
     /// Total shrink of all items in the paragraph up to the current item.
-    total_shrink: N,
+    total_shrink: GlueSum<N>,
+    /// The most recent run of items that `total_after` skips over, i.e. glue, kerns, and penalties
+    /// up to the next box, tab, or forced break.
+    run: Range<usize>,
+    /// The total width, stretch, and shrink of the items from each index in `run` to its end, so
+    /// that a long run with many breakpoints is only scanned once.
+    run_totals: Vec<(N, GlueSum<N>, GlueSum<N>)>,
     /// Head of the linked list of active nodes.
-    active: Option<*mut Node<N>>,
+    active: Option<NodeIndex>,
+    /// Maximum number of node creations and deactivations, if any.
+    work_budget: Option<usize>,
+    /// Number of node creations and deactivations so far.
+    node_ops: usize,
 }
 
-impl<'a, Box, Glue, Penalty, N: Num> KnuthPlassLayout<'a, Box, Glue, Penalty, N> {
-    /// Creates a new node for a breakpoint. Currently just a wrapper for bump.alloc.
-    fn new_node(&mut self, node: Node<N>) -> *mut Node<N> {
-        self.bump.alloc(node)
+impl<S: ItemSource<N> + ?Sized, N: Num> KnuthPlassLayout<'_, S, N> {
+    /// Creates a new node for a breakpoint and returns its index.
+    fn new_node(&mut self, node: Node<N>) -> NodeIndex {
+        self.nodes.push(node);
+        (self.nodes.len() - 1) as NodeIndex
+    }
+
+    /// Returns the node with the given index.
+    fn node(&self, a: NodeIndex) -> &Node<N> {
+        &self.nodes[a as usize]
+    }
+
+    /// Returns the node with the given index.
+    fn node_mut(&mut self, a: NodeIndex) -> &mut Node<N> {
+        &mut self.nodes[a as usize]
+    }
+
+    /// Returns the indent of the given line. Lines are numbered from 1.
+    fn indent(&self, l: usize) -> N {
+        if l <= 1 {
+            self.hanging_indent.0
+        } else {
+            self.hanging_indent.1
+        }
+    }
+
+    /// Returns the width of the line that follows the break at a.
+    fn line_width_after(&self, a: &Node<N>) -> N {
+        let width = match self.region {
+            Some(region) => region.width_for(a.y),
+            None => self.line_width,
+        };
+        width - self.indent(a.line + 1)
     }
 
-    /// Placeholder method for determining the width of a given line. Currently just returns
-    /// line_width.
-    fn get_line_width(&self, _l: usize) -> N {
-        self.line_width
+    /// Returns the total height of the lines up to the break at b, given that the best preceding
+    /// break is a.
+    fn y_after(&self, a: &Node<N>, b: usize) -> N {
+        if self.region.is_none() {
+            return N::from(0);
+        }
+        let start = if a.line == 0 { 0 } else { a.position + 1 };
+        a.y + line_height(&self.vertical_metrics[start..b])
     }
 
     /// Returns the width, stretch, and shrink of the node at b and indicates whether or not b is a
     /// legal break.
-    fn is_legal_breakpoint(&self, b: usize) -> (N, N, N, bool) {
-        self.items[b].is_legal_breakpoint((b != 0).then(|| &self.items[b - 1]))
+    fn is_legal_breakpoint(&self, b: usize) -> Breakpoint<N> {
+        let (width, stretch, shrink, is_legal) = match self.breakpoints {
+            Some(breakpoints) => self.items.breakpoint(b, breakpoints[b]),
+            None => self
+                .items
+                .item(b)
+                .is_legal_breakpoint((b != 0).then(|| self.items.item(b - 1)).as_ref()),
+        };
+        let item = self.items.item(b);
+        let is_legal = match item {
+            Item::Glue { .. } if self.ignore_zero_width_boxes => {
+                follows_visible_box(&self.items, b)
+            }
+            _ => is_legal,
+        };
+        let is_legal = is_legal
+            && (self.glue_breaks || !matches!(item, Item::Glue { .. }))
+            && (b >= self.first_content || b + 1 == self.items.len());
+        (width, stretch, shrink, is_legal)
     }
 
     /// Calculates the line number and adjustment ratio for a line from the end of a to b.
     fn adjustment_ratio(&self, a: &Node<N>, b: usize) -> (usize, N) {
         let j = a.line + 1;
-        let r = self.items[b].adjustment_ratio(
+        let r = self.items.item(b).adjustment_ratio(
             self.total_width - a.total_width,
-            self.total_stretch - a.total_stretch,
-            self.total_shrink - a.total_shrink,
-            self.get_line_width(j),
+            (self.total_stretch - a.total_stretch).value(),
+            (self.total_shrink - a.total_shrink).value(),
+            self.line_width_after(a),
         );
-        (j, r)
+        (j, self.fallback(a, b, r))
+    }
+
+    /// Returns true if setting the line from a to b with the given adjustment ratio would stretch
+    /// any glue beyond the maximum glue stretch.
+    fn is_overstretched(&self, a: &Node<N>, b: usize, r: N) -> bool {
+        self.max_glue_stretch.is_some_and(|factor| {
+            let start = if a.line == 0 { 0 } else { a.position + 1 };
+            exceeds_max_glue_stretch(&self.items, start..b, r, factor)
+        })
     }
 
-    /// Deactivates the given node by removing it from the active list.
-    unsafe fn deactivate_node(&mut self, a: &mut Node<N>) {
-        if let Some(previous) = a.previous {
-            (*previous).link = a.link;
+    /// Returns true if a line from a to b is consistent with the pinned breaks, i.e. it does not
+    /// skip over a pinned item, and it ends at a pinned item only if it is that item's line.
+    fn satisfies_pins(&self, a: &Node<N>, b: usize) -> bool {
+        let start = if a.line == 0 { 0 } else { a.position + 1 };
+        self.pinned_breaks.iter().all(|&(position, line)| {
+            if position == b {
+                line == a.line
+            } else {
+                !(start..b).contains(&position)
+            }
+        })
+    }
+
+    /// Returns true if a line from a to b is consistent with the hyphenation constraints, i.e. if b
+    /// is a flagged penalty within a constrained word, enough of the word's boxes lie on either
+    /// side of it and the word was not already broken at a.
+    fn satisfies_hyphenation(&self, a: &Node<N>, b: usize) -> bool {
+        let item = self.items.item(b);
+        if item.is_forced() || !item.is_flagged() {
+            return true;
         }
-        if self.active == Some(a) {
-            self.active = a.link;
+        let w = self
+            .hyphenation_words
+            .partition_point(|&(start, _)| start <= b);
+        let Some(&(start, end)) = w.checked_sub(1).map(|w| &self.hyphenation_words[w]) else {
+            return true;
+        };
+        if b >= end {
+            return true;
         }
+        let boxes = |range: Range<usize>| {
+            range
+                .filter(|&i| matches!(self.items.item(i), Item::Box { .. }))
+                .count()
+        };
+        boxes(start..b) >= self.hyphenation_min_edge
+            && boxes(b + 1..end) >= self.hyphenation_min_edge
+            && !(a.line > 0 && (start..end).contains(&a.position))
     }
 
-    /// Calculates the demerits and fitness class for a line from a to b.
-    unsafe fn demerits_and_fitness(&self, r: N, a: &Node<N>, b: usize) -> (N, Fitness) {
-        let cost = self.items[b].penalty_cost();
-        let d = if cost >= N::from(0) {
-            (N::from(1) + N::from(100) * r.abs().powi(3) + cost).powi(2)
-        } else if cost != N::NEG_INFINITY {
-            (N::from(1) + N::from(100) * r.abs().powi(3)).powi(2) - cost.powi(2)
+    /// Applies the fallbacks for lines that cannot otherwise be set to the adjustment ratio of the
+    /// line from a to b: the trailing empty line and, if it is enabled, the rigid glue fallback.
+    fn fallback(&self, a: &Node<N>, b: usize, r: N) -> N {
+        // The empty line that follows a final forced break has no glue left to adjust, as the
+        // glue after the forced break is discarded, but it is set as-is.
+        if a.line > 0
+            && b + 1 == self.items.len()
+            && is_trailing_empty_line(&self.items, Some(a.position), b)
+        {
+            return N::from(0);
+        }
+        if self.rigid_glue_fallback {
+            rigid_glue_fallback(r)
         } else {
-            (N::from(1) + N::from(100) * r.abs().powi(3)).powi(2)
-        };
-        let d = d + self.flagged_demerit
-            * self.items[b].penalty_flag()
-            * self.items[a.position].penalty_flag();
+            r
+        }
+    }
 
-        let c = if r < N::rat(-1, 2) {
-            Fitness::Zero
-        } else if r <= N::rat(1, 2) {
-            Fitness::One
-        } else if r <= N::from(1) {
-            Fitness::Two
+    /// Deactivates the given node by removing it from the active list. prev_a is the node that
+    /// precedes a in the active list, or None if a is the head of the list.
+    fn deactivate_node(&mut self, prev_a: Option<NodeIndex>, a: NodeIndex) {
+        let link = self.node(a).link;
+        match prev_a {
+            None => self.active = link,
+            Some(prev_a) => self.node_mut(prev_a).link = link,
+        };
+    }
+
+    /// Counts the words on the line from a to b, where a word is a run of boxes that is not
+    /// interrupted by glue. Stops counting once `limit` words have been found.
+    fn word_count(&self, a: &Node<N>, b: usize, limit: usize) -> usize {
+        let start = if a.line == 0 { 0 } else { a.position + 1 };
+        let mut words = 0;
+        let mut in_word = false;
+        for i in start..b {
+            match self.items.item(i) {
+                Item::Box { .. } => {
+                    if !in_word {
+                        words += 1;
+                        if words >= limit {
+                            break;
+                        }
+                    }
+                    in_word = true;
+                }
+                Item::Glue { .. } | Item::Tab { .. } => in_word = false,
+                Item::Penalty { .. } | Item::Kern { .. } | Item::BreakGlue { .. } => {}
+            }
+        }
+        words
+    }
+
+    /// Returns true if the line from a to b contains less than `min` content. Content is measured
+    /// by the total weight of the line's boxes if content weights are set, or by its number of
+    /// words otherwise.
+    fn is_short_line(&self, a: &Node<N>, b: usize, min: usize) -> bool {
+        if self.content_weights.is_empty() {
+            return self.word_count(a, b, min) < min;
+        }
+
+        let start = if a.line == 0 { 0 } else { a.position + 1 };
+        let mut content = N::from(0);
+        for i in start..b {
+            if let Item::Box { .. } = self.items.item(i) {
+                content += self.content_weights.get(i).copied().unwrap_or(N::from(1));
+            }
+        }
+        content < N::from_usize(min)
+    }
+
+    /// Calculates the demerits and fitness class for a line from a to b.
+    fn demerits_and_fitness(&self, r: N, a: &Node<N>, b: usize) -> (N, Fitness) {
+        let mut cost = self.items.item(b).penalty_cost();
+        if let Some(break_penalty) = self.break_penalty {
+            if cost != N::NEG_INFINITY {
+                cost += break_penalty(b);
+            }
+        }
+        let d = if cost >= N::from(0) {
+            (N::from(1) + badness(r) + cost).powi(2)
+        } else if cost != N::NEG_INFINITY {
+            (N::from(1) + badness(r)).powi(2) - cost.powi(2)
         } else {
-            Fitness::Three
+            (N::from(1) + badness(r)).powi(2)
+        };
+        let d = match self.max_rag {
+            Some(depth) if !self.items.item(b).is_forced() => {
+                let width = self.total_width - a.total_width + self.items.item(b).break_metrics().0;
+                let excess = self.line_width_after(a) - width - depth;
+                if excess > N::from(0) {
+                    d + (N::from(100) * excess.powi(3)).powi(2)
+                } else {
+                    d
+                }
+            }
+            _ => d,
+        };
+        let d = match self.short_line_penalty {
+            Some((min_words, penalty)) if self.is_short_line(a, b, min_words) => d + penalty,
+            _ => d,
+        };
+        let d = match self.penultimate_penalty {
+            Some(penalty)
+                if a.line > 0 && a.fitness == Fitness::Zero && b + 1 == self.items.len() =>
+            {
+                d + penalty
+            }
+            _ => d,
+        };
+        let d = match self.line_relation_penalty {
+            Some(penalty) if a.line > 0 => d + penalty(a.position, b),
+            _ => d,
+        };
+        let d = if PenaltyFlags::conflict(
+            self.items.item(a.position).penalty_flags(),
+            self.items.item(b).penalty_flags(),
+        ) {
+            d + self.flagged_demerit
+        } else {
+            d
+        };
+
+        let c = match self.fitness_fn {
+            Some(fitness_fn) => fitness_fn(r),
+            None => Fitness::of(r),
         };
 
         let d = if c.distance(&a.fitness) > 1 {
@@ -229,70 +1310,131 @@ impl<'a, Box, Glue, Penalty, N: Num> KnuthPlassLayout<'a, Box, Glue, Penalty, N>
         } else {
             d
         };
-        (d + a.total_demerits, c)
+        let d = match self.line_weight {
+            Some(line_weight) => d * line_weight(a.line),
+            None => d,
+        };
+        let total = match self.objective {
+            Objective::MinSum => d + a.total_demerits,
+            Objective::MinMax if d > a.total_demerits => d,
+            Objective::MinMax => a.total_demerits,
+        };
+        (total, c)
     }
 
     /// Calculates 𝚺𝓌 after b, 𝚺𝓎 after b, and 𝚺𝓏 after b per Knuth-Plass '81.
-    fn total_after(&self, b: usize) -> (N, N, N) {
+    fn total_after(&mut self, b: usize) -> (N, GlueSum<N>, GlueSum<N>) {
+        if !self.run.contains(&b) {
+            self.compute_run(b);
+        }
         let (mut total_width, mut total_stretch, mut total_shrink) =
             (self.total_width, self.total_stretch, self.total_shrink);
-        for i in b..self.items.len() {
-            match self.items[i] {
-                Item::Box { .. } => break,
-                Item::Glue {
-                    width,
-                    stretch,
-                    shrink,
-                    ..
-                } => {
-                    total_width += width;
-                    total_stretch += stretch;
-                    total_shrink += shrink;
-                }
-                Item::Penalty { cost, .. } => {
-                    if cost == N::NEG_INFINITY && i > b {
-                        break;
-                    }
+        let (width, stretch, shrink) = self.run_totals[b - self.run.start];
+        total_width += width;
+        total_stretch += stretch;
+        total_shrink += shrink;
+        (total_width, total_stretch, total_shrink)
+    }
+
+    /// Finds the run of items that starts at b and computes the totals from each of its items to
+    /// its end. Any later breakpoint within the run is not a forced break, as a forced break ends
+    /// the run, so its totals are the same as if the run had started there.
+    fn compute_run(&mut self, b: usize) {
+        let n = self.items.len();
+        let end = (b..n)
+            .find(|&i| match self.items.item(i) {
+                Item::Box { .. } | Item::Tab { .. } => true,
+                item => i > b && item.is_forced(),
+            })
+            .unwrap_or(n);
+
+        let mut totals = (N::from(0), GlueSum::default(), GlueSum::default());
+        self.run_totals.clear();
+        self.run_totals.resize(end.max(b + 1) - b, totals);
+        for i in (b..end).rev() {
+            match self.items.item(i) {
+                item @ Item::Glue { width, .. } => {
+                    let (y, z) = item.glue_flex();
+                    totals.0 += width;
+                    totals.1 += y;
+                    totals.2 += z;
                 }
-            };
+                Item::Kern { width } => totals.0 += width,
+                _ => {}
+            }
+            self.run_totals[i - b] = totals;
         }
-        (total_width, total_stretch, total_shrink)
+        self.run = b..end.max(b + 1);
     }
 
     /// Main loop for processing a legal breakpoint. Returns false if no layout is possible.
-    unsafe fn layout_breakpoint(&mut self, b: usize) -> bool {
+    fn layout_breakpoint(&mut self, b: usize) -> bool {
         let mut a = self.active;
         let mut prev_a = None;
         while a.is_some() {
-            let mut class_a: [Option<*mut Node<N>>; 4] = [None, None, None, None];
+            let mut class_a: [Option<NodeIndex>; 4] = [None, None, None, None];
             let mut class_demerits: [N; 4] = [N::INFINITY, N::INFINITY, N::INFINITY, N::INFINITY];
+            let mut class_cost: [N; 4] = [N::from(0); 4];
+            let mut class_flagged: [usize; 4] = [0; 4];
             let mut min_demerits: N = N::INFINITY;
             loop {
-                let unwrapped_a = &mut *a.unwrap();
-                let next_a = unwrapped_a.link;
+                let unwrapped_a = a.unwrap();
+                let node_a = *self.node(unwrapped_a);
 
-                let (j, r) = self.adjustment_ratio(unwrapped_a, b);
-                if r < N::from(-1) || self.items[b].is_mandatory_break() {
-                    self.deactivate_node(unwrapped_a);
+                let (j, r) = self.adjustment_ratio(&node_a, b);
+                let min_ratio = N::from(-1) - self.feasibility_epsilon;
+                let is_last_resort = self
+                    .items
+                    .item(b)
+                    .penalty_flags()
+                    .contains(PenaltyFlags::LAST_RESORT);
+                if r < min_ratio || self.items.item(b).is_forced() {
+                    self.deactivate_node(prev_a, unwrapped_a);
+                    self.node_ops += 1;
                 } else {
                     prev_a = a;
                 }
-                if N::from(-1) <= r && r <= self.threshold {
-                    let (demerits, fitness) = self.demerits_and_fitness(r, unwrapped_a, b);
-                    if demerits < class_demerits[fitness as usize] {
-                        class_demerits[fitness as usize] = demerits;
-                        class_a[fitness as usize] = a;
+                if min_ratio <= r
+                    && r <= self.threshold + self.feasibility_epsilon
+                    && !self.is_overstretched(&node_a, b, r)
+                    && self.satisfies_pins(&node_a, b)
+                    && self.satisfies_hyphenation(&node_a, b)
+                    && !self.is_forbidden_last_line(&node_a, b)
+                    && !(is_last_resort && self.has_regular_break(unwrapped_a, b))
+                {
+                    let (demerits, fitness) = self.demerits_and_fitness(r, &node_a, b);
+                    // Break ties in favor of the predecessor whose path has fewer flagged breaks,
+                    // and then in favor of the one that ends at the more desirable penalty.
+                    let cost = if node_a.line == 0 {
+                        N::from(0)
+                    } else {
+                        self.items.item(node_a.position).penalty_cost()
+                    };
+                    let c = fitness as usize;
+                    let wins_tie = match node_a.flagged_count.cmp(&class_flagged[c]) {
+                        Ordering::Less => true,
+                        Ordering::Greater => false,
+                        Ordering::Equal => match self.tiebreak_seed {
+                            Some(seed) => tiebreak(seed, node_a.position, b),
+                            None => cost < class_cost[c],
+                        },
+                    };
+                    if demerits < class_demerits[c] || demerits == class_demerits[c] && wins_tie {
+                        class_demerits[c] = demerits;
+                        class_cost[c] = cost;
+                        class_flagged[c] = node_a.flagged_count;
+                        class_a[c] = a;
                         if demerits < min_demerits {
                             min_demerits = demerits;
                         }
                     }
                 }
 
-                a = next_a;
+                a = node_a.link;
                 match a {
                     None => break,
                     Some(a) => {
-                        if (*a).line >= j && j < self.first_uniform_line {
+                        if self.node(a).line >= j && j < self.first_uniform_line {
                             break;
                         }
                     }
@@ -305,123 +1447,1562 @@ impl<'a, Box, Glue, Penalty, N: Num> KnuthPlassLayout<'a, Box, Glue, Penalty, N>
                     let demerits = class_demerits[c as usize];
                     if demerits <= min_demerits {
                         let class_a = class_a[c as usize].unwrap();
+                        let node_a = self.node(class_a);
+                        let at = self.items.item(b);
+                        let is_flagged = !at.is_forced() && at.is_flagged();
                         let s = self.new_node(Node {
                             position: b,
-                            line: (*class_a).line + 1,
+                            line: node_a.line + 1,
                             fitness: c,
                             total_width,
                             total_stretch,
                             total_shrink,
                             total_demerits: demerits,
+                            flagged_count: node_a.flagged_count + usize::from(is_flagged),
+                            y: self.y_after(node_a, b),
                             previous: Some(class_a),
                             link: a,
                         });
+                        self.node_ops += 1;
                         match prev_a {
                             None => self.active = Some(s),
-                            Some(prev_a) => (*prev_a).link = Some(s),
+                            Some(prev_a) => self.node_mut(prev_a).link = Some(s),
                         };
                         prev_a = Some(s);
                     }
                 }
             }
+            if self.is_over_budget() {
+                return false;
+            }
         }
         self.active.is_some()
     }
 
+    /// Returns true if the line that starts at node a can end feasibly at a legal breakpoint that
+    /// is not a last-resort penalty. b is the current item, so the running totals are those of
+    /// the items that precede it.
+    fn has_regular_break(&mut self, a: NodeIndex, b: usize) -> bool {
+        if let Some(&Some(known)) = self.regular_breaks.get(a as usize) {
+            return known;
+        }
+
+        // Measure the line from its start by backing the items between its start and b out of the
+        // running totals.
+        let node_a = *self.node(a);
+        let start = if node_a.line == 0 {
+            0
+        } else {
+            node_a.position + 1
+        };
+        let (mut width, mut stretch, mut shrink) = (
+            self.total_width - node_a.total_width,
+            self.total_stretch - node_a.total_stretch,
+            self.total_shrink - node_a.total_shrink,
+        );
+        for i in start..b {
+            let (w, y, z, _) = self.is_legal_breakpoint(i);
+            width -= w;
+            stretch -= y;
+            shrink -= z;
+        }
+
+        let line_width = self.line_width_after(&node_a);
+        let mut found = false;
+        for i in start..self.items.len() {
+            let (w, y, z, is_legal) = self.is_legal_breakpoint(i);
+            let item = self.items.item(i);
+            if is_legal && !item.penalty_flags().contains(PenaltyFlags::LAST_RESORT) {
+                let r = item.adjustment_ratio(width, stretch.value(), shrink.value(), line_width);
+                let r = self.fallback(&node_a, i, r);
+                if r < N::from(-1) - self.feasibility_epsilon {
+                    break;
+                }
+                // A line that cannot stretch has infinite badness, so it is never chosen.
+                if r <= self.threshold + self.feasibility_epsilon && r < N::INFINITY {
+                    found = true;
+                    break;
+                }
+                if item.is_forced() {
+                    break;
+                }
+            }
+            width += w;
+            stretch += y;
+            shrink += z;
+        }
+
+        if self.regular_breaks.len() <= a as usize {
+            self.regular_breaks.resize(a as usize + 1, None);
+        }
+        self.regular_breaks[a as usize] = Some(found);
+        found
+    }
+
+    /// Returns true if the line from `a` to `b` is the last line of the paragraph, it holds a
+    /// single box, and such a last line is disallowed.
+    fn is_forbidden_last_line(&self, a: &Node<N>, b: usize) -> bool {
+        if !self.no_single_box_last_line || b + 1 != self.items.len() {
+            return false;
+        }
+        let start = if a.line == 0 { 0 } else { a.position + 1 };
+        (start..b)
+            .filter(|&i| matches!(self.items.item(i), Item::Box { .. }))
+            .count()
+            == 1
+    }
+
+    /// Returns true if the algorithm has exceeded its work budget.
+    fn is_over_budget(&self) -> bool {
+        self.work_budget.is_some_and(|max| self.node_ops > max)
+    }
+
+    /// Returns the node in the active list starting at `active` with the fewest demerits,
+    /// preferring fewer flagged breaks and then fewer lines on ties.
+    fn best_endpoint(&self, active: NodeIndex) -> NodeIndex {
+        let mut b = active;
+        let mut a = Some(active);
+        while let Some(n) = a {
+            if self.node(n).is_better_endpoint(self.node(b)) {
+                b = n;
+            }
+            a = self.node(n).link;
+        }
+        b
+    }
+
+    /// Returns the node in the active list starting at `active` whose number of lines is closest
+    /// to the looseness plus the number of lines of the best node `b`.
+    fn loosen(&self, active: NodeIndex, mut b: NodeIndex) -> NodeIndex {
+        let k = self.node(b).line;
+
+        let mut a = Some(active);
+        let mut s = 0;
+        while let Some(n) = a {
+            let node = self.node(n);
+            // Nodes with fewer lines than the optimum cannot satisfy a positive looseness.
+            if let Some(delta) = node.line.checked_sub(k) {
+                if self.looseness <= delta && delta < s || s < delta && delta <= self.looseness {
+                    s = delta;
+                    b = n;
+                } else if delta == s && node.total_demerits < self.node(b).total_demerits {
+                    b = n;
+                }
+            }
+            a = node.link;
+        }
+        b
+    }
+
     /// Driver for Knuth-Plass paragraph layout.
-    unsafe fn run(mut self) -> Vec<Line<N>> {
+    fn run(self) -> Vec<Line<N>> {
+        self.run_with_stats().0
+    }
+
+    /// Runs the algorithm over the paragraph's items and returns the node that ends the chosen
+    /// layout alongside statistics about it, or None if the paragraph could not be laid out.
+    fn select(&mut self) -> Option<(NodeIndex, LayoutStats<N>)> {
+        if let Some(selected) = self.select_breaks() {
+            return Some(selected);
+        }
+
+        // A last line that holds a single box is only allowed if there is no other layout.
+        if !self.no_single_box_last_line || self.is_over_budget() {
+            return None;
+        }
+        self.no_single_box_last_line = false;
+        self.reset();
+        self.select_breaks()
+    }
+
+    /// Discards the nodes and running totals of a previous attempt to choose breaks.
+    fn reset(&mut self) {
+        self.nodes.clear();
+        self.regular_breaks.clear();
+        self.total_width = N::from(0);
+        self.total_stretch = GlueSum::default();
+        self.total_shrink = GlueSum::default();
+        self.run = 0..0;
+    }
+
+    /// Chooses the breaks for the paragraph given the current set of legal breakpoints.
+    fn select_breaks(&mut self) -> Option<(NodeIndex, LayoutStats<N>)> {
         // Initialize the list of active nodes.
         self.active = Some(self.new_node(Default::default()));
 
+        // Discount any glue at the start of the paragraph if leading glue is trimmed.
+        if self.trim_leading_glue {
+            let (width, stretch, shrink) = leading_glue(&self.items, 0..self.items.len());
+            self.total_width -= width;
+            self.total_stretch -= stretch;
+            self.total_shrink -= shrink;
+        }
+
         // Loop over the items to lay out and calculate the set of legal breakpoints.
+        let mut extra_lines = 0;
         for b in 0..self.items.len() {
             let (width, stretch, shrink, is_legal) = self.is_legal_breakpoint(b);
             if is_legal && !self.layout_breakpoint(b) {
-                return Vec::new();
+                return None;
             }
+
+            // A forced break deactivates every node that precedes it, so the looseness must be
+            // applied to each forced segment independently. Choose the loosened endpoint for this
+            // segment and continue from it alone.
+            if is_legal
+                && self.looseness != 0
+                && self.target_lines.is_none()
+                && self.items.item(b).is_forced()
+                && b + 1 < self.items.len()
+            {
+                if let Some(active) = self.active {
+                    let best = self.best_endpoint(active);
+                    let chosen = self.loosen(active, best);
+                    extra_lines += self.node(chosen).line - self.node(best).line;
+                    self.node_mut(chosen).link = None;
+                    self.active = Some(chosen);
+                }
+            }
+
             self.total_width += width;
             self.total_stretch += stretch;
             self.total_shrink += shrink;
         }
-        if self.active.is_none() {
-            return Vec::new();
-        }
+        let active = self.active?;
 
-        // Choose the active node with the fewest demerits.
-        let mut a = self.active;
-        let mut b = &*a.unwrap();
-        loop {
-            match a {
-                None => break,
-                Some(n) => {
-                    let n = &*n;
-                    if n.total_demerits < b.total_demerits {
-                        b = n;
-                    }
-                    a = n.link;
-                }
-            };
-        }
+        // Choose the active node with the fewest demerits, preferring fewer flagged breaks and
+        // then fewer lines on ties.
+        let mut b = self.best_endpoint(active);
+        let mut optimal_lines = self.node(b).line - extra_lines;
 
         // Choose the appropriate active node.
-        if self.looseness != 0 {
-            let k = b.line;
+        if let Some(k) = self.target_lines {
+            let mut best = None;
+            let mut a = Some(active);
+            while let Some(n) = a {
+                let node = self.node(n);
+                if node.line == k
+                    && best.is_none_or(|best| node.total_demerits < self.node(best).total_demerits)
+                {
+                    best = Some(n);
+                }
+                a = node.link;
+            }
+            b = best?;
+        } else if self.looseness != 0 {
+            b = self.loosen(active, b);
+        }
 
-            let mut a = &*self.active.unwrap();
-            let mut b = a;
-            let mut s = 0;
-            loop {
-                let delta = a.line - k;
-                if self.looseness <= delta && delta < s || s < delta && delta <= self.looseness {
-                    s = delta;
-                    b = a;
-                } else if delta == s && a.total_demerits < b.total_demerits {
-                    b = a;
+        // Drop the empty line that follows a final forced break by ending the layout at the
+        // forced break instead.
+        if !self.trailing_empty_line {
+            let node = self.node(b);
+            if let Some(p) = node.previous {
+                let prev = self.node(p);
+                if prev.line > 0
+                    && is_trailing_empty_line(&self.items, Some(prev.position), node.position)
+                {
+                    b = p;
+                    optimal_lines -= 1;
                 }
-                match a.link {
-                    None => break,
-                    Some(link) => a = &*link,
-                };
             }
+        }
+
+        let node = self.node(b);
+        let stats = LayoutStats {
+            optimal_lines,
+            selected_lines: node.line,
+            total_demerits: node.total_demerits,
         };
+        Some((b, stats))
+    }
+
+    /// Driver for Knuth-Plass paragraph layout that also reports statistics about the chosen
+    /// layout.
+    fn run_with_stats(mut self) -> (Vec<Line<N>>, LayoutStats<N>) {
+        match self.select() {
+            Some((b, stats)) => (self.lines(b), stats),
+            None => (Vec::new(), LayoutStats::default()),
+        }
+    }
 
+    /// Computes the lines of the layout that ends at the given node.
+    fn lines(&self, b: NodeIndex) -> Vec<Line<N>> {
         // Walk backwards from the chosen node to the start of the paragraph to compute the chosen
         // line breaks.
+        let mut b = *self.node(b);
+        let truncated = self.max_lines.is_some_and(|max_lines| b.line > max_lines);
+        if truncated {
+            while Some(b.line) != self.max_lines {
+                b = *self.node(b.previous.unwrap());
+            }
+        }
         let mut lines = vec![Default::default(); b.line];
         let mut j = b.line;
         while j > 0 {
-            let prev = &*b.previous.unwrap();
+            let prev = *self.node(b.previous.unwrap());
             let prev_pos = if j == 1 { 0 } else { prev.position + 1 };
 
-            let items = &self.items[prev_pos..b.position];
-            let (width, stretch, shrink) = items
-                .iter()
-                .map(|item| match item {
-                    Item::Box { width, .. } => (*width, N::from(0), N::from(0)),
-                    Item::Glue {
-                        width,
-                        stretch,
-                        shrink,
-                        ..
-                    } => (*width, *stretch, *shrink),
-                    Item::Penalty { width, .. } => (*width, N::from(0), N::from(0)),
-                })
-                .reduce(|acc, n| (acc.0 + n.0, acc.1 + n.1, acc.2 + n.2))
-                .unwrap();
+            let (mut width, mut stretch, mut shrink) =
+                sum_metrics(&self.items, prev_pos..b.position);
+            if self.trim_leading_glue {
+                let (trim_width, trim_stretch, trim_shrink) =
+                    leading_glue(&self.items, prev_pos..b.position);
+                width -= trim_width;
+                stretch -= trim_stretch;
+                shrink -= trim_shrink;
+            }
 
-            let at = &self.items[b.position];
-            let line_width = self.get_line_width(j);
-            let adjustment_ratio = at.adjustment_ratio(width, stretch, shrink, line_width);
+            let at = self.items.item(b.position);
+            let line_width = self.line_width_after(&prev);
+            let adjustment_ratio = self.fallback(
+                &prev,
+                b.position,
+                at.adjustment_ratio(width, stretch.value(), shrink.value(), line_width),
+            );
 
             lines[j - 1] = Line {
                 break_at: b.position,
                 adjustment_ratio,
+                truncated: false,
             };
+            if truncated && j == lines.len() {
+                let ellipsis = self.ellipsis.and_then(|width| {
+                    truncated_line(
+                        &self.items,
+                        prev_pos,
+                        b.position,
+                        line_width - width,
+                        self.trim_leading_glue,
+                    )
+                });
+                lines[j - 1] = ellipsis.unwrap_or(Line {
+                    truncated: true,
+                    ..lines[j - 1]
+                });
+            }
 
             b = prev;
             j -= 1;
         }
-
         lines
     }
+
+    /// Driver for Knuth-Plass paragraph layout that returns the chain of nodes that ends the
+    /// chosen layout rather than its lines.
+    fn run_chain(mut self) -> Vec<ChosenBreak<N>> {
+        let Some((b, _)) = self.select() else {
+            return Vec::new();
+        };
+
+        let mut chain = Vec::new();
+        let mut b = *self.node(b);
+        while b.line > 0 {
+            chain.push(ChosenBreak {
+                position: b.position,
+                line: b.line,
+                fitness: b.fitness,
+                total_demerits: b.total_demerits,
+            });
+            b = *self.node(b.previous.unwrap());
+        }
+        chain.reverse();
+        chain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixed_breaks, monospace, rigid_monospace, split, tenths};
+    use crate::{Fixed, GlueOrder, ItemKind};
+
+    #[test]
+    fn break_penalty_fn() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = split(text, &knuth_plass.layout_paragraph(&items, 20.0));
+        assert_eq!(
+            lines,
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+
+        // Discourage breaking after "fox".
+        let knuth_plass =
+            knuth_plass.with_break_penalty_fn(|b| if b == 19 { 10000.0 } else { 0.0 });
+        let lines = split(text, &knuth_plass.layout_paragraph(&items, 20.0));
+        assert_eq!(
+            lines,
+            ["The quick brown", "fox jumps over the", "lazy dog."]
+        );
+    }
+
+    #[test]
+    fn line_relation_penalty() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = split(text, &knuth_plass.layout_paragraph(&items, 20.0));
+        assert_eq!(
+            lines,
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+
+        // Discourage lines that break exactly one line width after the previous line's break,
+        // which would align the previous line's spaces with this line's.
+        let knuth_plass =
+            knuth_plass.with_line_relation_penalty(
+                |prev, this| if this - prev == 20 { 1.0e7 } else { 0.0 },
+            );
+        let lines = split(text, &knuth_plass.layout_paragraph(&items, 20.0));
+        assert_eq!(
+            lines,
+            ["The quick brown", "fox jumps over the", "lazy dog."]
+        );
+    }
+
+    #[test]
+    fn penalty_cost_tie_breaker() {
+        // A full line that may break at either of two adjacent penalties, followed by a short last
+        // line. The line relation penalty makes the demerits of both layouts equal, so the break at
+        // the cheaper penalty is chosen.
+        let items: Vec<Item> = alloc::vec![
+            Item::Box {
+                width: 5.0,
+                data: (),
+            },
+            Item::glue(1.0, 1.0, 0.0, ()),
+            Item::Box {
+                width: 4.0,
+                data: (),
+            },
+            Item::penalty(50.0, ()),
+            Item::penalty(0.0, ()),
+            Item::Box {
+                width: 5.0,
+                data: (),
+            },
+            Item::glue(0.0, f32::INFINITY, 0.0, ()),
+            Item::forced_break(()),
+        ];
+        let knuth_plass =
+            KnuthPlass::new()
+                .with_line_relation_penalty(|prev, _| if prev == 4 { 2600.0 } else { 0.0 });
+        let (lines, stats) = knuth_plass.layout_paragraph_with_stats(&items, 10.0);
+        assert_eq!(stats.total_demerits, 2602.0);
+        assert_eq!(lines.iter().map(|l| l.break_at).collect::<Vec<_>>(), [4, 7]);
+    }
+
+    #[test]
+    fn with_params() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let params = KnuthPlassParams {
+            threshold: f32::INFINITY,
+            looseness: 1,
+            ..Default::default()
+        };
+        let knuth_plass = KnuthPlass::with_params(params);
+        let expected = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .with_looseness(1);
+        assert_eq!(
+            split(text, &knuth_plass.layout_paragraph(&items, 20.0)),
+            split(text, &expected.layout_paragraph(&items, 20.0))
+        );
+        assert_eq!(knuth_plass.layout_paragraph(&items, 20.0).len(), 4);
+    }
+
+    #[test]
+    fn line_weight_fn() {
+        let text = "abcd a abc abcdef ab a";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = knuth_plass.layout_paragraph(&items, 10.0);
+        assert_eq!(split(text, &lines), ["abcd a abc", "abcdef ab", "a"]);
+        assert_eq!(crate::line_ratios(&lines)[..2], [0.0, 1.0]);
+
+        // Weighting the lines after the first more heavily moves the loose line to the top.
+        let knuth_plass =
+            knuth_plass.with_line_weight_fn(|line| if line == 0 { 1.0 } else { 1.0e4 });
+        let lines = knuth_plass.layout_paragraph(&items, 10.0);
+        assert_eq!(split(text, &lines), ["abcd a", "abc abcdef", "ab a"]);
+        assert_eq!(crate::line_ratios(&lines)[..2], [4.0, 0.0]);
+    }
+
+    #[test]
+    fn fitness_fn() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+
+        // With a single fitness class, no line incurs the fitness demerit.
+        let knuth_plass = KnuthPlass::new().with_fitness_fn(|_| Fitness::One);
+        let (lines, stats) = knuth_plass.layout_paragraph_with_stats(&items, 20.0);
+        let demerits: f32 = crate::line_badness(&lines)
+            .iter()
+            .map(|b| (1.0 + b) * (1.0 + b))
+            .sum();
+        assert!((stats.total_demerits - demerits).abs() < 1e-3);
+
+        // Classifying the nearly-unstretched last line as very loose sets it apart from the lines
+        // before it, which are classified as tight.
+        let knuth_plass = KnuthPlass::new().with_fitness_fn(|r: f32| {
+            if r < 0.01 {
+                Fitness::Three
+            } else {
+                Fitness::Zero
+            }
+        });
+        let (loose, stats) = knuth_plass.layout_paragraph_with_stats(&items, 20.0);
+        assert_eq!(split(text, &loose), split(text, &lines));
+        assert!((stats.total_demerits - (demerits + 100.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn trim_leading_glue() {
+        let text = "  The quick brown fox jumps over the lazy dog.";
+        let mut items = monospace(text);
+        items[0] = Item::glue(1.0, 1.0, 0.0, ());
+
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let breaks = knuth_plass.layout_paragraph(&items, 20.0);
+        assert_eq!(
+            split(text, &breaks),
+            ["  The quick brown", "fox jumps over the", "lazy dog."]
+        );
+
+        let knuth_plass = knuth_plass.with_trim_leading_glue(true);
+        let breaks = knuth_plass.layout_paragraph(&items, 20.0);
+        assert_eq!(
+            split(text, &breaks),
+            ["  The quick brown fox", "jumps over the lazy", "dog."]
+        );
+        assert_eq!(breaks[0].adjustment_ratio, 1.0 / 3.0);
+    }
+
+    #[test]
+    fn equal_demerit_endpoints_prefer_fewer_lines() {
+        let shorter = Node {
+            line: 3,
+            total_demerits: 300.0,
+            ..Default::default()
+        };
+        let longer = Node {
+            line: 4,
+            total_demerits: 300.0,
+            ..Default::default()
+        };
+        assert!(shorter.is_better_endpoint(&longer));
+        assert!(!longer.is_better_endpoint(&shorter));
+
+        let cheaper = Node {
+            line: 4,
+            total_demerits: 299.0,
+            ..Default::default()
+        };
+        assert!(cheaper.is_better_endpoint(&shorter));
+    }
+
+    #[test]
+    fn equal_demerit_layouts_prefer_fewer_lines() {
+        let text = "aaaaaa bb cc";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .with_rigid_glue_fallback(true)
+            .with_justify_last_line(true);
+        let lines = knuth_plass.layout_paragraph(&items, 6.0);
+        assert_eq!(split(text, &lines), ["aaaaaa", "bb", "cc"]);
+
+        // Penalize "bb" alone on a line so that the three-line layout, whose lines are all
+        // perfect, is exactly as costly as the two-line layout, whose last line is loose.
+        let knuth_plass =
+            knuth_plass
+                .with_line_relation_penalty(|a, b| if (a, b) == (6, 9) { 10199.0 } else { 0.0 });
+        let lines = knuth_plass.layout_paragraph(&items, 6.0);
+        assert_eq!(split(text, &lines), ["aaaaaa", "bb cc"]);
+        let (_, stats) = knuth_plass.layout_paragraph_with_stats(&items, 6.0);
+        assert_eq!(stats.total_demerits, 10202.0);
+    }
+
+    #[test]
+    fn equal_demerit_endpoints_prefer_fewer_flagged_breaks() {
+        let hyphenated = Node {
+            line: 3,
+            total_demerits: 300.0,
+            flagged_count: 2,
+            ..Default::default()
+        };
+        let unhyphenated = Node {
+            line: 4,
+            total_demerits: 300.0,
+            flagged_count: 1,
+            ..Default::default()
+        };
+        assert!(unhyphenated.is_better_endpoint(&hyphenated));
+        assert!(!hyphenated.is_better_endpoint(&unhyphenated));
+    }
+
+    #[test]
+    fn equal_demerit_paths_prefer_fewer_flagged_breaks() {
+        // The underscore is a hyphenation point. As the glue is rigid and every line is set as
+        // is, breaking after "a" or after "aaa" leads to equally good second lines.
+        let text = "a_aa bbb ccc";
+        let mut items = rigid_monospace(text);
+        items[1] = Item::flagged_penalty(0.0, 0.0, true, ());
+        let knuth_plass = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .with_rigid_glue_fallback(true);
+        let lines = knuth_plass.layout_paragraph(&items, 6.0);
+        assert_eq!(split(text, &lines), ["a_aa", "bbb", "ccc"]);
+    }
+
+    #[test]
+    fn deactivate_node() {
+        let items = monospace("a b c");
+        let knuth_plass = KnuthPlass::new();
+        let mut layout = knuth_plass.layout(&items, 10.0);
+
+        let nodes: Vec<NodeIndex> = (0..3)
+            .map(|position| {
+                layout.new_node(Node {
+                    position,
+                    ..Default::default()
+                })
+            })
+            .collect();
+        layout.node_mut(nodes[0]).link = Some(nodes[1]);
+        layout.node_mut(nodes[1]).link = Some(nodes[2]);
+        layout.active = Some(nodes[0]);
+
+        let active = |layout: &KnuthPlassLayout<Vec<Item>, f32>| {
+            let mut positions = Vec::new();
+            let mut a = layout.active;
+            while let Some(n) = a {
+                positions.push(layout.node(n).position);
+                a = layout.node(n).link;
+            }
+            positions
+        };
+        assert_eq!(active(&layout), [0, 1, 2]);
+
+        // Deactivate a node in the middle of the list, then the head of the list.
+        layout.deactivate_node(Some(nodes[0]), nodes[1]);
+        assert_eq!(active(&layout), [0, 2]);
+        layout.deactivate_node(None, nodes[0]);
+        assert_eq!(active(&layout), [2]);
+    }
+
+    #[test]
+    fn layout_exact_lines() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new();
+
+        let lines = knuth_plass.layout_exact_lines(&items, 20.0, 3).unwrap();
+        assert_eq!(
+            split(text, &lines),
+            split(text, &knuth_plass.layout_paragraph(&items, 20.0))
+        );
+
+        let lines = knuth_plass.layout_exact_lines(&items, 20.0, 5).unwrap();
+        assert_eq!(lines.len(), 5);
+        assert!(lines.iter().any(|l| l.adjustment_ratio > 1.0));
+
+        assert!(knuth_plass.layout_exact_lines(&items, 20.0, 2).is_none());
+        assert!(knuth_plass.layout_exact_lines(&items, 20.0, 10).is_none());
+    }
+
+    #[test]
+    fn min_pages() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let mut items = monospace(text);
+        for item in &mut items {
+            if let Item::Glue { shrink, .. } = item {
+                *shrink = 1.0;
+            }
+        }
+        let knuth_plass = KnuthPlass::new();
+        assert_eq!(
+            split(text, &knuth_plass.layout_paragraph(&items, 20.0)),
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+
+        // "dog." would spill onto a second page of two lines, so the last line is shrunk to fit.
+        let (pages, lines) = knuth_plass.min_pages(&items, 20.0, 2);
+        assert_eq!(pages, 1);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown fox", "jumps over the lazy dog."]
+        );
+
+        // Three lines per page already fit the optimal layout on one page.
+        let (pages, lines) = knuth_plass.min_pages(&items, 20.0, 3);
+        assert_eq!((pages, lines.len()), (1, 3));
+    }
+
+    #[test]
+    fn rigid_glue_fallback() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = rigid_monospace(text);
+
+        let knuth_plass = KnuthPlass::new();
+        assert!(knuth_plass.layout_paragraph(&items, 20.0).is_empty());
+
+        let lines = knuth_plass
+            .with_rigid_glue_fallback(true)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[..2].iter().all(|l| l.adjustment_ratio == 0.0));
+    }
+
+    #[test]
+    fn looseness() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        assert_eq!(knuth_plass.layout_paragraph(&items, 20.0).len(), 3);
+
+        // The optimal path to each break has fewer lines than the looser layout needs, so the
+        // looser layout is only found if line counts are tracked separately.
+        let lines = knuth_plass.with_looseness(1).layout_paragraph(&items, 20.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown", "fox jumps", "over the lazy", "dog."]
+        );
+    }
+
+    #[test]
+    fn layout_paragraph_with_stats() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+
+        let (lines, stats) = knuth_plass.layout_paragraph_with_stats(&items, 20.0);
+        assert_eq!(stats.optimal_lines, 3);
+        assert_eq!(stats.selected_lines, lines.len());
+        assert_eq!(stats.optimal_lines, stats.selected_lines);
+
+        let (lines, stats) = knuth_plass
+            .with_looseness(1)
+            .layout_paragraph_with_stats(&items, 20.0);
+        assert_eq!(stats.optimal_lines, 3);
+        assert_eq!(stats.selected_lines, 4);
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn layout_chain() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+
+        let (lines, stats) = knuth_plass.layout_paragraph_with_stats(&items, 20.0);
+        let chain = knuth_plass.layout_chain(&items, 20.0);
+        assert_eq!(
+            chain.iter().map(|b| b.position).collect::<Vec<_>>(),
+            lines.iter().map(|l| l.break_at).collect::<Vec<_>>()
+        );
+        assert_eq!(chain.iter().map(|b| b.line).collect::<Vec<_>>(), [1, 2, 3]);
+        assert!(chain
+            .windows(2)
+            .all(|w| w[0].total_demerits <= w[1].total_demerits));
+        assert_eq!(chain[2].total_demerits, stats.total_demerits);
+        // The last line is set with infinitely-stretchable glue, so it is decent.
+        assert_eq!(chain[2].fitness, Fitness::One);
+    }
+
+    #[test]
+    fn objective() {
+        // Six one-unit boxes separated by very stretchy glue and penalties, so each line is set
+        // nearly perfectly and its demerits are dominated by the cost of its penalty. Breaking
+        // into two lines costs one expensive penalty, while breaking into three costs two cheaper
+        // ones: the former has fewer total demerits, but the latter has a better worst line.
+        let mut items = Vec::new();
+        for cost in [1000.0, 40.0, 50.0, 40.0, 1000.0, f32::NEG_INFINITY] {
+            items.push(Item::glue(0.0, 1000.0, 0.0, ()));
+            items.push(Item::Box {
+                width: 1.0,
+                data: (),
+            });
+            if cost == f32::NEG_INFINITY {
+                items.extend(monospace(""));
+            } else {
+                items.push(Item::penalty(cost, ()));
+            }
+        }
+        let breaks = |lines: Vec<Line>| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+
+        let knuth_plass = KnuthPlass::new().with_threshold(10.0);
+        assert_eq!(breaks(knuth_plass.layout_paragraph(&items, 3.0)), [8, 18]);
+
+        let knuth_plass = knuth_plass.with_objective(Objective::MinMax);
+        assert_eq!(
+            breaks(knuth_plass.layout_paragraph(&items, 3.0)),
+            [5, 11, 18]
+        );
+    }
+
+    #[test]
+    fn max_glue_stretch() {
+        // The space after "aaaa" is far more elastic than the others, so it absorbs most of the
+        // slack on any line that is stretched.
+        let text = "aaaa bbbb cccc dddd";
+        let mut items = monospace(text);
+        let n = items.len();
+        for (i, item) in items[..n - 2].iter_mut().enumerate() {
+            if let Item::Glue {
+                stretch, shrink, ..
+            } = item
+            {
+                if i == 4 {
+                    *stretch = 10.0;
+                }
+                *shrink = 1.0;
+            }
+        }
+
+        let knuth_plass = KnuthPlass::new();
+        let lines = knuth_plass.layout_paragraph(&items, 13.0);
+        assert_eq!(split(text, &lines), ["aaaa bbbb", "cccc dddd"]);
+        assert!((lines[0].glue_width(1.0, 10.0, 1.0) - 5.0).abs() < 1e-5);
+
+        // Stretching the elastic space to five times its width is no longer allowed, so the first
+        // line is shrunk instead.
+        let lines = knuth_plass
+            .with_max_glue_stretch(3.0)
+            .layout_paragraph(&items, 13.0);
+        assert_eq!(split(text, &lines), ["aaaa bbbb cccc", "dddd"]);
+    }
+
+    #[test]
+    fn pinned_break() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = || KnuthPlass::new().with_threshold(f32::INFINITY);
+
+        // End the first line after "brown" rather than after "fox".
+        let lines = split(
+            text,
+            &knuth_plass()
+                .with_pinned_break(15, 0)
+                .layout_paragraph(&items, 20.0),
+        );
+        assert_eq!(
+            lines,
+            ["The quick brown", "fox jumps over the", "lazy dog."]
+        );
+
+        // Ending the second line after "fox" requires splitting the first.
+        let lines = split(
+            text,
+            &knuth_plass()
+                .with_pinned_break(19, 1)
+                .layout_paragraph(&items, 20.0),
+        );
+        assert_eq!(
+            lines,
+            ["The quick", "brown fox", "jumps over the lazy", "dog."]
+        );
+
+        // The best path to "over" reaches it on the second line, yet the pin requires the third.
+        let lines = split(
+            text,
+            &knuth_plass()
+                .with_pinned_break(30, 2)
+                .layout_paragraph(&items, 20.0),
+        );
+        assert_eq!(
+            lines,
+            ["The quick", "brown fox", "jumps over", "the lazy dog."]
+        );
+
+        // A pin at an item that is not a legal breakpoint makes the paragraph infeasible.
+        assert!(knuth_plass()
+            .with_pinned_break(1, 0)
+            .layout_paragraph(&items, 20.0)
+            .is_empty());
+    }
+
+    #[test]
+    fn max_rag() {
+        let text = "Far out in the uncharted backwaters of the unfashionable end of the western \
+                    spiral arm of the Galaxy lies a small unregarded yellow sun.";
+        let items = monospace(text);
+        let rag = |lines: &[&str]| {
+            let widths = lines[..lines.len() - 1].iter().map(|l| l.len());
+            widths.clone().max().unwrap() - widths.min().unwrap()
+        };
+
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = split(text, &knuth_plass.layout_paragraph(&items, 28.0));
+        assert_eq!(rag(&lines), 13);
+
+        let knuth_plass = knuth_plass.with_max_rag(4.0);
+        let lines = split(text, &knuth_plass.layout_paragraph(&items, 28.0));
+        assert_eq!(rag(&lines), 7);
+        assert_eq!(lines[0], "Far out in the uncharted");
+    }
+
+    #[test]
+    fn looseness_per_forced_segment() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let mut items = monospace(text);
+        items.extend(monospace(text));
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+
+        let (lines, stats) = knuth_plass.layout_paragraph_with_stats(&items, 20.0);
+        assert_eq!(lines.len(), 6);
+        assert_eq!(stats.optimal_lines, 6);
+
+        // Each segment is set one line looser than its optimum.
+        let (lines, stats) = knuth_plass
+            .with_looseness(1)
+            .layout_paragraph_with_stats(&items, 20.0);
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[3].break_at, text.len() + 1);
+        assert_eq!(stats.optimal_lines, 6);
+        assert_eq!(stats.selected_lines, 8);
+    }
+
+    #[test]
+    fn short_line_penalty() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = split(text, &knuth_plass.layout_paragraph(&items, 20.0));
+        assert_eq!(
+            lines,
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+
+        // Penalizing one-word lines pulls "lazy" down to the last line.
+        let lines = split(
+            text,
+            &knuth_plass
+                .with_short_line_penalty(2, 1.0e7)
+                .layout_paragraph(&items, 20.0),
+        );
+        assert_eq!(
+            lines,
+            ["The quick brown", "fox jumps over the", "lazy dog."]
+        );
+
+        // Penalizing two-word lines as well pulls "the" down too.
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = split(
+            text,
+            &knuth_plass
+                .with_short_line_penalty(3, 1.0e7)
+                .layout_paragraph(&items, 20.0),
+        );
+        assert_eq!(
+            lines,
+            ["The quick brown", "fox jumps over", "the lazy dog."]
+        );
+    }
+
+    #[test]
+    fn content_weights() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .with_short_line_penalty(5, 1.0e7);
+
+        // Measured in words, every line is short, so the penalty has no effect.
+        let lines = split(text, &knuth_plass.layout_paragraph(&items, 20.0));
+        assert_eq!(
+            lines,
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+
+        // Measured in characters, only "dog." is short.
+        let weights = vec![1.0; items.len()];
+        let lines = split(
+            text,
+            &knuth_plass
+                .with_content_weights(&weights)
+                .layout_paragraph(&items, 20.0),
+        );
+        assert_eq!(
+            lines,
+            ["The quick brown", "fox jumps over the", "lazy dog."]
+        );
+
+        // The minimum is not limited to the range of i16.
+        let weights = vec![10000.0; items.len()];
+        let lines = split(
+            text,
+            &KnuthPlass::new()
+                .with_threshold(f32::INFINITY)
+                .with_short_line_penalty(50000, 1.0e7)
+                .with_content_weights(&weights)
+                .layout_paragraph(&items, 20.0),
+        );
+        assert_eq!(
+            lines,
+            ["The quick brown", "fox jumps over the", "lazy dog."]
+        );
+    }
+
+    #[test]
+    fn glue_breaks() {
+        // Allow the statement to break after the "=" by inserting a penalty before the space.
+        let text = "    let total = first + second;";
+        let mut items = monospace(text);
+        items.insert(15, Item::penalty(0.0, ()));
+        let breaks = |lines: &[Line<f32>]| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        assert_eq!(
+            breaks(&knuth_plass.layout_paragraph(&items, 24.0)),
+            [24, 33]
+        );
+
+        let knuth_plass = knuth_plass.with_glue_breaks(false);
+        assert_eq!(
+            breaks(&knuth_plass.layout_paragraph(&items, 24.0)),
+            [15, 33]
+        );
+        assert!(knuth_plass.layout_paragraph(&items[16..], 10.0).is_empty());
+    }
+
+    #[test]
+    fn ignore_zero_width_boxes() {
+        // A zero-width marker sits between the two spaces that follow "aaa".
+        let mut items = monospace("aaa  bbb cc");
+        items.insert(
+            4,
+            Item::Box {
+                width: 0.0,
+                data: (),
+            },
+        );
+        let breaks = |lines: &[Line<f32>]| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+
+        let knuth_plass = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .with_rigid_glue_fallback(true);
+        assert_eq!(
+            breaks(&knuth_plass.layout_paragraph(&items, 6.0)),
+            [3, 5, 13]
+        );
+
+        // The glue that follows the marker is not a breakpoint once the marker is ignored.
+        let knuth_plass = knuth_plass.with_ignore_zero_width_boxes(true);
+        assert_eq!(
+            breaks(&knuth_plass.layout_paragraph(&items, 6.0)),
+            [3, 9, 13]
+        );
+    }
+
+    #[test]
+    fn justify_last_line() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+
+        // By default, the finishing glue absorbs nearly all of the last line's leftover space.
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = knuth_plass.layout_paragraph(&items, 20.0);
+        assert_eq!(split(text, &lines)[2], "dog.");
+        assert!(lines[2].adjustment_ratio < 0.001);
+
+        // Once justified, the last line's two spaces must fill the remaining 7 units, so
+        // Knuth-Plass evens out the lines rather than leave "dog." alone.
+        let lines = knuth_plass
+            .with_justify_last_line(true)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown", "fox jumps over", "the lazy dog."]
+        );
+        assert_eq!(lines[2].adjustment_ratio, 3.5);
+    }
+
+    #[test]
+    fn trailing_empty_line() {
+        // End the paragraph with a newline, i.e. finishing glue and a forced break.
+        let mut items = monospace("The quick brown fox jumps over the lazy dog.");
+        let n = items.len();
+        items.splice(n - 2..n - 2, [items[n - 2].clone(), Item::forced_break(())]);
+        let breaks = |lines: &[Line<f32>]| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = knuth_plass.layout_paragraph(&items, 20.0);
+        assert_eq!(breaks(&lines), [19, 39, 45, 47]);
+
+        let knuth_plass = knuth_plass.with_trailing_empty_line(false);
+        let (lines, stats) = knuth_plass.layout_paragraph_with_stats(&items, 20.0);
+        assert_eq!(breaks(&lines), [19, 39, 45]);
+        assert_eq!(stats.selected_lines, 3);
+        assert_eq!(knuth_plass.layout_chain(&items, 20.0).len(), 3);
+    }
+
+    #[test]
+    fn work_budget() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let expected = knuth_plass.try_layout_paragraph(&items, 20.0).unwrap();
+
+        let knuth_plass = knuth_plass.with_work_budget(10);
+        assert!(knuth_plass.layout_paragraph(&items, 20.0).is_empty());
+        assert_eq!(
+            knuth_plass.try_layout_paragraph(&items, 20.0).unwrap_err(),
+            LayoutError::BudgetExceeded
+        );
+
+        let knuth_plass = knuth_plass.with_work_budget(1000);
+        let lines = knuth_plass.try_layout_paragraph(&items, 20.0).unwrap();
+        assert_eq!(
+            lines.iter().map(|l| l.break_at).collect::<Vec<_>>(),
+            expected.iter().map(|l| l.break_at).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn penultimate_penalty() {
+        // Allow each space to shrink by up to one unit.
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let mut items = monospace(text);
+        for item in &mut items {
+            if let Item::Glue {
+                width: 1.0, shrink, ..
+            } = item
+            {
+                *shrink = 1.0;
+            }
+        }
+
+        // Squeezing "lazy" onto a tight second line leaves "dog." alone on the last.
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = knuth_plass.layout_paragraph(&items, 17.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+        assert!(lines[1].adjustment_ratio < -0.5);
+
+        // Penalizing the tight second line carries "lazy" down to keep "dog." company.
+        let lines = knuth_plass
+            .with_penultimate_penalty(1.0e7)
+            .layout_paragraph(&items, 17.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown", "fox jumps over the", "lazy dog."]
+        );
+    }
+
+    #[test]
+    fn arena_capacity() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let knuth_plass = KnuthPlass::new();
+        assert_eq!(
+            knuth_plass.layout(&items, 20.0).nodes.capacity(),
+            items.len()
+        );
+
+        let node_size = size_of::<Node<f32>>();
+        let knuth_plass = KnuthPlass::new().with_arena_capacity(4 * node_size);
+        assert_eq!(knuth_plass.layout(&items, 20.0).nodes.capacity(), 4);
+
+        // The arena grows past its initial capacity as needed.
+        let knuth_plass = knuth_plass.with_arena_capacity(0);
+        assert_eq!(
+            knuth_plass.layout_paragraph(&items, 20.0).len(),
+            KnuthPlass::new().layout_paragraph(&items, 20.0).len()
+        );
+    }
+
+    #[test]
+    fn fixed_point_breaks() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let knuth_plass = KnuthPlass::new().with_threshold(Fixed::MAX);
+        assert_eq!(fixed_breaks(&knuth_plass, &items, 20.0), [19, 39, 45]);
+        assert_eq!(fixed_breaks(&knuth_plass, &items, 28.0), [25, 45]);
+    }
+
+    #[test]
+    fn greedy_shortcut() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+
+        // The greedy layout's second line has an adjustment ratio of 4/3, so it is only accepted
+        // by a tolerance above that.
+        let lines = knuth_plass
+            .with_greedy_shortcut(2.0)
+            .layout_paragraph(&items, 19.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown fox", "jumps over the", "lazy dog."]
+        );
+
+        let knuth_plass = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .with_greedy_shortcut(0.5);
+        let lines = knuth_plass.layout_paragraph(&items, 19.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+        let prepared = PreparedParagraph::new(&items);
+        let prepared_lines = knuth_plass.layout_prepared(&prepared, 19.0);
+        assert_eq!(split(text, &prepared_lines), split(text, &lines));
+    }
+
+    #[test]
+    fn layout_by() {
+        // The paragraph is terminated by finishing glue of one fil, marked by a newline, and a
+        // forced break, marked by a NUL.
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let glyphs: Vec<u8> = text.bytes().chain(*b"\n\0").collect();
+        let metrics = |&c: &u8| {
+            let (kind, width, stretch, stretch_order, cost) = match c {
+                b' ' => (ItemKind::Glue, 1.0, 1.0, GlueOrder::Normal, 0.0),
+                b'\n' => (ItemKind::Glue, 0.0, 1.0, GlueOrder::Fil, 0.0),
+                b'\0' => (
+                    ItemKind::Penalty,
+                    0.0,
+                    0.0,
+                    GlueOrder::Normal,
+                    f32::NEG_INFINITY,
+                ),
+                _ => (ItemKind::Box, 1.0, 0.0, GlueOrder::Normal, 0.0),
+            };
+            ItemMetrics {
+                width,
+                stretch,
+                stretch_order,
+                shrink: 0.0,
+                shrink_order: GlueOrder::Normal,
+                discardable: true,
+                kind,
+                cost,
+                flagged: c == 0,
+            }
+        };
+
+        let knuth_plass = KnuthPlass::new();
+        let breaks = |lines: Vec<Line>| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+        assert_eq!(
+            breaks(knuth_plass.layout_by(&glyphs, metrics, 20.0)),
+            breaks(knuth_plass.layout_paragraph(&monospace(text), 20.0))
+        );
+    }
+
+    #[test]
+    fn balance_then_justify() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new();
+        assert_eq!(
+            split(text, &knuth_plass.layout_paragraph(&items, 20.0)),
+            ["The quick brown fox", "jumps over the lazy", "dog."]
+        );
+
+        let lines = knuth_plass
+            .with_balance_then_justify(true)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown", "fox jumps over", "the lazy dog."]
+        );
+        // Each line's two spaces absorb the difference between its length and the line width.
+        assert_eq!(crate::line_ratios(&lines), [2.5, 3.0, 3.5]);
+    }
+
+    #[test]
+    fn max_lines() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new().with_max_lines(2);
+        let lines = knuth_plass.layout_paragraph(&items, 20.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown fox", "jumps over the lazy"]
+        );
+        assert_eq!(
+            lines.iter().map(|l| l.truncated).collect::<Vec<_>>(),
+            [false, true]
+        );
+
+        // An ellipsis three units wide no longer fits after "lazy", so the line is broken earlier
+        // and set at its natural width.
+        let lines = knuth_plass
+            .with_ellipsis(3.0)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(
+            split(text, &lines),
+            ["The quick brown fox", "jumps over the"]
+        );
+        assert_eq!((lines[1].adjustment_ratio, lines[1].truncated), (0.0, true));
+
+        // Trimmed leading glue does not take up room that the ellipsis needs.
+        let text = "  The quick brown fox jumps over the lazy dog.";
+        let mut items = monospace(text);
+        items[0] = items[1].clone();
+        let lines = KnuthPlass::new()
+            .with_trim_leading_glue(true)
+            .with_max_lines(1)
+            .with_ellipsis(4.0)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(split(text, &lines), ["  The quick brown"]);
+        assert!(lines[0].truncated);
+
+        // A paragraph that fits is left alone.
+        let lines = KnuthPlass::new()
+            .with_max_lines(3)
+            .with_ellipsis(3.0)
+            .layout_paragraph(&items, 20.0);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|l| !l.truncated));
+    }
+
+    #[test]
+    fn no_single_box_last_line() {
+        let text = "I saw a cat and a dog and a b";
+        let items = monospace(text);
+        let knuth_plass = KnuthPlass::new();
+        assert_eq!(
+            split(text, &knuth_plass.layout_paragraph(&items, 27.0)),
+            ["I saw a cat and a dog and a", "b"]
+        );
+
+        let knuth_plass = knuth_plass.with_no_single_box_last_line(true);
+        assert_eq!(
+            split(text, &knuth_plass.layout_paragraph(&items, 27.0)),
+            ["I saw a cat and a dog and", "a b"]
+        );
+
+        // There is no other feasible layout, so the last line keeps its single box.
+        assert_eq!(
+            split(text, &knuth_plass.layout_paragraph(&items, 9.0)),
+            ["I saw a", "cat and a", "dog and a", "b"]
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn tiebreak_seed() {
+        let texts = [
+            "The quick brown fox jumps over the lazy dog.",
+            "a b c d e f g h i j k l m n o p q r s t u v w x y z",
+        ];
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        for text in texts {
+            let items = monospace(text);
+            for line_width in [6.0, 9.0, 10.0, 12.0, 15.0, 20.0] {
+                let (_, stats) = knuth_plass.layout_paragraph_with_stats(&items, line_width);
+                for seed in 0..16 {
+                    let (_, seeded) = KnuthPlass::new()
+                        .with_threshold(f32::INFINITY)
+                        .with_tiebreak_seed(seed)
+                        .layout_paragraph_with_stats(&items, line_width);
+                    assert_eq!(seeded.total_demerits, stats.total_demerits);
+                }
+            }
+        }
+
+        // Splitting five equally long words into lines of two and three words or of three and two
+        // words costs the same. Fixed-point demerits sum exactly regardless of the order of the
+        // lines, so the two layouts tie and the seed decides between them.
+        let items = crate::convert_items(
+            &monospace("a a a a a"),
+            Fixed::<fixed::types::I32F32>::from_num,
+        );
+        let knuth_plass = || {
+            KnuthPlass::new()
+                .with_threshold(Fixed::from_num(10))
+                .with_justify_last_line(true)
+        };
+        let (_, stats) = knuth_plass().layout_paragraph_with_stats(&items, Fixed::from_num(8));
+        let mut layouts = Vec::new();
+        for seed in 0..16 {
+            let (lines, seeded) = knuth_plass()
+                .with_tiebreak_seed(seed)
+                .layout_paragraph_with_stats(&items, Fixed::from_num(8));
+            assert_eq!(seeded.total_demerits, stats.total_demerits);
+            let breaks = lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+            if !layouts.contains(&breaks) {
+                layouts.push(breaks);
+            }
+        }
+        layouts.sort();
+        assert_eq!(layouts, [[3, 10], [5, 10]]);
+    }
+
+    #[test]
+    fn last_resort_penalty() {
+        let breaks = |lines: &[Line<f32>]| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+
+        // A penalty between "bbbb" and "cccc" would be preferred over the very loose first line
+        // that is needed to break at glue instead.
+        let mut items = monospace("aa aa bbbbcccc");
+        items.insert(
+            10,
+            Item::Penalty {
+                width: 0.0,
+                cost: 50.0,
+                flags: PenaltyFlags::NONE,
+                data: (),
+            },
+        );
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        assert_eq!(
+            breaks(&knuth_plass.layout_paragraph(&items, 10.0)),
+            [10, 16]
+        );
+
+        // As a last resort, the penalty is ignored as long as any glue break works.
+        items[10] = Item::Penalty {
+            width: 0.0,
+            cost: 50.0,
+            flags: PenaltyFlags::LAST_RESORT,
+            data: (),
+        };
+        assert_eq!(breaks(&knuth_plass.layout_paragraph(&items, 10.0)), [5, 16]);
+
+        // Without a feasible glue break, the penalty is used after all.
+        let knuth_plass = KnuthPlass::new();
+        assert_eq!(
+            breaks(&knuth_plass.layout_paragraph(&items, 10.0)),
+            [10, 16]
+        );
+
+        // Only the line that has no feasible glue break may end at a penalty, even if the
+        // paragraph would have fewer demerits if another line ended at one too.
+        let mut items = monospace("aaa bb cccccccc d e ffffffffff g h");
+        for b in [25, 11] {
+            items.insert(
+                b,
+                Item::Penalty {
+                    width: 0.0,
+                    cost: 50.0,
+                    flags: PenaltyFlags::LAST_RESORT,
+                    data: (),
+                },
+            );
+        }
+        let knuth_plass = KnuthPlass::new().with_threshold(5.0);
+        assert_eq!(
+            breaks(&knuth_plass.layout_paragraph(&items, 11.0)),
+            [6, 18, 26, 37]
+        );
+    }
+
+    #[test]
+    fn feasibility_epsilon() {
+        // Thirteen words set at exactly their full stretch, and five words set at exactly their
+        // full shrink, both of which are just infeasible in floating point without a tolerance.
+        for (items, line_width) in [(tenths(13), 3.7), (tenths(5), 0.5)] {
+            let exact = KnuthPlass::new().with_feasibility_epsilon(0.0);
+            assert!(exact.layout_paragraph(&items, line_width).is_empty());
+
+            let lines = KnuthPlass::new().layout_paragraph(&items, line_width);
+            assert_eq!(lines.len(), 1);
+            assert!((lines[0].adjustment_ratio.abs() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn hyphenation_constraints() {
+        // Every hyphen is a free, zero-width hyphenation point within "hyphenation".
+        let text = "xx hy-phen-a-tion yy";
+        let mut items = Vec::new();
+        for c in text.chars() {
+            items.push(match c {
+                ' ' => Item::glue(1.0, 1.0, 0.0, ()),
+                '-' => Item::flagged_penalty(0.0, 0.0, true, ()),
+                _ => Item::Box {
+                    width: 1.0,
+                    data: (),
+                },
+            });
+        }
+        items.push(Item::glue(0.0, f32::INFINITY, 0.0, ()));
+        items.push(Item::forced_break(()));
+        let word = [(3, 17)];
+
+        // Without constraints, the word may be broken twice.
+        let knuth_plass = || {
+            KnuthPlass::new()
+                .with_threshold(f32::INFINITY)
+                .with_rigid_glue_fallback(true)
+        };
+        assert_eq!(
+            split(text, &knuth_plass().layout_paragraph(&items, 5.0)),
+            ["xx hy", "phen", "a-tion", "yy"]
+        );
+        assert!(knuth_plass()
+            .with_hyphenation_constraints(&word, 0)
+            .layout_paragraph(&items, 5.0)
+            .is_empty());
+
+        // Breaks that leave fewer than five letters on either side of them are forbidden.
+        assert_eq!(
+            split(text, &knuth_plass().layout_paragraph(&items, 10.0)),
+            ["xx hy-phen-a", "tion yy"]
+        );
+        assert_eq!(
+            split(
+                text,
+                &knuth_plass()
+                    .with_hyphenation_constraints(&word, 5)
+                    .layout_paragraph(&items, 10.0)
+            ),
+            ["xx", "hy-phen", "a-tion yy"]
+        );
+    }
+
+    #[test]
+    fn leading_penalty() {
+        // Neither a hyphenation hint nor a forced break at the start of the paragraph produces an
+        // empty first line.
+        let text = "The quick brown fox jumps over the lazy dog.";
+        for penalty in [
+            Item::flagged_penalty(0.0, -100.0, true, ()),
+            Item::forced_break(()),
+        ] {
+            let mut items = monospace(text);
+            items.insert(0, penalty);
+            let lines = KnuthPlass::new().layout_paragraph(&items, 20.0);
+            assert_eq!(
+                lines.iter().map(|l| l.break_at).collect::<Vec<_>>(),
+                [20, 40, 46]
+            );
+        }
+    }
+
+    #[test]
+    fn discarded_infinite_glue() {
+        // Zero-width glue after the second space is discarded along with the space by a break
+        // there, so it must not make the following line infinitely stretchable.
+        let layout = |order| {
+            let mut items = monospace("aa aa bb bb cc");
+            items.insert(
+                6,
+                Item::Glue {
+                    width: 0.0,
+                    stretch: 1.0,
+                    stretch_order: order,
+                    shrink: 0.0,
+                    shrink_order: GlueOrder::Normal,
+                    priority: 0,
+                    discardable: true,
+                    data: (),
+                },
+            );
+            KnuthPlass::new()
+                .with_threshold(10.0)
+                .layout_paragraph(&items, 6.0)
+        };
+        for order in [GlueOrder::Normal, GlueOrder::Fil] {
+            let lines = layout(order);
+            assert_eq!(
+                lines.iter().map(|l| l.break_at).collect::<Vec<_>>(),
+                [5, 12, 16]
+            );
+        }
+        assert_eq!(layout(GlueOrder::Normal)[1].adjustment_ratio, 0.5);
+    }
 }
@@ -5,18 +5,58 @@ compile_error! { "Either the std, fixed, or libm feature must be enabled" }
 
 extern crate alloc;
 use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug, Display};
+use core::ops::{AddAssign, BitOr, BitOrAssign, Range, Sub, SubAssign};
+
+mod analysis;
+pub use analysis::*;
+
+mod balance_tail;
+pub use balance_tail::*;
 
 mod first_fit;
 pub use first_fit::*;
 
+mod justify;
+pub use justify::{GlueMetrics, Justifier, Proportional, Uniform};
+
 mod knuth_plass;
 pub use knuth_plass::*;
 
 mod math;
 pub use math::{Fixed, Num};
 
+mod prepared;
+pub use prepared::PreparedParagraph;
+
+mod quantized;
+pub use quantized::QuantizedLayout;
+
+mod region;
+pub use region::{BoxMetrics, Region};
+
+#[cfg(feature = "std")]
+mod render;
+#[cfg(feature = "std")]
+pub use render::{debug_layout, render_framed};
+
+#[cfg(feature = "text")]
+mod text;
+#[cfg(feature = "text")]
+pub use text::*;
+
+#[cfg(feature = "unicode")]
+mod unicode;
+#[cfg(feature = "unicode")]
+pub use unicode::*;
+
+#[cfg(test)]
+mod testing;
+
 /// A single item in a paragraph.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Item<Box = (), Glue = (), Penalty = (), N = f32> {
     /// An unbreakable box containing paragraph content. Typically represents a glyph or sequence
     /// of glyphs. Lines may not be broken at boxes.
@@ -32,27 +72,257 @@ pub enum Item<Box = (), Glue = (), Penalty = (), N = f32> {
         width: N,
         /// The stretch parameter. If this item needs to be stretched in order to lay out a line,
         /// the stretch amount will be proportional to this value.
+        ///
+        /// A line whose glue has no stretch can only be set if its natural width is at least the
+        /// line width, as any leftover space cannot be absorbed. Such lines have an infinite
+        /// adjustment ratio and are infeasible under any finite threshold unless the layout
+        /// algorithm is configured with a rigid glue fallback.
+        ///
+        /// The stretch may be infinite, e.g. for the glue that finishes a paragraph, regardless of
+        /// the shrink. A line that contains infinitely stretchable glue can be stretched to any
+        /// width, and has an adjustment ratio of 0.
         stretch: N,
+        /// The order of infinity of the stretch. Stretch of a higher order dominates, so a line
+        /// whose stretch includes any of a higher order than `GlueOrder::Normal` is infinitely
+        /// stretchable.
+        stretch_order: GlueOrder,
         /// The shrink parameter. If this item needs to be shrunk in order to lay out a line, the
-        /// shrink amount will be proportional to this value.
+        /// shrink amount will be proportional to this value. Like the stretch, the shrink may be
+        /// infinite independently of the stretch.
         shrink: N,
+        /// The order of infinity of the shrink, which is independent of the order of the stretch.
+        shrink_order: GlueOrder,
+        /// The glue's priority. When the positions of the items on a line are resolved by
+        /// `layout_positions`, glue with a higher priority absorbs the line's slack first, and
+        /// glue with a lower priority only stretches or shrinks once every higher priority has
+        /// been adjusted by its full stretch or shrink. Layout algorithms ignore the priority and
+        /// compute adjustment ratios from the aggregate stretch and shrink of each line.
+        ///
+        /// Ordinary glue has a priority of 0; e.g. the space after a sentence might have a priority
+        /// of 1 so that it widens before interword spaces do.
+        priority: u8,
+        /// Whether the glue is discarded if a line is broken at it. Ordinary spaces are
+        /// discardable, so a space at which a line breaks belongs to neither line. Glue that is not
+        /// discardable still stretches and shrinks like any other glue within a line, but when a
+        /// line is broken at it, its natural width is kept at the end of that line and it is
+        /// neither stretched nor shrunk.
+        discardable: bool,
         /// The glue's data.
         data: Glue,
     },
     /// A penalty item. Represents a possible breakpoint with a particular aesthetic cost that
-    /// indicates the desirability or undesirability of such a breakpoint.
+    /// indicates the desirability or undesirability of such a breakpoint. Penalties that precede
+    /// the first box or tab of a paragraph are not breakpoints, even if they are forced, as a break
+    /// there would produce an empty first line; a penalty that ends the paragraph always is. Glue
+    /// and kerns do not count as content for this purpose.
     Penalty {
         /// The width of the penalty item.
         width: N,
         /// The aesthetic cost of the penalty item. A high cost is a relatively undesirable
         /// breakpoint, while a low cost indicates a relatively desirable breakpoint.
         cost: N,
-        /// Whether or not this is a flagged penalty item. Some algorithms will attempt to avoid
-        /// having multiple consecutive breaks at flagged penalty items.
-        flagged: bool,
+        /// The penalty item's flags. Some algorithms will attempt to avoid having consecutive
+        /// breaks at flagged penalty items, as described by the flags.
+        flags: PenaltyFlags,
         /// The penalty's data.
         data: Penalty,
     },
+    /// A kern item. Represents a fixed adjustment to the space between two adjacent items, e.g.
+    /// the kerning of a pair of glyphs. A kern cannot be stretched or shrunk, and is never a
+    /// legal breakpoint.
+    Kern {
+        /// The width of the kern, which may be negative to bring its neighbors closer together.
+        width: N,
+    },
+    /// A tab item. Represents space that extends the line to the next tab stop, e.g. to align the
+    /// columns of tabular text. Layout algorithms treat a tab as rigid space of its minimum width
+    /// that is never a legal breakpoint; the space is only expanded to the next tab stop when the
+    /// positions of the items are resolved by `layout_positions`.
+    Tab {
+        /// The minimum width of the tab.
+        width: N,
+    },
+    /// Glue that only appears at a line break, e.g. the space reserved for a marker that is drawn
+    /// at the end of a broken line. Lines may be broken at break glue. If the line is not broken
+    /// there, the item takes up no space; otherwise it contributes its break width, stretch, and
+    /// shrink to the line that ends at it.
+    BreakGlue {
+        /// The width of the item if the line is broken at it.
+        break_width: N,
+        /// The stretch parameter if the line is broken at the item.
+        stretch: N,
+        /// The shrink parameter if the line is broken at the item.
+        shrink: N,
+    },
+}
+
+/// The order of infinity of a glue item's stretch or shrink, as in TeX. The stretch or shrink of a
+/// line is dominated by its highest order whose amounts do not cancel out, so e.g. a line that
+/// contains both `Fil` and `Fill` stretch is as stretchable as its `Fill` stretch alone. Layout
+/// algorithms treat a line whose stretch or shrink is dominated by an infinite order as infinitely
+/// stretchable or shrinkable on that side.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GlueOrder {
+    /// A finite amount.
+    #[default]
+    Normal,
+    /// An infinite amount of the first order, e.g. TeX's `fil`.
+    Fil,
+    /// An infinite amount of the second order, e.g. TeX's `fill`.
+    Fill,
+    /// An infinite amount of the third order, e.g. TeX's `filll`.
+    Filll,
+}
+
+/// Flags that describe how a break at a penalty item interacts with adjacent breaks at flagged
+/// penalty items, or whether the break is only taken as a last resort. A typical use is a
+/// hyphenation point, which should not be broken at on two consecutive lines.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PenaltyFlags(u8);
+
+impl PenaltyFlags {
+    /// No flags. Breaks at this penalty never incur a flagged demerit.
+    pub const NONE: Self = PenaltyFlags(0);
+    /// Avoid consecutive breaks at penalties that both carry this flag. This is the classic
+    /// flagged penalty of Knuth-Plass '81.
+    pub const AVOID_CONSECUTIVE: Self = PenaltyFlags(1);
+    /// Avoid a break at this penalty if the next line also ends at a flagged penalty.
+    pub const BEFORE: Self = PenaltyFlags(2);
+    /// Avoid a break at this penalty if the previous line also ended at a flagged penalty.
+    pub const AFTER: Self = PenaltyFlags(4);
+    /// Only break at this penalty as a last resort. A line may only end at a penalty with this
+    /// flag if it has no feasible break that is not such a penalty, in which case the penalty is
+    /// weighed by its cost like any other penalty. This ranks the break below every glue break on
+    /// the line rather than merely discouraging it. The flag does not make a break flagged for the
+    /// purposes of the flagged demerit.
+    pub const LAST_RESORT: Self = PenaltyFlags(8);
+
+    /// Returns true if all of the flags in other are set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if no flags are set.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if a break at a penalty with these flags is flagged, i.e. may incur a flagged
+    /// demerit.
+    fn is_flagged(self) -> bool {
+        !self.demerit_flags().is_empty()
+    }
+
+    /// Returns the flags that determine flagged demerits.
+    fn demerit_flags(self) -> Self {
+        PenaltyFlags(self.0 & !Self::LAST_RESORT.0)
+    }
+
+    /// Returns true if consecutive breaks at penalties with the flags prev and next should incur
+    /// a flagged demerit.
+    fn conflict(prev: Self, next: Self) -> bool {
+        let (prev, next) = (prev.demerit_flags(), next.demerit_flags());
+        (prev.contains(Self::AVOID_CONSECUTIVE) && next.contains(Self::AVOID_CONSECUTIVE))
+            || (prev.contains(Self::BEFORE) && !next.is_empty())
+            || (!prev.is_empty() && next.contains(Self::AFTER))
+    }
+}
+
+impl From<bool> for PenaltyFlags {
+    /// Converts a classic flagged bit into flags: true maps to `AVOID_CONSECUTIVE`.
+    fn from(flagged: bool) -> Self {
+        if flagged {
+            Self::AVOID_CONSECUTIVE
+        } else {
+            Self::NONE
+        }
+    }
+}
+
+impl BitOr for PenaltyFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        PenaltyFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for PenaltyFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl<Box, Glue, Penalty, N> Item<Box, Glue, Penalty, N> {
+    /// Creates a penalty item. If `flagged` is true, the penalty has the `AVOID_CONSECUTIVE` flag.
+    pub fn flagged_penalty(width: N, cost: N, flagged: bool, data: Penalty) -> Self {
+        Item::Penalty {
+            width,
+            cost,
+            flags: flagged.into(),
+            data,
+        }
+    }
+
+    /// Returns true if this item is a penalty with a flag that may incur a flagged demerit. This
+    /// takes the place of the `flagged` field that penalties had before it was generalized into
+    /// `flags`.
+    pub fn is_flagged(&self) -> bool {
+        matches!(self, Item::Penalty { flags, .. } if flags.is_flagged())
+    }
+}
+
+impl<Box, Glue, Penalty, N: Num> Item<Box, Glue, Penalty, N> {
+    /// Creates a glue item with the given width, stretch, and shrink. The stretch and shrink are
+    /// finite, i.e. of `GlueOrder::Normal`, the glue has the lowest priority, and it is discarded
+    /// when a line breaks at it. Use `Item::Glue` directly to create any other glue.
+    pub fn glue(width: N, stretch: N, shrink: N, data: Glue) -> Self {
+        Item::Glue {
+            width,
+            stretch,
+            stretch_order: GlueOrder::Normal,
+            shrink,
+            shrink_order: GlueOrder::Normal,
+            priority: 0,
+            discardable: true,
+            data,
+        }
+    }
+
+    /// Creates a zero-width, unflagged penalty item with the given cost. This is shorthand for the
+    /// most common kind of penalty; use `flagged_penalty` to set its width or flag it.
+    pub fn penalty(cost: N, data: Penalty) -> Self {
+        Self::flagged_penalty(N::from(0), cost, false, data)
+    }
+
+    /// Creates a zero-width penalty item with a cost of -∞, at which a line must break.
+    pub fn forced_break(data: Penalty) -> Self {
+        Self::penalty(N::NEG_INFINITY, data)
+    }
+
+    /// Creates a zero-width penalty item with a cost of +∞, at which a line may never break.
+    pub fn forbidden_break(data: Penalty) -> Self {
+        Self::penalty(N::INFINITY, data)
+    }
+
+    /// Returns true if this item is a penalty at which a line must break, i.e. its cost is -∞.
+    pub fn is_forced(&self) -> bool {
+        match self {
+            Item::Penalty { cost, .. } => *cost == N::NEG_INFINITY,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this item is a penalty at which a line may never break, i.e. its cost is
+    /// +∞.
+    pub fn is_forbidden(&self) -> bool {
+        match self {
+            Item::Penalty { cost, .. } => *cost == N::INFINITY,
+            _ => false,
+        }
+    }
 }
 
 impl<Box, Glue, Penalty, N: Num> Item<Box, Glue, Penalty, N> {
@@ -63,57 +333,70 @@ impl<Box, Glue, Penalty, N: Num> Item<Box, Glue, Penalty, N> {
         }
     }
 
-    fn penalty_flag(&self) -> N {
+    /// Returns the width, stretch, and shrink that this item adds to a line that is broken at it.
+    fn break_metrics(&self) -> (N, N, N) {
         match self {
-            Item::Penalty { flagged, .. } => {
-                if *flagged {
-                    N::from(1i16)
-                } else {
-                    N::from(0i16)
-                }
-            }
-            _ => N::from(0i16),
+            Item::Penalty { width, .. }
+            | Item::Glue {
+                width,
+                discardable: false,
+                ..
+            } => (*width, N::from(0), N::from(0)),
+            Item::BreakGlue {
+                break_width,
+                stretch,
+                shrink,
+            } => (*break_width, *stretch, *shrink),
+            _ => (N::from(0), N::from(0), N::from(0)),
         }
     }
 
-    fn is_mandatory_break(&self) -> bool {
+    fn penalty_flags(&self) -> PenaltyFlags {
         match self {
-            Item::Penalty { cost, .. } => *cost == N::NEG_INFINITY,
-            _ => false,
+            Item::Penalty { flags, .. } => *flags,
+            _ => PenaltyFlags::NONE,
         }
     }
 
     /// Returns the width, stretch, and shrink of the node at b and indicates whether or not b is a
     /// legal break.
-    fn is_legal_breakpoint(&self, pred: Option<&Self>) -> (N, N, N, bool) {
+    fn is_legal_breakpoint(&self, pred: Option<&Self>) -> Breakpoint<N> {
+        let none = GlueSum::default();
         match self {
-            Item::Box { width, .. } => (*width, N::from(0), N::from(0), false),
+            Item::Box { width, .. } => (*width, none, none, false),
+            Item::Glue { width, .. } => {
+                let (stretch, shrink) = self.glue_flex();
+                let is_legal = matches!(pred, Some(Item::Box { .. } | Item::Tab { .. }));
+                (*width, stretch, shrink, is_legal)
+            }
+            Item::Penalty { width, .. } => (*width, none, none, !self.is_forbidden()),
+            Item::Kern { width } | Item::Tab { width } => (*width, none, none, false),
+            Item::BreakGlue { .. } => (N::from(0), none, none, true),
+        }
+    }
+
+    /// Returns the stretch and shrink of a glue item at their orders, or none for any other item.
+    fn glue_flex(&self) -> (GlueSum<N>, GlueSum<N>) {
+        match *self {
             Item::Glue {
-                width,
                 stretch,
+                stretch_order,
                 shrink,
+                shrink_order,
                 ..
             } => (
-                *width,
-                *stretch,
-                *shrink,
-                matches!(pred, Some(Item::Box { .. })),
+                GlueSum::of(stretch, stretch_order),
+                GlueSum::of(shrink, shrink_order),
             ),
-            Item::Penalty { width, cost, .. } => {
-                (*width, N::from(0), N::from(0), *cost != N::INFINITY)
-            }
+            _ => (GlueSum::default(), GlueSum::default()),
         }
     }
 
     /// Calculates the adjustment ratio for a break at the given item. Width, stretch, and shrink
     /// are for the line that ends at the break.
     fn adjustment_ratio(&self, width: N, stretch: N, shrink: N, line_width: N) -> N {
-        let penalty_width = if let Item::Penalty { width, .. } = self {
-            *width
-        } else {
-            N::from(0)
-        };
-        let width = width + penalty_width;
+        let (w, y, z) = self.break_metrics();
+        let (width, stretch, shrink) = (width + w, stretch + y, shrink + z);
         if width < line_width {
             if stretch > N::from(0) {
                 (line_width - width) / stretch
@@ -132,8 +415,495 @@ impl<Box, Glue, Penalty, N: Num> Item<Box, Glue, Penalty, N> {
     }
 }
 
-/// A single line of text as represented by its break point and adjustment ratio.
+/// Replaces the infinite adjustment ratio of a line that is short but cannot stretch with 0, so
+/// that the leftover space is left at the end of the line.
+fn rigid_glue_fallback<N: Num>(adjustment_ratio: N) -> N {
+    if adjustment_ratio == N::INFINITY {
+        N::from(0)
+    } else {
+        adjustment_ratio
+    }
+}
+
+/// Calculates the badness of a line from its adjustment ratio, as in TeX. Referred to as 𝛃 in
+/// Knuth-Plass '81.
+fn badness<N: Num>(adjustment_ratio: N) -> N {
+    N::from(100) * adjustment_ratio.abs().powi(3)
+}
+
+/// The metrics of a potential breakpoint: the width, stretch and shrink it adds to the running
+/// totals and whether a line may legally end there.
+type Breakpoint<N> = (N, GlueSum<N>, GlueSum<N>, bool);
+
+/// A running total of glue stretch or shrink. The amounts of each order are summed separately, and
+/// infinite amounts are counted separately from finite ones, so the difference of two totals
+/// remains meaningful even if infinite glue precedes both, e.g. the finishing glue of an earlier
+/// forced segment of the paragraph. Stretch and shrink are tracked by separate totals, so glue may
+/// be infinite in one and finite in the other.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct GlueSum<N> {
+    /// The sum of the finite amounts of each order, indexed by `GlueOrder`.
+    orders: [N; 4],
+    /// The number of infinite amounts, less the number of negatively infinite amounts.
+    infinite: i32,
+}
+
+impl<N: Num> GlueSum<N> {
+    /// Returns a total that consists of a single amount of the given order.
+    fn of(amount: N, order: GlueOrder) -> Self {
+        let mut sum = GlueSum::default();
+        match order {
+            GlueOrder::Normal => sum += amount,
+            order => sum.orders[order as usize] = amount,
+        }
+        sum
+    }
+
+    /// Returns the total, which is infinite if any infinite amounts remain or if the dominant
+    /// order, i.e. the highest whose amounts do not cancel out, is infinite.
+    fn value(self) -> N {
+        let dominant = match self.infinite {
+            0 => self.orders[1..]
+                .iter()
+                .rev()
+                .copied()
+                .find(|&amount| amount != N::from(0)),
+            i if i > 0 => Some(N::INFINITY),
+            _ => Some(N::NEG_INFINITY),
+        };
+        match dominant {
+            None => self.orders[0],
+            Some(amount) if amount > N::from(0) => N::INFINITY,
+            Some(_) => N::NEG_INFINITY,
+        }
+    }
+}
+
+impl<N: Num> AddAssign<N> for GlueSum<N> {
+    fn add_assign(&mut self, amount: N) {
+        if amount == N::INFINITY {
+            self.infinite += 1;
+        } else if amount == N::NEG_INFINITY {
+            self.infinite -= 1;
+        } else {
+            self.orders[0] += amount;
+        }
+    }
+}
+
+impl<N: Num> SubAssign<N> for GlueSum<N> {
+    fn sub_assign(&mut self, amount: N) {
+        if amount == N::INFINITY {
+            self.infinite -= 1;
+        } else if amount == N::NEG_INFINITY {
+            self.infinite += 1;
+        } else {
+            self.orders[0] -= amount;
+        }
+    }
+}
+
+impl<N: Num> AddAssign for GlueSum<N> {
+    fn add_assign(&mut self, other: Self) {
+        for (amount, other) in self.orders.iter_mut().zip(other.orders) {
+            *amount += other;
+        }
+        self.infinite += other.infinite;
+    }
+}
+
+impl<N: Num> SubAssign for GlueSum<N> {
+    fn sub_assign(&mut self, other: Self) {
+        for (amount, other) in self.orders.iter_mut().zip(other.orders) {
+            *amount -= other;
+        }
+        self.infinite -= other.infinite;
+    }
+}
+
+impl<N: Num> Sub for GlueSum<N> {
+    type Output = Self;
+
+    fn sub(mut self, other: Self) -> Self {
+        self -= other;
+        self
+    }
+}
+
+/// Returns true if setting the items in the given range with the given adjustment ratio would
+/// stretch any glue beyond `factor` times its natural width. Glue with no natural width, e.g. the
+/// glue that finishes a paragraph, is not limited.
+fn exceeds_max_glue_stretch<S: ItemSource<N> + ?Sized, N: Num>(
+    items: &S,
+    range: Range<usize>,
+    adjustment_ratio: N,
+    factor: N,
+) -> bool {
+    if adjustment_ratio <= N::from(0) {
+        return false;
+    }
+    range.map(|i| items.item(i)).any(|item| match item {
+        Item::Glue { width, stretch, .. } => {
+            width > N::from(0) && width + stretch * adjustment_ratio > width * factor
+        }
+        _ => false,
+    })
+}
+
+/// Returns the total width, stretch, and shrink of the items in the given range.
+fn sum_metrics<S: ItemSource<N> + ?Sized, N: Num>(
+    items: &S,
+    range: Range<usize>,
+) -> (N, GlueSum<N>, GlueSum<N>) {
+    let (mut width, mut stretch, mut shrink) = (N::from(0), GlueSum::default(), GlueSum::default());
+    for i in range {
+        let item = items.item(i);
+        match item {
+            Item::Box { width: w, .. }
+            | Item::Penalty { width: w, .. }
+            | Item::Kern { width: w }
+            | Item::Tab { width: w } => width += w,
+            Item::Glue { width: w, .. } => {
+                let (y, z) = item.glue_flex();
+                width += w;
+                stretch += y;
+                shrink += z;
+            }
+            Item::BreakGlue { .. } => {}
+        }
+    }
+    (width, stretch, shrink)
+}
+
+/// Returns the total width, stretch, and shrink of the glue and kern items in the given range that
+/// precede the first box or tab in the range. These items are discarded from the start of a line
+/// when leading glue is trimmed.
+fn leading_glue<S: ItemSource<N> + ?Sized, N: Num>(
+    items: &S,
+    range: Range<usize>,
+) -> (N, GlueSum<N>, GlueSum<N>) {
+    let (mut width, mut stretch, mut shrink) = (N::from(0), GlueSum::default(), GlueSum::default());
+    for i in range {
+        let item = items.item(i);
+        match item {
+            Item::Box { .. } | Item::Tab { .. } => break,
+            Item::Glue { width: w, .. } => {
+                let (y, z) = item.glue_flex();
+                width += w;
+                stretch += y;
+                shrink += z;
+            }
+            Item::Kern { width: w } => width += w,
+            Item::Penalty { .. } | Item::BreakGlue { .. } => {}
+        }
+    }
+    (width, stretch, shrink)
+}
+
+/// Returns the index of the first box or tab in the paragraph, or its length if it has none. A
+/// break before this index would leave the first line without content, so such breaks are not
+/// legal, e.g. at a stray penalty at the start of the paragraph. A break at the paragraph's last
+/// item remains legal so that a paragraph without content can still be laid out. Kerns are not
+/// content: like glue, they only space the boxes around them, so a line of kerns is empty.
+fn first_content<S: ItemSource<N> + ?Sized, N: Num>(items: &S) -> usize {
+    (0..items.len())
+        .find(|&i| matches!(items.item(i), Item::Box { .. } | Item::Tab { .. }))
+        .unwrap_or(items.len())
+}
+
+/// Returns true if the item at index b follows a box or a tab, skipping over any zero-width boxes
+/// that precede it.
+fn follows_visible_box<S: ItemSource<N> + ?Sized, N: Num>(items: &S, b: usize) -> bool {
+    (0..b)
+        .rev()
+        .map(|i| items.item(i))
+        .find(|item| !matches!(item, Item::Box { width, .. } if *width == N::from(0)))
+        .is_some_and(|item| matches!(item, Item::Box { .. } | Item::Tab { .. }))
+}
+
+/// Returns true if the line that ends at `last` is an empty line that only exists because the
+/// paragraph ends with a forced break, i.e. if it follows a line that ends at a forced break and
+/// contains no boxes.
+fn is_trailing_empty_line<S: ItemSource<N> + ?Sized, N: Num>(
+    items: &S,
+    previous: Option<usize>,
+    last: usize,
+) -> bool {
+    match previous {
+        Some(p) => {
+            items.item(p).is_forced()
+                && !(p + 1..last).any(|i| matches!(items.item(i), Item::Box { .. }))
+        }
+        None => false,
+    }
+}
+
+/// Calculates the adjustment ratio for a line that consists of the items in `start..end` and
+/// breaks at `end`.
+fn range_adjustment_ratio<S: ItemSource<N> + ?Sized, N: Num>(
+    items: &S,
+    start: usize,
+    end: usize,
+    line_width: N,
+) -> N {
+    let (width, stretch, shrink) = sum_metrics(items, start..end);
+    items
+        .item(end)
+        .adjustment_ratio(width, stretch.value(), shrink.value(), line_width)
+}
+
+/// Breaks a line that begins at `start` and was broken at `end` again so that it fits within
+/// `line_width`, e.g. to leave room for an ellipsis after the last visible line of a truncated
+/// paragraph. The line is broken at the last legal breakpoint in `start + 1..=end` at which it fits
+/// once fully shrunk, and is set at its natural width unless it must shrink to fit. Returns `None`
+/// if the line does not fit at any of these breakpoints. If `trim_leading_glue` is set, the glue
+/// that precedes the line's first box is not measured, as when the line was laid out.
+fn truncated_line<S: ItemSource<N> + ?Sized, N: Num>(
+    items: &S,
+    start: usize,
+    end: usize,
+    line_width: N,
+    trim_leading_glue: bool,
+) -> Option<Line<N>> {
+    (start + 1..=end).rev().find_map(|b| {
+        let (_, _, _, is_legal) = items.item(b).is_legal_breakpoint(Some(&items.item(b - 1)));
+        let (mut width, mut stretch, mut shrink) = sum_metrics(items, start..b);
+        if trim_leading_glue {
+            let (trim_width, trim_stretch, trim_shrink) = leading_glue(items, start..b);
+            width -= trim_width;
+            stretch -= trim_stretch;
+            shrink -= trim_shrink;
+        }
+        let r = items
+            .item(b)
+            .adjustment_ratio(width, stretch.value(), shrink.value(), line_width);
+        (is_legal && r >= N::from(-1)).then(|| Line {
+            break_at: b,
+            adjustment_ratio: if r > N::from(0) { N::from(0) } else { r },
+            truncated: true,
+        })
+    })
+}
+
+/// A source of paragraph items. Layout algorithms only consult the metrics of items, so a source
+/// may produce items on demand from some other representation, e.g. a rope, rather than
+/// materializing the entire paragraph as a slice. The data of the items is not exposed.
+pub trait ItemSource<N: Num> {
+    /// Returns the number of items in the paragraph.
+    fn len(&self) -> usize;
+
+    /// Returns true if the paragraph has no items.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the item at the given index.
+    fn item(&self, index: usize) -> Item<(), (), (), N>;
+}
+
+impl<Box, Glue, Penalty, N: Num> ItemSource<N> for [Item<Box, Glue, Penalty, N>] {
+    fn len(&self) -> usize {
+        <[_]>::len(self)
+    }
+
+    fn item(&self, index: usize) -> Item<(), (), (), N> {
+        match self[index] {
+            Item::Box { width, .. } => Item::Box { width, data: () },
+            Item::Glue {
+                width,
+                stretch,
+                stretch_order,
+                shrink,
+                shrink_order,
+                priority,
+                discardable,
+                ..
+            } => Item::Glue {
+                width,
+                stretch,
+                stretch_order,
+                shrink,
+                shrink_order,
+                priority,
+                discardable,
+                data: (),
+            },
+            Item::Penalty {
+                width, cost, flags, ..
+            } => Item::Penalty {
+                width,
+                cost,
+                flags,
+                data: (),
+            },
+            Item::Kern { width } => Item::Kern { width },
+            Item::Tab { width } => Item::Tab { width },
+            Item::BreakGlue {
+                break_width,
+                stretch,
+                shrink,
+            } => Item::BreakGlue {
+                break_width,
+                stretch,
+                shrink,
+            },
+        }
+    }
+}
+
+impl<Box, Glue, Penalty, N: Num> ItemSource<N> for Vec<Item<Box, Glue, Penalty, N>> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn item(&self, index: usize) -> Item<(), (), (), N> {
+        self.as_slice().item(index)
+    }
+}
+
+/// The kind of an item described by `ItemMetrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    /// An unbreakable box, as by `Item::Box`.
+    Box,
+    /// Glue, as by `Item::Glue`.
+    Glue,
+    /// A penalty, as by `Item::Penalty`.
+    Penalty,
+}
+
+/// The metrics of a single item, as produced on demand by the accessor passed to
+/// `KnuthPlass::layout_by`. Only the fields that apply to the item's kind are consulted: boxes only
+/// have a width, glue has a width, stretch, and shrink with their orders, and whether it is
+/// discardable, and penalties have a width, cost, and flag. Glue has the lowest priority, which
+/// does not affect the choice of breaks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ItemMetrics<N = f32> {
+    /// The width of the item.
+    pub width: N,
+    /// The stretch of glue.
+    pub stretch: N,
+    /// The order of infinity of the stretch of glue.
+    pub stretch_order: GlueOrder,
+    /// The shrink of glue.
+    pub shrink: N,
+    /// The order of infinity of the shrink of glue.
+    pub shrink_order: GlueOrder,
+    /// Whether glue is discarded when a line breaks at it, as by `Item::Glue`.
+    pub discardable: bool,
+    /// The kind of the item.
+    pub kind: ItemKind,
+    /// The cost of a penalty.
+    pub cost: N,
+    /// Whether a penalty is flagged, as by `Item::flagged_penalty`.
+    pub flagged: bool,
+}
+
+/// An item source that maps each element of a slice to its metrics using an accessor.
+struct MappedItems<'a, T, F> {
+    elements: &'a [T],
+    to_item: F,
+}
+
+impl<T, F: Fn(&T) -> ItemMetrics<N>, N: Num> ItemSource<N> for MappedItems<'_, T, F> {
+    fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    fn item(&self, index: usize) -> Item<(), (), (), N> {
+        let metrics = (self.to_item)(&self.elements[index]);
+        match metrics.kind {
+            ItemKind::Box => Item::Box {
+                width: metrics.width,
+                data: (),
+            },
+            ItemKind::Glue => Item::Glue {
+                width: metrics.width,
+                stretch: metrics.stretch,
+                stretch_order: metrics.stretch_order,
+                shrink: metrics.shrink,
+                shrink_order: metrics.shrink_order,
+                priority: 0,
+                discardable: metrics.discardable,
+                data: (),
+            },
+            ItemKind::Penalty => {
+                Item::flagged_penalty(metrics.width, metrics.cost, metrics.flagged, ())
+            }
+        }
+    }
+}
+
+/// An item source that hides the stretch of a paragraph's finishing glue, i.e. the glue that
+/// precedes a final forced break, if the last line is to be justified like any other line.
+struct JustifiedLastLine<'a, S: ?Sized> {
+    items: &'a S,
+    finishing_glue: Option<usize>,
+}
+
+impl<'a, S: ?Sized> JustifiedLastLine<'a, S> {
+    fn new<N: Num>(items: &'a S, justify_last_line: bool) -> Self
+    where
+        S: ItemSource<N>,
+    {
+        let n = items.len();
+        let finishing_glue = (justify_last_line
+            && n >= 2
+            && items.item(n - 1).is_forced()
+            && matches!(items.item(n - 2), Item::Glue { .. }))
+        .then(|| n - 2);
+        JustifiedLastLine {
+            items,
+            finishing_glue,
+        }
+    }
+
+    /// Applies the same adjustment to a precomputed breakpoint for the item at the given index.
+    fn breakpoint<N: Num>(&self, index: usize, breakpoint: Breakpoint<N>) -> Breakpoint<N> {
+        let (width, stretch, shrink, is_legal) = breakpoint;
+        if self.finishing_glue == Some(index) {
+            (width, GlueSum::default(), shrink, is_legal)
+        } else {
+            (width, stretch, shrink, is_legal)
+        }
+    }
+}
+
+impl<S: ItemSource<N> + ?Sized, N: Num> ItemSource<N> for JustifiedLastLine<'_, S> {
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn item(&self, index: usize) -> Item<(), (), (), N> {
+        match self.items.item(index) {
+            Item::Glue {
+                width,
+                shrink,
+                shrink_order,
+                priority,
+                discardable,
+                ..
+            } if self.finishing_glue == Some(index) => Item::Glue {
+                width,
+                stretch: N::from(0),
+                stretch_order: GlueOrder::Normal,
+                shrink,
+                shrink_order,
+                priority,
+                discardable,
+                data: (),
+            },
+            item => item,
+        }
+    }
+}
+
+/// A single line of text as represented by its break point and adjustment ratio. Lines are
+/// usually produced by a layout; use `Line::new` and `Line::with_truncated` to create one directly,
+/// as more fields may be added in the future.
 #[derive(Debug, Default, Clone, Copy)]
+#[non_exhaustive]
 pub struct Line<N: Num = f32> {
     /// The index of the item at which to break this line.
     pub break_at: usize,
@@ -141,11 +911,33 @@ pub struct Line<N: Num = f32> {
     /// adjustment ratio is negative, glue should be adjusted by its shrink parameter. If the
     /// adjustment ratio is positive, glue should be adjusted by its stretch parameter. In general,
     pub adjustment_ratio: N,
+    /// Whether the paragraph was cut short after this line, e.g. by `KnuthPlass::with_max_lines`.
+    /// Only the last line of a truncated paragraph is marked as truncated.
+    pub truncated: bool,
 }
 
 impl<N: Num> Line<N> {
+    /// Creates a line that breaks at the item with the given index and is set with the given
+    /// adjustment ratio. The line is not truncated.
+    pub fn new(break_at: usize, adjustment_ratio: N) -> Self {
+        Line {
+            break_at,
+            adjustment_ratio,
+            truncated: false,
+        }
+    }
+
+    /// Marks the line as the last line of a truncated paragraph, or not, e.g. when re-creating a
+    /// layout that was stored elsewhere.
+    pub fn with_truncated(mut self, truncated: bool) -> Self {
+        self.truncated = truncated;
+        self
+    }
+
     /// Returns the width of a glue item with the given width, stretch, and shrink once the
-    /// adjustment ratio is taken into account.
+    /// adjustment ratio is taken into account. The adjustment ratio only applies to glue of
+    /// `GlueOrder::Normal`: the ratio of a line whose stretch or shrink is dominated by an infinite
+    /// order is 0, and `layout_positions` instead gives the line's slack to the glue of that order.
     pub fn glue_width(&self, width: N, stretch: N, shrink: N) -> N {
         if self.adjustment_ratio < N::from(0i16) {
             width + shrink * self.adjustment_ratio
@@ -155,15 +947,866 @@ impl<N: Num> Line<N> {
             width
         }
     }
+
+    /// Returns the factor by which a representative glue item with the given width, stretch, and
+    /// shrink is scaled on this line, e.g. 1.12 if spaces are set at 112% of their natural width.
+    /// This is intended for display to designers who are tuning spacing. Returns 1 for a line set
+    /// at its natural width or for glue with no natural width.
+    pub fn space_scale(&self, width: N, stretch: N, shrink: N) -> N {
+        if width == N::from(0) {
+            return N::from(1);
+        }
+        self.glue_width(width, stretch, shrink) / width
+    }
+
+    /// Returns the width of a glue item with the given width, stretch, and shrink once the line's
+    /// slack has been distributed according to `distribution`. `line_slack` is the difference
+    /// between the line width and the line's natural width, and `glue_count` is the number of glue
+    /// items on the line. Both are only consulted for uniform distribution.
+    pub fn glue_width_with(
+        &self,
+        width: N,
+        stretch: N,
+        shrink: N,
+        distribution: GlueDistribution,
+        line_slack: N,
+        glue_count: usize,
+    ) -> N {
+        match distribution {
+            GlueDistribution::Proportional => self.glue_width(width, stretch, shrink),
+            GlueDistribution::Uniform => {
+                if glue_count == 0 {
+                    width
+                } else {
+                    width + line_slack / N::from_usize(glue_count)
+                }
+            }
+        }
+    }
+}
+
+/// Describes how a line's slack is distributed among its glue items.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GlueDistribution {
+    /// Each glue item absorbs slack in proportion to its stretch or shrink parameter. Glue with
+    /// more stretch grows more, so e.g. a space after a sentence can widen faster than a space
+    /// between words.
+    #[default]
+    Proportional,
+    /// Each glue item absorbs the same absolute amount of slack regardless of its stretch or
+    /// shrink parameter. Every space on a line grows or shrinks by the same amount, which keeps
+    /// spacing visually even at the cost of ignoring the relative flexibility of each glue.
+    Uniform,
+}
+
+/// Describes why a paragraph could not be laid out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutError<N> {
+    /// The unbreakable run of items that starts with the box at `index` is wider than the line, so
+    /// no line that contains it can be set. This is typically an unbreakable token such as a URL
+    /// or a long word, whether it is set as a single box or as one box per glyph.
+    BoxTooWide {
+        /// The index of the first box of the run within the paragraph's items.
+        index: usize,
+        /// The width of the run, shrunk as far as possible.
+        width: N,
+    },
+    /// The paragraph has no feasible layout under the algorithm's configuration, e.g. because its
+    /// lines cannot be set within the adjustment ratio threshold.
+    Infeasible,
+    /// The algorithm exhausted its work budget before it could lay out the paragraph.
+    BudgetExceeded,
+}
+
+impl<N: Debug> Display for LayoutError<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::BoxTooWide { index, width } => {
+                write!(
+                    f,
+                    "the unbreakable run of width {width:?} at box {index} is wider than the line"
+                )
+            }
+            LayoutError::Infeasible => write!(f, "the paragraph has no feasible layout"),
+            LayoutError::BudgetExceeded => write!(f, "the layout exceeded its work budget"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<N: Debug> std::error::Error for LayoutError<N> {}
+
+/// Determines why a paragraph with the given items could not be laid out. `line_widths` are the
+/// widths available to the first line and to every other line, e.g. after a hanging indent. Like
+/// `overflow_amount`, this measures the unbreakable runs of items between consecutive legal
+/// breakpoints, fully shrunk.
+fn diagnose<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    line_widths: (N, N),
+) -> LayoutError<N> {
+    // A run may be set on the first line if it fits there together with everything that precedes
+    // it. Otherwise, it must be set on a later line, which requires a legal breakpoint before it.
+    let mut prefix_width = N::from(0);
+    let mut can_break = false;
+    let mut seen_content = false;
+    let mut run_start = None;
+    let (mut width, mut shrink) = (N::from(0), N::from(0));
+    for (index, item) in items.iter().enumerate() {
+        let pred = index.checked_sub(1).map(|i| &items[i]);
+        let (w, _, z, is_legal) = item.is_legal_breakpoint(pred);
+        let z = z.value();
+        match item {
+            Item::Box { .. } => {
+                run_start.get_or_insert(index);
+                seen_content = true;
+            }
+            Item::Tab { .. } => seen_content = true,
+            _ => {}
+        }
+        let run_width = if is_legal {
+            let (break_width, _, break_shrink) = item.break_metrics();
+            width - shrink + break_width - break_shrink
+        } else if index + 1 == items.len() {
+            width + w - (shrink + z)
+        } else {
+            width += w;
+            shrink += z;
+            continue;
+        };
+
+        if let Some(start) = run_start.take() {
+            let fits_first_line = prefix_width + run_width <= line_widths.0;
+            if !fits_first_line && (!can_break || run_width > line_widths.1) {
+                return LayoutError::BoxTooWide {
+                    index: start,
+                    width: run_width,
+                };
+            }
+        }
+        can_break |= seen_content && is_legal;
+        prefix_width += width - shrink;
+        if !matches!(item, Item::Penalty { .. }) {
+            prefix_width += w - z;
+        }
+        (width, shrink) = (N::from(0), N::from(0));
+    }
+    LayoutError::Infeasible
 }
 
 /// Represents a paragraph layout algorithm
 pub trait ParagraphLayout<Box = (), Glue = (), Penalty = (), N: Num = f32> {
     /// Lays out a paragraph with the given line width that consists of as list of items and
-    /// returns the laid-out lines.
+    /// returns the laid-out lines. An empty paragraph has no lines, so laying out an empty list of
+    /// items returns an empty `Vec` rather than panicking. Because an empty `Vec` also signals
+    /// that a paragraph could not be laid out, use `try_layout_paragraph` to tell the two apart.
     fn layout_paragraph(
         &self,
         items: &[Item<Box, Glue, Penalty, N>],
         line_width: N,
     ) -> Vec<Line<N>>;
+
+    /// Lays out a prepared paragraph with the given line width. This produces the same lines as
+    /// `layout_paragraph`, but algorithms may use the prepared metadata to avoid recomputing it.
+    fn layout_prepared(
+        &self,
+        prepared: &PreparedParagraph<Box, Glue, Penalty, N>,
+        line_width: N,
+    ) -> Vec<Line<N>> {
+        self.layout_paragraph(prepared.items(), line_width)
+    }
+
+    /// Lays out a paragraph like `layout_paragraph`, but returns an error that describes why the
+    /// paragraph could not be laid out rather than an empty list of lines.
+    fn try_layout_paragraph(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+    ) -> Result<Vec<Line<N>>, LayoutError<N>> {
+        let lines = self.layout_paragraph(items, line_width);
+        if lines.is_empty() && !items.is_empty() {
+            return Err(diagnose(items, (line_width, line_width)));
+        }
+        Ok(lines)
+    }
+
+    /// Returns the number of lines in the layout of a paragraph at each of the given line widths,
+    /// e.g. to choose a width for a responsive design. The count is 0 for widths at which the
+    /// paragraph could not be laid out. Algorithms may share work between widths.
+    fn line_counts_over_widths(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        widths: &[N],
+    ) -> Vec<usize> {
+        widths
+            .iter()
+            .map(|&line_width| self.layout_paragraph(items, line_width).len())
+            .collect()
+    }
+
+    /// Lays out a paragraph with the given line width and distributes its lines across `columns`
+    /// columns as evenly as possible by line count, e.g. for balanced multi-column text. Earlier
+    /// columns receive any extra lines. The lines keep their breaks into the paragraph's items.
+    ///
+    /// Returns one list of lines per column, or no columns if `columns` is 0. Every column is empty
+    /// if the paragraph could not be laid out.
+    fn balance_columns(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+        columns: usize,
+    ) -> Vec<Vec<Line<N>>> {
+        let lines = self.layout_paragraph(items, line_width);
+        let Some(per_column) = lines.len().checked_div(columns) else {
+            return Vec::new();
+        };
+        let extra = lines.len() % columns;
+
+        let mut lines = lines.into_iter();
+        (0..columns)
+            .map(|c| {
+                let count = per_column + usize::from(c < extra);
+                lines.by_ref().take(count).collect()
+            })
+            .collect()
+    }
+
+    /// Lays out each of a document's paragraphs with the given line width and returns the lines of
+    /// each paragraph. Paragraphs are broken independently of one another, and a paragraph that
+    /// could not be laid out has no lines. Use `document_height` to measure the result.
+    fn layout_document(
+        &self,
+        paragraphs: &[&[Item<Box, Glue, Penalty, N>]],
+        line_width: N,
+    ) -> Vec<Vec<Line<N>>> {
+        paragraphs
+            .iter()
+            .map(|items| self.layout_paragraph(items, line_width))
+            .collect()
+    }
+
+    /// Lays out a paragraph with the given line width and collects everything needed to render
+    /// it: the lines themselves, the range of items that makes up each line, and the natural width
+    /// of each line. Returns an empty paragraph if the paragraph could not be laid out.
+    fn layout_full(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+    ) -> LaidOutParagraph<N> {
+        let lines = self.layout_paragraph(items, line_width);
+        let mut ranges = Vec::with_capacity(lines.len());
+        let mut widths = Vec::with_capacity(lines.len());
+        let mut start = 0;
+        for line in &lines {
+            let (width, _, _) = sum_metrics(items, start..line.break_at);
+            widths.push(width + items[line.break_at].break_metrics().0);
+            ranges.push(start..line.break_at);
+            start = line.break_at + 1;
+        }
+        LaidOutParagraph {
+            lines,
+            ranges,
+            widths,
+        }
+    }
+
+    /// Lays out a paragraph with the given line width and returns the position and width of each
+    /// of its boxes, e.g. as a flat buffer of rectangles for a renderer. Boxes are positioned as
+    /// by `layout_positions` with the default options, and each line is placed `line_height` below
+    /// the previous one, starting at 0. Glue, penalties, and other items are omitted.
+    ///
+    /// The quads are sorted by line and then by x-offset. Returns no quads if the paragraph could
+    /// not be laid out.
+    fn layout_to_quads(
+        &self,
+        items: &[Item<Box, Glue, Penalty, N>],
+        line_width: N,
+        line_height: N,
+    ) -> Vec<Quad<N>> {
+        let lines = self.layout_paragraph(items, line_width);
+        let positions = layout_positions(items, &lines, line_width, PositionOptions::default());
+
+        let mut quads = Vec::new();
+        let (mut start, mut y) = (0, N::from(0));
+        for (line, offsets) in lines.iter().zip(positions) {
+            let first = quads.len();
+            for (i, x) in offsets.into_iter().enumerate() {
+                if let Some(&Item::Box { width, .. }) = items.get(start + i) {
+                    quads.push(Quad {
+                        x,
+                        y,
+                        width,
+                        item_index: start + i,
+                    });
+                }
+            }
+            // Negative kerns can move a box to the left of its predecessor.
+            quads[first..].sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal));
+            start = line.break_at + 1;
+            y += line_height;
+        }
+        quads
+    }
+}
+
+/// The position and width of a box in a laid-out paragraph, as returned by
+/// `ParagraphLayout::layout_to_quads`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Quad<N: Num = f32> {
+    /// The x-offset of the box, relative to the left edge of the paragraph.
+    pub x: N,
+    /// The y-offset of the top of the box's line, relative to the top of the paragraph.
+    pub y: N,
+    /// The width of the box.
+    pub width: N,
+    /// The index of the box within the paragraph's items.
+    pub item_index: usize,
+}
+
+/// A laid-out paragraph, as returned by `ParagraphLayout::layout_full`. The ranges and widths
+/// correspond one-to-one with the lines.
+#[derive(Debug, Default, Clone)]
+pub struct LaidOutParagraph<N: Num = f32> {
+    /// The paragraph's lines.
+    pub lines: Vec<Line<N>>,
+    /// The range of items that makes up each line. A line's range spans the items from the
+    /// previous line's break up to, but not including, the line's own break.
+    pub ranges: Vec<Range<usize>>,
+    /// The natural width of each line, i.e. its width before its glue is adjusted. This includes
+    /// the width of a penalty at which the line breaks, but not the width of discardable glue at
+    /// which it breaks.
+    pub widths: Vec<N>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::monospace;
+    use fixed::types::I32F32;
+
+    /// Produces the same items as `monospace` on demand.
+    struct MonospaceSource<'a>(&'a [u8]);
+
+    impl ItemSource<f32> for MonospaceSource<'_> {
+        fn len(&self) -> usize {
+            self.0.len() + 2
+        }
+
+        fn item(&self, index: usize) -> Item {
+            match self.0.get(index) {
+                Some(c) if c.is_ascii_whitespace() && index != 0 => Item::glue(1.0, 1.0, 0.0, ()),
+                Some(_) => Item::Box {
+                    width: 1.0,
+                    data: (),
+                },
+                None if index == self.0.len() => Item::glue(0.0, 100000.0, 0.0, ()),
+                None => Item::flagged_penalty(0.0, f32::NEG_INFINITY, true, ()),
+            }
+        }
+    }
+
+    #[test]
+    fn item_source() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items = monospace(text);
+        let source = MonospaceSource(text.as_bytes());
+
+        let breaks = |lines: Vec<Line>| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        assert_eq!(
+            breaks(first_fit.layout_source(&source, 20.0)),
+            breaks(first_fit.layout_paragraph(&items, 20.0))
+        );
+        let knuth_plass = KnuthPlass::new();
+        assert_eq!(
+            breaks(knuth_plass.layout_source(&source, 20.0)),
+            breaks(knuth_plass.layout_paragraph(&items, 20.0))
+        );
+    }
+
+    #[test]
+    fn glue_distribution() {
+        let line = Line {
+            break_at: 0,
+            adjustment_ratio: 0.5,
+            truncated: false,
+        };
+
+        // Six units of slack shared between glue with stretch 2 and glue with stretch 10.
+        let (proportional_a, proportional_b) = (
+            line.glue_width_with(1.0, 2.0, 0.0, GlueDistribution::Proportional, 6.0, 2),
+            line.glue_width_with(1.0, 10.0, 0.0, GlueDistribution::Proportional, 6.0, 2),
+        );
+        assert_eq!((proportional_a, proportional_b), (2.0, 6.0));
+
+        let (uniform_a, uniform_b) = (
+            line.glue_width_with(1.0, 2.0, 0.0, GlueDistribution::Uniform, 6.0, 2),
+            line.glue_width_with(1.0, 10.0, 0.0, GlueDistribution::Uniform, 6.0, 2),
+        );
+        assert_eq!((uniform_a, uniform_b), (4.0, 4.0));
+
+        // Counts beyond the range of i16 are not saturated.
+        let uniform =
+            line.glue_width_with(1.0, 2.0, 0.0, GlueDistribution::Uniform, 40000.0, 40000);
+        assert_eq!(uniform, 2.0);
+    }
+
+    #[test]
+    fn space_scale() {
+        let line = |adjustment_ratio| Line {
+            break_at: 0,
+            adjustment_ratio,
+            truncated: false,
+        };
+        assert_eq!(line(0.0).space_scale(4.0, 2.0, 1.0), 1.0);
+        assert_eq!(line(0.25).space_scale(4.0, 2.0, 1.0), 1.125);
+        assert_eq!(line(-0.5).space_scale(4.0, 2.0, 1.0), 0.875);
+        assert_eq!(line(0.5).space_scale(0.0, 2.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn penalty_flags() {
+        let (none, consecutive, before, after) = (
+            PenaltyFlags::NONE,
+            PenaltyFlags::AVOID_CONSECUTIVE,
+            PenaltyFlags::BEFORE,
+            PenaltyFlags::AFTER,
+        );
+        assert!(PenaltyFlags::conflict(consecutive, consecutive));
+        assert!(!PenaltyFlags::conflict(consecutive, none));
+        assert!(!PenaltyFlags::conflict(consecutive, before));
+        assert!(PenaltyFlags::conflict(before, after));
+        assert!(PenaltyFlags::conflict(before, consecutive));
+        assert!(!PenaltyFlags::conflict(after, before));
+        assert!(!PenaltyFlags::conflict(none, after));
+        assert!(PenaltyFlags::conflict(consecutive | before, after));
+
+        assert!(Item::<(), (), (), f32>::flagged_penalty(0.0, 50.0, true, ()).is_flagged());
+        assert!(!Item::<(), (), (), f32>::penalty(50.0, ()).is_flagged());
+        assert!(!Item::<(), (), (), f32>::Penalty {
+            width: 0.0,
+            cost: 50.0,
+            flags: PenaltyFlags::LAST_RESORT,
+            data: (),
+        }
+        .is_flagged());
+    }
+
+    #[test]
+    fn break_constructors() {
+        let forced = Item::<(), (), (), f32>::forced_break(());
+        assert!(forced.is_forced() && !forced.is_forbidden());
+        let forbidden = Item::<(), (), (), f32>::forbidden_break(());
+        assert!(forbidden.is_forbidden() && !forbidden.is_forced());
+
+        // The sentinels follow the numeric backend.
+        let forced = Item::<(), (), (), Fixed<I32F32>>::forced_break(());
+        assert!(matches!(forced, Item::Penalty { cost, .. } if cost == Fixed::MIN));
+        assert!(forced.is_forced());
+        let forbidden = Item::<(), (), (), Fixed<I32F32>>::forbidden_break(());
+        assert!(matches!(forbidden, Item::Penalty { cost, .. } if cost == Fixed::MAX));
+        assert!(forbidden.is_forbidden());
+
+        assert!(!Item::<(), (), (), f32>::penalty(50.0, ()).is_forced());
+        assert!(matches!(
+            Item::<(), (), (), f32>::penalty(50.0, ()),
+            Item::Penalty { width, cost, flags, .. }
+                if width == 0.0 && cost == 50.0 && flags == PenaltyFlags::NONE
+        ));
+        assert!(Item::<(), (), (), f32>::penalty(f32::NEG_INFINITY, ()).is_forced());
+        assert!(!Item::<(), (), (), f32>::Box {
+            width: 1.0,
+            data: ()
+        }
+        .is_forbidden());
+    }
+
+    #[test]
+    fn try_layout_paragraph() {
+        // Set each word as a single box.
+        let mut items = Vec::new();
+        for word in "Details are at https://example.com/a/long/path".split(' ') {
+            if !items.is_empty() {
+                items.push(Item::glue(1.0, 1.0, 0.0, ()));
+            }
+            items.push(Item::Box {
+                width: word.len() as f32,
+                data: (),
+            });
+        }
+        items.extend(monospace(""));
+
+        for layout in [
+            &FirstFit::new().with_threshold(f32::INFINITY) as &dyn ParagraphLayout,
+            &KnuthPlass::new().with_threshold(f32::INFINITY),
+        ] {
+            assert_eq!(
+                layout.try_layout_paragraph(&items, 20.0).unwrap_err(),
+                LayoutError::BoxTooWide {
+                    index: 6,
+                    width: 31.0
+                }
+            );
+            assert_eq!(layout.try_layout_paragraph(&items, 34.0).unwrap().len(), 2);
+        }
+        assert_eq!(
+            KnuthPlass::new()
+                .try_layout_paragraph(&items, 34.0)
+                .unwrap_err(),
+            LayoutError::Infeasible
+        );
+
+        // A box is measured against the width of the lines it may be set on.
+        for layout in [
+            &FirstFit::new()
+                .with_threshold(f32::INFINITY)
+                .with_hanging_indent(0.0, 10.0) as &dyn ParagraphLayout,
+            &KnuthPlass::new()
+                .with_threshold(f32::INFINITY)
+                .with_hanging_indent(0.0, 10.0),
+        ] {
+            assert_eq!(
+                layout.try_layout_paragraph(&items, 34.0).unwrap_err(),
+                LayoutError::BoxTooWide {
+                    index: 6,
+                    width: 31.0
+                }
+            );
+        }
+        for layout in [
+            &FirstFit::new()
+                .with_threshold(f32::INFINITY)
+                .with_hanging_indent(30.0, 0.0) as &dyn ParagraphLayout,
+            &KnuthPlass::new()
+                .with_threshold(f32::INFINITY)
+                .with_hanging_indent(30.0, 0.0),
+        ] {
+            assert_eq!(
+                layout.try_layout_paragraph(&items, 34.0).unwrap_err(),
+                LayoutError::BoxTooWide {
+                    index: 0,
+                    width: 7.0
+                }
+            );
+        }
+
+        // A word set as one box per glyph is measured as a whole, wherever it appears.
+        for layout in [
+            &FirstFit::new().with_threshold(f32::INFINITY) as &dyn ParagraphLayout,
+            &KnuthPlass::new().with_threshold(f32::INFINITY),
+        ] {
+            for (text, index) in [("abcdefghij bb", 0), ("bb abcdefghij", 3)] {
+                assert_eq!(
+                    layout
+                        .try_layout_paragraph(&monospace(text), 5.0)
+                        .unwrap_err(),
+                    LayoutError::BoxTooWide { index, width: 10.0 }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn balance_columns() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = knuth_plass.layout_paragraph(&items, 10.0);
+        assert_eq!(lines.len(), 5);
+
+        let columns = knuth_plass.balance_columns(&items, 10.0, 2);
+        assert_eq!(columns.iter().map(|c| c.len()).collect::<Vec<_>>(), [3, 2]);
+        let breaks = columns.iter().flatten().map(|l| l.break_at);
+        assert!(breaks.eq(lines.iter().map(|l| l.break_at)));
+
+        let columns = knuth_plass.balance_columns(&items, 10.0, 7);
+        assert_eq!(
+            columns.iter().map(|c| c.len()).collect::<Vec<_>>(),
+            [1, 1, 1, 1, 1, 0, 0]
+        );
+        assert!(knuth_plass.balance_columns(&items, 10.0, 0).is_empty());
+    }
+
+    #[test]
+    fn layout_document() {
+        let first = monospace("The quick brown fox jumps over the lazy dog.");
+        let second = monospace("Pack my box with five dozen liquor jugs.");
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let document = knuth_plass.layout_document(&[&first, &second, &[]], 20.0);
+        assert_eq!(document.len(), 3);
+        assert_eq!(
+            document[1].iter().map(|l| l.break_at).collect::<Vec<_>>(),
+            knuth_plass
+                .layout_paragraph(&second, 20.0)
+                .iter()
+                .map(|l| l.break_at)
+                .collect::<Vec<_>>()
+        );
+
+        // Six lines of height 2, plus the spacing between the two non-empty paragraphs.
+        assert_eq!(document.iter().map(Vec::len).sum::<usize>(), 6);
+        assert_eq!(document_height(&document, 2.0, 1.5), 13.5);
+    }
+
+    #[test]
+    fn layout_full() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let knuth_plass = KnuthPlass::new();
+        let paragraph = knuth_plass.layout_full(&items, 20.0);
+        let lines = knuth_plass.layout_paragraph(&items, 20.0);
+        assert!(paragraph
+            .lines
+            .iter()
+            .map(|l| l.break_at)
+            .eq(lines.iter().map(|l| l.break_at)));
+        assert_eq!(paragraph.ranges, [0..19, 20..39, 40..45]);
+        assert_eq!(paragraph.widths, [19.0, 19.0, 4.0]);
+
+        assert!(knuth_plass.layout_full(&items, 3.0).lines.is_empty());
+    }
+
+    #[test]
+    fn layout_to_quads() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let quads = KnuthPlass::new().layout_to_quads(&items, 20.0, 2.0);
+        assert_eq!(quads.len(), 36);
+        assert_eq!(
+            quads[1],
+            Quad {
+                x: 1.0,
+                y: 0.0,
+                width: 1.0,
+                item_index: 1,
+            }
+        );
+        // "The quick brown fox" has one unit of slack shared by three spaces.
+        assert_eq!(quads[3].item_index, 4);
+        assert!((quads[3].x - (4.0 + 1.0 / 3.0)).abs() < 1e-5);
+        let last = quads[35];
+        assert_eq!((last.y, last.item_index), (4.0, 43));
+        assert!(KnuthPlass::new()
+            .layout_to_quads(&items, 3.0, 1.0)
+            .is_empty());
+
+        // A negative kern moves the box that follows it to the left of its predecessor.
+        let mut items = monospace("ab");
+        items.insert(1, Item::Kern { width: -2.0 });
+        let quads = FirstFit::new()
+            .with_threshold(f32::INFINITY)
+            .layout_to_quads(&items, 20.0, 1.0);
+        assert_eq!(
+            quads.iter().map(|q| q.item_index).collect::<Vec<_>>(),
+            [2, 0]
+        );
+    }
+
+    #[test]
+    fn empty_paragraph() {
+        let items: [Item; 0] = [];
+        let prepared = PreparedParagraph::new(&items);
+        for layout in [&FirstFit::new() as &dyn ParagraphLayout, &KnuthPlass::new()] {
+            assert!(layout.layout_paragraph(&items, 20.0).is_empty());
+            assert!(layout.layout_prepared(&prepared, 20.0).is_empty());
+            assert!(layout
+                .try_layout_paragraph(&items, 20.0)
+                .unwrap()
+                .is_empty());
+            assert_eq!(layout.line_counts_over_widths(&items, &[0.0, 20.0]), [0, 0]);
+        }
+
+        let mut streamed = 0;
+        assert!(FirstFit::new().layout_with(&items, 20.0, |_| streamed += 1));
+        assert_eq!(streamed, 0);
+        assert!(FirstFit::new()
+            .reusable_layout()
+            .run(&items, 20.0)
+            .is_empty());
+
+        let knuth_plass = KnuthPlass::new();
+        let (lines, stats) = knuth_plass.layout_paragraph_with_stats(&items, 20.0);
+        assert!(lines.is_empty());
+        assert_eq!(stats, LayoutStats::default());
+        assert!(knuth_plass.layout_chain(&items, 20.0).is_empty());
+    }
+
+    #[test]
+    fn kern() {
+        // Tighten every pair of adjacent letters with a negative kern.
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let mut items = Vec::new();
+        let mut words = Vec::new();
+        for item in monospace(text) {
+            if let (Some(Item::Box { .. }), Item::Box { .. }) = (items.last(), &item) {
+                items.push(Item::Kern { width: -0.125 });
+            }
+            if let Item::Glue { .. } = item {
+                words.push(items.len());
+            }
+            items.push(item);
+        }
+
+        for layout in [
+            &FirstFit::new().with_threshold(f32::INFINITY) as &dyn ParagraphLayout,
+            &KnuthPlass::new().with_threshold(f32::INFINITY),
+        ] {
+            // Without kerns, "The quick brown fox" is too wide for the first line.
+            assert_ne!(
+                layout.layout_paragraph(&monospace(text), 18.0)[0].break_at,
+                19
+            );
+
+            let lines = layout.layout_paragraph(&items, 18.0);
+            assert_eq!(lines[0].break_at, words[3]);
+            assert!(lines
+                .iter()
+                .all(|l| !matches!(items[l.break_at], Item::Kern { .. })));
+        }
+    }
+
+    #[test]
+    fn break_glue() {
+        // Two words separated by a space that is preceded by break glue, which only takes up space
+        // at the end of a broken line.
+        let items: Vec<Item> = alloc::vec![
+            Item::Box {
+                width: 5.0,
+                data: (),
+            },
+            Item::BreakGlue {
+                break_width: 3.0,
+                stretch: 0.0,
+                shrink: 0.0,
+            },
+            Item::glue(1.0, 1.0, 0.0, ()),
+            Item::Box {
+                width: 5.0,
+                data: (),
+            },
+            Item::glue(0.0, f32::INFINITY, 0.0, ()),
+            Item::forced_break(()),
+        ];
+
+        for layout in [
+            &FirstFit::new().with_threshold(f32::INFINITY) as &dyn ParagraphLayout,
+            &KnuthPlass::new().with_threshold(f32::INFINITY),
+        ] {
+            // Unbroken, the break glue takes up no space.
+            let lines = layout.layout_paragraph(&items, 11.0);
+            assert_eq!(lines.len(), 1);
+            let positions = layout_positions(&items, &lines, 11.0, PositionOptions::default());
+            assert_eq!(positions, [[0.0, 5.0, 5.0, 6.0, 11.0, 11.0]]);
+
+            // Broken, the first line is exactly as wide as the first word and the break width.
+            let lines = layout.layout_paragraph(&items, 8.0);
+            assert_eq!(lines.iter().map(|l| l.break_at).collect::<Vec<_>>(), [1, 5]);
+            assert_eq!(lines[0].adjustment_ratio, 0.0);
+        }
+    }
+
+    #[test]
+    fn infinite_glue() {
+        // Two forced segments, the first of which ends with infinitely stretchable finishing glue.
+        // The interword glue of the second may stretch without bound but may only shrink by one
+        // unit per space.
+        let mut items = monospace("Hello.");
+        items[6] = Item::glue(0.0, f32::INFINITY, 0.0, ());
+        items[7] = Item::forced_break(());
+        items.extend(
+            monospace("The quick brown fox")
+                .into_iter()
+                .map(|item| match item {
+                    Item::Glue { width, .. } if width == 1.0 => {
+                        Item::glue(width, f32::INFINITY, 1.0, ())
+                    }
+                    item => item,
+                }),
+        );
+
+        let breaks = |lines: &[Line]| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let knuth_plass = KnuthPlass::new();
+
+        // The second line stretches freely...
+        for layout in [&first_fit as &dyn ParagraphLayout, &knuth_plass] {
+            let lines = layout.layout_paragraph(&items, 21.0);
+            assert_eq!(breaks(&lines), [7, 28]);
+            assert_eq!(lines[1].adjustment_ratio, 0.0);
+        }
+
+        // ...but only shrinks within its finite limit.
+        let lines = first_fit.layout_paragraph(&items, 17.0);
+        assert_eq!(breaks(&lines), [7, 28]);
+        assert!((lines[1].adjustment_ratio - -2.0 / 3.0).abs() < 1e-5);
+        let lines = first_fit.layout_paragraph(&items, 15.0);
+        assert_eq!(breaks(&lines), [7, 23, 28]);
+
+        // Knuth-Plass prefers perfectly-set lines with infinite stretch to a shrunken line.
+        let lines = knuth_plass.layout_paragraph(&items, 17.0);
+        assert_eq!(breaks(&lines), [7, 17, 28]);
+        assert!(lines.iter().all(|l| l.adjustment_ratio == 0.0));
+    }
+
+    #[test]
+    fn glue_orders() {
+        // The dominant order of each side is picked independently, and opposite amounts of the
+        // same order cancel out.
+        let mut sum = GlueSum::of(1.0, GlueOrder::Fil);
+        sum += GlueSum::of(2.0, GlueOrder::Normal);
+        assert_eq!(sum.value(), f32::INFINITY);
+        sum -= GlueSum::of(1.0, GlueOrder::Fill);
+        assert_eq!(sum.value(), f32::NEG_INFINITY);
+        sum += GlueSum::of(1.0, GlueOrder::Fill);
+        sum -= GlueSum::of(1.0, GlueOrder::Fil);
+        assert_eq!(sum.value(), 2.0);
+
+        // Interword glue whose stretch is of the first infinite order but whose shrink is finite.
+        let items: Vec<Item> = monospace("The quick brown fox")
+            .into_iter()
+            .map(|item| match item {
+                Item::Glue { width, .. } if width == 1.0 => Item::Glue {
+                    width,
+                    stretch: 1.0,
+                    stretch_order: GlueOrder::Fil,
+                    shrink: 1.0,
+                    shrink_order: GlueOrder::Normal,
+                    priority: 0,
+                    discardable: true,
+                    data: (),
+                },
+                item => item,
+            })
+            .collect();
+
+        let breaks = |lines: &[Line]| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let knuth_plass = KnuthPlass::new();
+
+        // The line stretches freely...
+        for layout in [&first_fit as &dyn ParagraphLayout, &knuth_plass] {
+            let lines = layout.layout_paragraph(&items, 30.0);
+            assert_eq!(breaks(&lines), [20]);
+            assert_eq!(lines[0].adjustment_ratio, 0.0);
+        }
+
+        // ...but only shrinks within its finite limit.
+        let lines = first_fit.layout_paragraph(&items, 17.0);
+        assert_eq!(breaks(&lines), [20]);
+        assert!((lines[0].adjustment_ratio - -2.0 / 3.0).abs() < 1e-5);
+        let lines = first_fit.layout_paragraph(&items, 15.0);
+        assert_eq!(breaks(&lines), [15, 20]);
+
+        // Knuth-Plass prefers perfectly-set lines with infinite stretch to a shrunken line.
+        let lines = knuth_plass.layout_paragraph(&items, 17.0);
+        assert_eq!(breaks(&lines), [9, 20]);
+        assert!(lines.iter().all(|l| l.adjustment_ratio == 0.0));
+    }
+
+    #[test]
+    fn line_constructors() {
+        let line = Line::new(3, 0.5);
+        assert_eq!(
+            (line.break_at, line.adjustment_ratio, line.truncated),
+            (3, 0.5, false)
+        );
+        assert!(line.with_truncated(true).truncated);
+        assert!(!line.with_truncated(true).with_truncated(false).truncated);
+    }
 }
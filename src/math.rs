@@ -1,7 +1,7 @@
 use core::cmp::{Ordering, PartialOrd};
 use core::fmt::{self, Debug};
 use core::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
-use fixed::traits::{FixedSigned, ToFixed};
+use fixed::traits::{FixedSigned, FromFixed, ToFixed};
 
 /// A trait that describes the operations necessary for this crate's layout algorithms.
 pub trait Num
@@ -22,11 +22,76 @@ where
 
     fn from(i: i16) -> Self;
     fn abs(self) -> Self;
-    fn powi(self, y: u32) -> Self;
+
+    /// Raises `self` to the power `y` by repeated multiplication. Every implementation shares this
+    /// definition so that demerits, and therefore break decisions, are identical regardless of
+    /// whether `f32` is backed by `std` or `libm`.
+    fn powi(self, y: u32) -> Self {
+        let mut result = Self::from(1);
+        for _ in 0..y {
+            result = result * self;
+        }
+        result
+    }
 
     fn rat(num: i16, denom: i16) -> Self {
         Self::from(num) / Self::from(denom)
     }
+
+    /// Converts a count, e.g. of glue items, to a number. Counts beyond the range of `i16` are
+    /// assembled by repeated addition, so the conversion is exact wherever the type can represent
+    /// the count; implementations should override it with a direct conversion where one is
+    /// available.
+    fn from_usize(n: usize) -> Self {
+        let max = i16::MAX as usize;
+        let mut result = Self::from((n % max) as i16);
+        for _ in 0..n / max {
+            result += Self::from(i16::MAX);
+        }
+        result
+    }
+
+    /// Returns the default tolerance for comparisons of adjustment ratios against the bounds of
+    /// feasibility, i.e. -1 and the threshold, which absorbs the rounding error that accumulates in
+    /// running totals of widths. Defaults to 0, which is appropriate for exact types.
+    fn feasibility_epsilon() -> Self {
+        Self::from(0)
+    }
+
+    /// Rounds `self` to the nearest integer, with halves rounded away from zero. Values beyond the
+    /// range of `i64`, including infinities, saturate, and NaN rounds to 0. The default
+    /// implementation assembles the integer one bit at a time, so implementations should override
+    /// it with a direct conversion where one is available.
+    fn round_to_i64(self) -> i64 {
+        let zero = Self::from(0);
+        let negative = self < zero;
+        let mut rest = if negative { zero - self } else { self } + Self::rat(1, 2);
+        if rest.partial_cmp(&zero).is_none() {
+            return 0;
+        }
+
+        let mut powers = [zero; 63];
+        let mut power = Self::from(1);
+        for p in &mut powers {
+            *p = power;
+            power = power + power;
+        }
+        if rest >= power {
+            return if negative { i64::MIN } else { i64::MAX };
+        }
+        let mut result = 0i64;
+        for (bit, &p) in powers.iter().enumerate().rev() {
+            if rest >= p {
+                rest -= p;
+                result |= 1 << bit;
+            }
+        }
+        if negative {
+            -result
+        } else {
+            result
+        }
+    }
 }
 
 #[cfg(feature = "std")]
@@ -42,8 +107,16 @@ impl Num for f32 {
         self.abs()
     }
 
-    fn powi(self, y: u32) -> f32 {
-        self.powi(y as i32)
+    fn feasibility_epsilon() -> f32 {
+        1e-5
+    }
+
+    fn from_usize(n: usize) -> f32 {
+        n as f32
+    }
+
+    fn round_to_i64(self) -> i64 {
+        self.round() as i64
     }
 }
 
@@ -60,8 +133,16 @@ impl Num for f32 {
         libm::fabsf(self)
     }
 
-    fn powi(self, y: u32) -> f32 {
-        libm::powf(self, y as f32)
+    fn feasibility_epsilon() -> f32 {
+        1e-5
+    }
+
+    fn from_usize(n: usize) -> f32 {
+        n as f32
+    }
+
+    fn round_to_i64(self) -> i64 {
+        libm::roundf(self) as i64
     }
 }
 
@@ -139,6 +220,18 @@ impl<F: FixedSigned> Fixed<F> {
     pub fn from_num<Src: ToFixed>(src: Src) -> Self {
         Fixed(F::from_num(src))
     }
+
+    pub fn saturating_from_num<Src: ToFixed>(src: Src) -> Self {
+        Fixed(F::saturating_from_num(src))
+    }
+
+    pub fn to_num<Dst: FromFixed>(self) -> Dst {
+        self.0.to_num()
+    }
+
+    pub fn saturating_round(self) -> Self {
+        Fixed(self.0.saturating_round())
+    }
 }
 
 impl<F: FixedSigned> Num for Fixed<F> {
@@ -153,11 +246,117 @@ impl<F: FixedSigned> Num for Fixed<F> {
         Fixed(self.0.abs())
     }
 
-    fn powi(self, y: u32) -> Self {
-        let mut result: Self = Self::from_num(1);
-        for _ in 0..y {
-            result = result * self;
+    fn from_usize(n: usize) -> Self {
+        Fixed(F::saturating_from_num(n))
+    }
+
+    fn round_to_i64(self) -> i64 {
+        self.0.saturating_round().saturating_to_num()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Uses the default implementations of the provided methods of `Num`.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+    struct Generic(f32);
+
+    impl core::ops::Add for Generic {
+        type Output = Self;
+        fn add(self, rhs: Self) -> Self {
+            Generic(self.0 + rhs.0)
         }
-        result
+    }
+    impl core::ops::AddAssign for Generic {
+        fn add_assign(&mut self, rhs: Self) {
+            self.0 += rhs.0;
+        }
+    }
+    impl core::ops::Sub for Generic {
+        type Output = Self;
+        fn sub(self, rhs: Self) -> Self {
+            Generic(self.0 - rhs.0)
+        }
+    }
+    impl core::ops::SubAssign for Generic {
+        fn sub_assign(&mut self, rhs: Self) {
+            self.0 -= rhs.0;
+        }
+    }
+    impl core::ops::Mul for Generic {
+        type Output = Self;
+        fn mul(self, rhs: Self) -> Self {
+            Generic(self.0 * rhs.0)
+        }
+    }
+    impl core::ops::Div for Generic {
+        type Output = Self;
+        fn div(self, rhs: Self) -> Self {
+            Generic(self.0 / rhs.0)
+        }
+    }
+    impl Num for Generic {
+        const INFINITY: Self = Generic(f32::INFINITY);
+        const NEG_INFINITY: Self = Generic(f32::NEG_INFINITY);
+        fn from(i: i16) -> Self {
+            Generic(i.into())
+        }
+        fn abs(self) -> Self {
+            Generic(Num::abs(self.0))
+        }
+    }
+
+    #[test]
+    fn powi_matches_multiplication() {
+        // The demerits of a line with adjustment ratio 0.7 must not depend on the enabled
+        // features, so compare against the same computation written out by hand.
+        let r = 0.7f32;
+        let badness = 1.0 + 100.0 * (r * r * r);
+        let demerits = Num::powi(1.0 + 100.0 * Num::powi(r, 3), 2);
+        assert_eq!(demerits.to_bits(), (badness * badness).to_bits());
+        assert_eq!(Num::powi(-2.0f32, 0), 1.0);
+    }
+
+    #[test]
+    fn from_usize() {
+        // The default implementation is exact beyond the range of i16.
+        for n in [0, 1, 32767, 32768, 100000] {
+            assert_eq!(Generic::from_usize(n), Generic(n as f32));
+            assert_eq!(f32::from_usize(n), n as f32);
+        }
+        assert_eq!(
+            Fixed::<fixed::types::I32F32>::from_usize(100000),
+            Fixed::from_num(100000)
+        );
+        assert_eq!(
+            Fixed::<fixed::types::I8F8>::from_usize(100000),
+            Fixed::<fixed::types::I8F8>::MAX
+        );
+    }
+
+    #[test]
+    fn round_to_i64() {
+        for x in [
+            0.0,
+            2.4,
+            2.5,
+            -2.5,
+            1234.567,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+        ] {
+            assert_eq!(Generic(x).round_to_i64(), x.round_to_i64());
+        }
+        assert_eq!(
+            Fixed::<fixed::types::I32F32>::from_num(-2.5).round_to_i64(),
+            -3
+        );
+        assert_eq!(
+            Fixed::<fixed::types::I32F32>::MAX.round_to_i64(),
+            i32::MAX as i64
+        );
     }
 }
@@ -0,0 +1,171 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use crate::math::Num;
+use crate::{Breakpoint, GlueSum, Item};
+
+/// A paragraph whose layout-independent metadata has been computed ahead of time. Preparing a
+/// paragraph once avoids recomputing this metadata when the same items are laid out repeatedly at
+/// different line widths, e.g. for responsive designs.
+///
+/// If the `serde` feature is enabled, a prepared paragraph can be serialized alongside its items,
+/// e.g. so that a build tool can cache it on disk. A deserialized paragraph owns its items, and
+/// deserialization fails if its cached metadata does not cover exactly its items.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PreparedParagraph<'a, Box = (), Glue = (), Penalty = (), N = f32> {
+    items: Items<'a, Item<Box, Glue, Penalty, N>>,
+    /// The width, stretch, and shrink of each item, and whether or not it is a legal breakpoint.
+    breakpoints: Vec<Breakpoint<N>>,
+}
+
+impl<'a, Box, Glue, Penalty, N: Num> PreparedParagraph<'a, Box, Glue, Penalty, N> {
+    /// Prepares the given items for layout.
+    pub fn new(items: &'a [Item<Box, Glue, Penalty, N>]) -> Self {
+        let breakpoints: Vec<Breakpoint<N>> = items
+            .iter()
+            .enumerate()
+            .map(|(b, item)| item.is_legal_breakpoint((b != 0).then(|| &items[b - 1])))
+            .collect();
+
+        PreparedParagraph {
+            items: Items::Borrowed(items),
+            breakpoints,
+        }
+    }
+
+    /// Returns the paragraph's items.
+    pub fn items(&self) -> &[Item<Box, Glue, Penalty, N>] {
+        &self.items
+    }
+
+    /// Returns the total width, stretch, and shrink of the items in `start..end`. Infinite stretch
+    /// and shrink dominate any finite amount.
+    pub fn sum_metrics(&self, start: usize, end: usize) -> (N, N, N) {
+        let mut sums = (N::from(0), GlueSum::default(), GlueSum::default());
+        for &(width, stretch, shrink, _) in &self.breakpoints[start..end] {
+            sums.0 += width;
+            sums.1 += stretch;
+            sums.2 += shrink;
+        }
+        (sums.0, sums.1.value(), sums.2.value())
+    }
+
+    /// Returns the width, stretch, and shrink of each item, and whether or not it is a legal
+    /// breakpoint.
+    pub(crate) fn breakpoints(&self) -> &[Breakpoint<N>] {
+        &self.breakpoints
+    }
+}
+
+/// The items of a prepared paragraph, which are borrowed unless the paragraph was deserialized.
+enum Items<'a, T> {
+    Borrowed(&'a [T]),
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    Owned(Vec<T>),
+}
+
+impl<T> Deref for Items<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Items::Borrowed(items) => items,
+            Items::Owned(items) => items,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Items<'_, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.deref().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, Box, Glue, Penalty, N> serde::Deserialize<'de>
+    for PreparedParagraph<'_, Box, Glue, Penalty, N>
+where
+    Item<Box, Glue, Penalty, N>: serde::Deserialize<'de>,
+    N: serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// The serialized form of a prepared paragraph, before its metadata is validated.
+        #[derive(serde::Deserialize)]
+        #[serde(bound(deserialize = "T: serde::Deserialize<'de>, N: serde::Deserialize<'de>"))]
+        struct Serialized<T, N> {
+            items: Vec<T>,
+            breakpoints: Vec<Breakpoint<N>>,
+        }
+
+        let Serialized { items, breakpoints } = Serialized::deserialize(deserializer)?;
+        if breakpoints.len() != items.len() {
+            return Err(serde::de::Error::invalid_length(
+                breakpoints.len(),
+                &"one breakpoint per item",
+            ));
+        }
+        Ok(PreparedParagraph {
+            items: Items::Owned(items),
+            breakpoints,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::monospace;
+    use crate::{FirstFit, KnuthPlass, ParagraphLayout};
+
+    #[test]
+    fn layout_prepared() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let prepared = PreparedParagraph::new(&items);
+        assert_eq!(prepared.sum_metrics(3, 9), (6.0, 1.0, 0.0));
+
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let knuth_plass = KnuthPlass::new();
+        for line_width in [15.0, 20.0, 25.0] {
+            let breaks =
+                |lines: Vec<crate::Line>| lines.iter().map(|l| l.break_at).collect::<Vec<_>>();
+            assert_eq!(
+                breaks(first_fit.layout_prepared(&prepared, line_width)),
+                breaks(first_fit.layout_paragraph(&items, line_width))
+            );
+            assert_eq!(
+                breaks(knuth_plass.layout_prepared(&prepared, line_width)),
+                breaks(knuth_plass.layout_paragraph(&items, line_width))
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let prepared = PreparedParagraph::new(&items);
+        let bytes = postcard::to_allocvec(&prepared).unwrap();
+        let deserialized: PreparedParagraph = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(deserialized.sum_metrics(3, 9), prepared.sum_metrics(3, 9));
+
+        let knuth_plass = KnuthPlass::new();
+        for line_width in [15.0, 20.0, 25.0] {
+            let lines = |prepared: &PreparedParagraph| {
+                knuth_plass
+                    .layout_prepared(prepared, line_width)
+                    .iter()
+                    .map(|l| (l.break_at, l.adjustment_ratio))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(lines(&deserialized), lines(&prepared));
+        }
+
+        // Metadata that does not cover exactly the paragraph's items is rejected.
+        let mut truncated = PreparedParagraph::new(&items);
+        truncated.breakpoints.pop();
+        let bytes = postcard::to_allocvec(&truncated).unwrap();
+        assert!(postcard::from_bytes::<PreparedParagraph>(&bytes).is_err());
+    }
+}
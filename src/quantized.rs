@@ -0,0 +1,143 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use fixed::types::I32F32;
+
+use crate::math::Fixed;
+use crate::{Item, Line, ParagraphLayout};
+
+/// The number of grid units per unit of width.
+const UNITS_PER_POINT: i16 = 1000;
+
+/// Adapts a layout algorithm that operates on fixed-point numbers to accept `f32` items. Item
+/// widths, stretch, and shrink are snapped to a grid of millipoints before layout, so the break
+/// decisions are computed deterministically using integer arithmetic regardless of platform. The
+/// adjustment ratios of the resulting lines are converted back to `f32`.
+///
+/// The data attached to items is not passed to the wrapped algorithm.
+pub struct QuantizedLayout<A> {
+    inner: A,
+}
+
+impl<A> QuantizedLayout<A> {
+    /// Creates a new QuantizedLayout that wraps the given algorithm.
+    pub fn new(inner: A) -> Self {
+        QuantizedLayout { inner }
+    }
+}
+
+/// Converts a length to a whole number of millipoints. Infinite lengths saturate.
+fn quantize_length(x: f32) -> Fixed<I32F32> {
+    (Fixed::saturating_from_num(x) * Fixed::saturating_from_num(UNITS_PER_POINT)).saturating_round()
+}
+
+/// Converts a dimensionless quantity to fixed-point. Infinite quantities saturate.
+fn quantize(x: f32) -> Fixed<I32F32> {
+    Fixed::saturating_from_num(x)
+}
+
+fn unquantize(x: Fixed<I32F32>) -> f32 {
+    if x == Fixed::MAX {
+        f32::INFINITY
+    } else if x == Fixed::MIN {
+        f32::NEG_INFINITY
+    } else {
+        x.to_num()
+    }
+}
+
+impl<Box, Glue, Penalty, A> ParagraphLayout<Box, Glue, Penalty, f32> for QuantizedLayout<A>
+where
+    A: ParagraphLayout<(), (), (), Fixed<I32F32>>,
+{
+    fn layout_paragraph(
+        &self,
+        items: &[Item<Box, Glue, Penalty, f32>],
+        line_width: f32,
+    ) -> Vec<Line<f32>> {
+        let items: Vec<Item<(), (), (), Fixed<I32F32>>> = items
+            .iter()
+            .map(|item| match *item {
+                Item::Box { width, .. } => Item::Box {
+                    width: quantize_length(width),
+                    data: (),
+                },
+                Item::Glue {
+                    width,
+                    stretch,
+                    stretch_order,
+                    shrink,
+                    shrink_order,
+                    priority,
+                    discardable,
+                    ..
+                } => Item::Glue {
+                    width: quantize_length(width),
+                    stretch: quantize_length(stretch),
+                    stretch_order,
+                    shrink: quantize_length(shrink),
+                    shrink_order,
+                    priority,
+                    discardable,
+                    data: (),
+                },
+                Item::Penalty {
+                    width, cost, flags, ..
+                } => Item::Penalty {
+                    width: quantize_length(width),
+                    cost: quantize(cost),
+                    flags,
+                    data: (),
+                },
+                Item::Kern { width } => Item::Kern {
+                    width: quantize_length(width),
+                },
+                Item::Tab { width } => Item::Tab {
+                    width: quantize_length(width),
+                },
+                Item::BreakGlue {
+                    break_width,
+                    stretch,
+                    shrink,
+                } => Item::BreakGlue {
+                    break_width: quantize_length(break_width),
+                    stretch: quantize_length(stretch),
+                    shrink: quantize_length(shrink),
+                },
+            })
+            .collect();
+
+        self.inner
+            .layout_paragraph(&items, quantize_length(line_width))
+            .into_iter()
+            .map(|l| Line {
+                break_at: l.break_at,
+                adjustment_ratio: unquantize(l.adjustment_ratio),
+                truncated: l.truncated,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::monospace;
+    use crate::KnuthPlass;
+
+    #[test]
+    fn matches_float_layout() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let expected = KnuthPlass::new().layout_paragraph(&items, 20.0);
+
+        let quantized = QuantizedLayout::new(KnuthPlass::<Fixed<I32F32>>::new());
+        let lines = quantized.layout_paragraph(&items, 20.0);
+        assert_eq!(
+            lines.iter().map(|l| l.break_at).collect::<Vec<_>>(),
+            expected.iter().map(|l| l.break_at).collect::<Vec<_>>()
+        );
+        for (l, e) in lines.iter().zip(&expected) {
+            assert!((l.adjustment_ratio - e.adjustment_ratio).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use crate::math::Num;
+use crate::{Item, Line};
+
+/// Describes the shape of an area into which a paragraph is poured. The width that is available
+/// to a line may depend on how far below the top of the region the line begins, e.g. for text
+/// that is set inside a circle.
+pub trait Region<N: Num> {
+    /// Returns the width available to a line whose top edge lies `y_offset` below the top of the
+    /// region.
+    fn width_for(&self, y_offset: N) -> N;
+}
+
+impl<N: Num, F: Fn(N) -> N> Region<N> for F {
+    fn width_for(&self, y_offset: N) -> N {
+        self(y_offset)
+    }
+}
+
+/// Provides the vertical metrics of the data attached to a box item. The height of a line is the
+/// sum of the greatest height and the greatest depth of the boxes on that line, after each box is
+/// shifted by its vertical shift.
+pub trait BoxMetrics<N: Num> {
+    /// Returns the distance from the box's baseline to its top edge.
+    fn height(&self) -> N;
+
+    /// Returns the distance from the box's baseline to its bottom edge. Defaults to 0.
+    fn depth(&self) -> N {
+        N::from(0)
+    }
+
+    /// Returns the distance by which the box is raised above the baseline, e.g. for a superscript,
+    /// or lowered below it if negative, e.g. for a subscript. The shift is added to the box's
+    /// height and subtracted from its depth when computing the height of its line; it has no
+    /// effect on the horizontal layout. Defaults to 0.
+    fn vertical_shift(&self) -> N {
+        N::from(0)
+    }
+}
+
+/// Returns the height and depth of each item in a paragraph, accounting for the vertical shift of
+/// each box. Items other than boxes have no height or depth.
+pub(crate) fn vertical_metrics<Box: BoxMetrics<N>, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+) -> Vec<(N, N)> {
+    items
+        .iter()
+        .map(|item| match item {
+            Item::Box { data, .. } => {
+                let shift = data.vertical_shift();
+                (data.height() + shift, data.depth() - shift)
+            }
+            _ => (N::from(0), N::from(0)),
+        })
+        .collect()
+}
+
+/// Returns the height of a line given the vertical metrics of its items.
+pub(crate) fn line_height<N: Num>(metrics: &[(N, N)]) -> N {
+    let (height, depth) =
+        metrics
+            .iter()
+            .fold((N::from(0), N::from(0)), |(height, depth), &(h, d)| {
+                (
+                    if h > height { h } else { height },
+                    if d > depth { d } else { depth },
+                )
+            });
+    height + depth
+}
+
+/// Splits a region's layout of a paragraph with `item_count` items after its first `max_lines`
+/// lines. Returns the lines that fit and the index of the item that begins the first remaining
+/// line, if any lines remain, or `None` if the layout failed, i.e. if it has no lines even though
+/// the paragraph has items.
+pub(crate) fn split_lines<N: Num>(
+    mut lines: Vec<Line<N>>,
+    item_count: usize,
+    max_lines: usize,
+) -> Option<(Vec<Line<N>>, Option<usize>)> {
+    if lines.is_empty() && item_count != 0 {
+        return None;
+    }
+    if lines.len() <= max_lines {
+        return Some((lines, None));
+    }
+    lines.truncate(max_lines);
+    let resume = lines.last().map_or(0, |l| l.break_at + 1);
+    Some((lines, Some(resume)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{monospace, split};
+    use crate::{FirstFit, KnuthPlass};
+
+    struct Glyph(f32);
+
+    impl BoxMetrics<f32> for Glyph {
+        fn height(&self) -> f32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn layout_in_region() {
+        // A region that narrows once the first two units of height have been filled. The first
+        // glyph is twice as tall as the rest, so only the first line is set at the full width.
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let items: Vec<Item<Glyph>> = monospace(text)
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| match item {
+                Item::Box { width, .. } => Item::Box {
+                    width,
+                    data: Glyph(if i == 0 { 2.0 } else { 1.0 }),
+                },
+                Item::Glue {
+                    width,
+                    stretch,
+                    stretch_order,
+                    shrink,
+                    shrink_order,
+                    priority,
+                    discardable,
+                    data,
+                } => Item::Glue {
+                    width,
+                    stretch,
+                    stretch_order,
+                    shrink,
+                    shrink_order,
+                    priority,
+                    discardable,
+                    data,
+                },
+                Item::Penalty {
+                    width,
+                    cost,
+                    flags,
+                    data,
+                } => Item::Penalty {
+                    width,
+                    cost,
+                    flags,
+                    data,
+                },
+                Item::Kern { width } => Item::Kern { width },
+                Item::Tab { width } => Item::Tab { width },
+                Item::BreakGlue {
+                    break_width,
+                    stretch,
+                    shrink,
+                } => Item::BreakGlue {
+                    break_width,
+                    stretch,
+                    shrink,
+                },
+            })
+            .collect();
+        let region = |y: f32| if y < 2.0 { 20.0 } else { 10.0 };
+
+        let first_fit = FirstFit::new().with_threshold(f32::INFINITY);
+        let lines = split(text, &first_fit.layout_in_region(&items, &region));
+        assert_eq!(
+            lines,
+            ["The quick brown fox", "jumps", "over the", "lazy dog."]
+        );
+
+        // Only two lines fit, so the remainder resumes at "over".
+        let (lines, resume) = first_fit.layout_until(&items, &region, 2).unwrap();
+        assert_eq!(split(text, &lines), ["The quick brown fox", "jumps"]);
+        assert_eq!(resume, Some(26));
+        let (lines, resume) = first_fit.layout_until(&items[26..], &region, 2).unwrap();
+        assert_eq!(split(&text[26..], &lines), ["over the lazy dog."]);
+        assert_eq!(resume, None);
+
+        let knuth_plass = KnuthPlass::new().with_threshold(f32::INFINITY);
+        let lines = split(text, &knuth_plass.layout_in_region(&items, &region));
+        assert_eq!(
+            lines,
+            ["The quick brown fox", "jumps over", "the lazy", "dog."]
+        );
+        let (lines, resume) = knuth_plass.layout_until(&items, &region, 2).unwrap();
+        assert_eq!(split(text, &lines), ["The quick brown fox", "jumps over"]);
+        assert_eq!(resume, Some(31));
+        assert_eq!(
+            knuth_plass.layout_until(&items, &region, 4).unwrap().1,
+            None
+        );
+
+        // A paragraph that cannot be laid out is distinguishable from one that is complete.
+        let narrow = |_: f32| 3.0;
+        assert!(first_fit.layout_until(&items, &narrow, 2).is_none());
+        assert!(knuth_plass.layout_until(&items, &narrow, 2).is_none());
+        assert!(knuth_plass.layout_until(&items[..0], &narrow, 2).is_some());
+    }
+
+    #[test]
+    fn vertical_shift() {
+        struct Script {
+            height: f32,
+            depth: f32,
+            shift: f32,
+        }
+
+        impl BoxMetrics<f32> for Script {
+            fn height(&self) -> f32 {
+                self.height
+            }
+
+            fn depth(&self) -> f32 {
+                self.depth
+            }
+
+            fn vertical_shift(&self) -> f32 {
+                self.shift
+            }
+        }
+
+        // A superscript raises the line's ascent above that of the base glyph, while its depth
+        // lies above the baseline and does not affect the line's descent.
+        let glyph = |shift| Item::Box {
+            width: 1.0,
+            data: Script {
+                height: 1.0,
+                depth: 0.25,
+                shift,
+            },
+        };
+        let items: Vec<Item<Script>> = alloc::vec![glyph(0.0), glyph(0.0)];
+        assert_eq!(line_height(&vertical_metrics(&items)), 1.25);
+        let items: Vec<Item<Script>> = alloc::vec![glyph(0.0), glyph(0.5)];
+        assert_eq!(vertical_metrics(&items)[1], (1.5, -0.25));
+        assert_eq!(line_height(&vertical_metrics(&items)), 1.75);
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt::Write;
+use std::string::String;
+
+use crate::math::Num;
+use crate::{Fitness, Item, Line};
+
+/// Renders lines of text inside a frame of box-drawing characters, padding each line with spaces
+/// to `width` columns, e.g. to display a laid-out paragraph in a terminal. Every character is
+/// treated as one column wide, and lines that are wider than `width` overflow the frame.
+pub fn render_framed(lines: &[&str], width: usize) -> String {
+    let rule = "━".repeat(width);
+    let mut result = String::new();
+    result.push('┏');
+    result.push_str(&rule);
+    result.push_str("┓\n");
+    for line in lines {
+        result.push('┃');
+        result.push_str(line);
+        result.push_str(&" ".repeat(width.saturating_sub(line.chars().count())));
+        result.push_str("┃\n");
+    }
+    result.push('┗');
+    result.push_str(&rule);
+    result.push_str("┛\n");
+    result
+}
+
+/// Renders a laid-out paragraph as a table with one row per line that gives the line's number,
+/// the index and kind of the item at which it breaks, its adjustment ratio, and its fitness
+/// class. Unlike `render_framed`, this only needs the items and lines, so the output is suitable
+/// for snapshot tests of the layout algorithms themselves.
+pub fn debug_layout<Box, Glue, Penalty, N: Num>(
+    items: &[Item<Box, Glue, Penalty, N>],
+    lines: &[Line<N>],
+) -> String {
+    let mut result = String::from("line break item       ratio fitness\n");
+    for (l, line) in lines.iter().enumerate() {
+        let kind = match items.get(line.break_at) {
+            Some(Item::Box { .. }) => "box",
+            Some(Item::Glue { .. }) => "glue",
+            Some(item @ Item::Penalty { .. }) if item.is_forced() => "forced",
+            Some(Item::Penalty { .. }) => "penalty",
+            Some(Item::Kern { .. }) => "kern",
+            Some(Item::Tab { .. }) => "tab",
+            Some(Item::BreakGlue { .. }) => "break",
+            None => "-",
+        };
+        let r = line.adjustment_ratio;
+        writeln!(
+            result,
+            "{:>4} {:>5} {:<7} {:>8.3?} {:?}",
+            l + 1,
+            line.break_at,
+            kind,
+            r,
+            Fitness::of(r)
+        )
+        .unwrap();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::monospace;
+    use crate::{KnuthPlass, ParagraphLayout};
+
+    #[test]
+    fn debug_layout() {
+        let items = monospace("The quick brown fox jumps over the lazy dog.");
+        let lines = KnuthPlass::new().layout_paragraph(&items, 20.0);
+        assert_eq!(
+            super::debug_layout(&items, &lines),
+            "\
+line break item       ratio fitness
+   1    19 glue       0.333 One
+   2    39 glue       0.333 One
+   3    45 forced     0.000 One
+"
+        );
+    }
+
+    #[test]
+    fn render_framed() {
+        assert_eq!(
+            super::render_framed(&["The quick", "brown fox jumps"], 12),
+            "┏━━━━━━━━━━━━┓\n┃The quick   ┃\n┃brown fox jumps┃\n┗━━━━━━━━━━━━┛\n"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use fixed::types::I32F32;
+
+use crate::{convert_items, Fixed, Item, Line, ParagraphLayout, PenaltyFlags};
+
+/// Processes a string into items using the same scheme as the examples: every character is a
+/// unit-width box or glue, and the paragraph is terminated by finishing glue and a forced break.
+pub(crate) fn monospace(text: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    for c in text.chars() {
+        items.push(if c.is_whitespace() && !items.is_empty() {
+            Item::glue(1.0, 1.0, 0.0, ())
+        } else {
+            Item::Box {
+                width: 1.0,
+                data: (),
+            }
+        });
+    }
+    items.push(Item::glue(0.0, 100000.0, 0.0, ()));
+    items.push(Item::Penalty {
+        width: 0.0,
+        cost: f32::NEG_INFINITY,
+        flags: PenaltyFlags::AVOID_CONSECUTIVE,
+        data: (),
+    });
+    items
+}
+
+/// Like `monospace`, but the glue between words has no stretch, so only the finishing glue can
+/// absorb leftover space.
+pub(crate) fn rigid_monospace(text: &str) -> Vec<Item> {
+    let mut items = monospace(text);
+    let n = items.len();
+    for item in &mut items[..n - 2] {
+        if let Item::Glue { stretch, .. } = item {
+            *stretch = 0.0;
+        }
+    }
+    items
+}
+
+/// Returns a paragraph of `words` boxes of width 0.1 separated by glue whose width, stretch, and
+/// shrink are all 0.1, ending in a forced break. None of these values is exact in floating point,
+/// so the adjustment ratio of a line that should be exactly -1 or 1 is slightly off.
+pub(crate) fn tenths(words: usize) -> Vec<Item> {
+    let mut items = Vec::new();
+    for i in 0..words {
+        if i != 0 {
+            items.push(Item::glue(0.1, 0.1, 0.1, ()));
+        }
+        items.push(Item::Box {
+            width: 0.1,
+            data: (),
+        });
+    }
+    items.push(Item::forced_break(()));
+    items
+}
+
+/// Splits a string that was processed by `monospace` into lines at the given breaks.
+pub(crate) fn split<'a>(text: &'a str, lines: &[Line]) -> Vec<&'a str> {
+    let mut start = 0;
+    lines
+        .iter()
+        .map(|l| {
+            let end = l.break_at.min(text.len());
+            let line = &text[start..end];
+            start = (end + 1).min(text.len());
+            line
+        })
+        .collect()
+}
+
+/// Lays out `f32` items using a fixed-point layout and returns the break positions. Fixed-point
+/// arithmetic is deterministic, so the breaks do not depend on the platform's float behavior or on
+/// whether the `std` or `libm` feature is enabled.
+pub(crate) fn fixed_breaks<P: ParagraphLayout<(), (), (), Fixed<I32F32>>>(
+    layout: &P,
+    items: &[Item],
+    line_width: f32,
+) -> Vec<usize> {
+    let items = convert_items(items, Fixed::from_num);
+    layout
+        .layout_paragraph(&items, Fixed::from_num(line_width))
+        .iter()
+        .map(|l| l.break_at)
+        .collect()
+}
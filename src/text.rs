@@ -0,0 +1,161 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{FirstFit, Item, KnuthPlass, Line, ParagraphLayout, PenaltyFlags};
+
+/// Wraps a string into lines of at most `columns` characters, treating every character as one
+/// column wide. Each run of whitespace is collapsed into a single break opportunity, and leading
+/// and trailing whitespace is dropped. Because the returned lines are slices of the original text,
+/// whitespace within a line keeps its original width. Lines are broken using Knuth-Plass with an
+/// unbounded threshold; if a word is wider than `columns`, the line containing it is allowed to
+/// overflow.
+///
+/// Returns the lines as slices of `text`.
+pub fn wrap_monospace(text: &str, columns: usize) -> Vec<&str> {
+    // Process the text into its items. Each box records the byte range of its character.
+    let mut items: Vec<Item<Range<usize>>> = Vec::new();
+    let mut pending_space = 0;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            if !items.is_empty() {
+                pending_space += 1;
+            }
+            continue;
+        }
+        if pending_space != 0 {
+            items.push(Item::glue(pending_space as f32, 1.0, 0.0, ()));
+            pending_space = 0;
+        }
+        items.push(Item::Box {
+            width: 1.0,
+            data: i..i + c.len_utf8(),
+        });
+    }
+    if items.is_empty() {
+        return Vec::new();
+    }
+    items.push(Item::glue(0.0, f32::INFINITY, 0.0, ()));
+    items.push(Item::Penalty {
+        width: 0.0,
+        cost: f32::NEG_INFINITY,
+        flags: PenaltyFlags::AVOID_CONSECUTIVE,
+        data: (),
+    });
+
+    // Calculate the paragraph's breaks, falling back to an overflowing first-fit layout if there
+    // is no feasible layout.
+    let line_width = columns as f32;
+    let mut breaks = KnuthPlass::new()
+        .with_threshold(f32::INFINITY)
+        .layout_paragraph(&items, line_width);
+    if breaks.is_empty() {
+        breaks = FirstFit::new()
+            .with_threshold(f32::INFINITY)
+            .allow_overflow(true)
+            .layout_paragraph(&items, line_width);
+    }
+
+    // Map each line back to the slice of text that spans its boxes.
+    let mut start = 0;
+    let mut lines = Vec::new();
+    for b in breaks {
+        let mut boxes = items[start..b.break_at]
+            .iter()
+            .filter_map(|item| match item {
+                Item::Box { data, .. } => Some(data),
+                _ => None,
+            });
+        if let Some(first) = boxes.next() {
+            let last = boxes.next_back().unwrap_or(first);
+            lines.push(&text[first.start..last.end]);
+        }
+        start = b.break_at + 1;
+    }
+    lines
+}
+
+/// Maps laid-out lines back to slices of the text from which their items were produced.
+/// `item_char_indices` gives the index of the character in `text` at which each item starts;
+/// several items may start at the same character, and a character may have no items at all.
+/// Items beyond the end of `item_char_indices` are treated as starting at the end of the text.
+///
+/// Each line spans the text from the start of the item that follows the previous line's break up
+/// to the start of the line's own break item, so the text of the break item itself is dropped.
+pub fn lines_to_strings<'a>(
+    text: &'a str,
+    item_char_indices: &[usize],
+    lines: &[Line<f32>],
+) -> Vec<&'a str> {
+    // Map character indices to byte offsets, including the offset of the end of the text.
+    let offsets: Vec<usize> = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(core::iter::once(text.len()))
+        .collect();
+    let byte_offset = |item: usize| {
+        item_char_indices
+            .get(item)
+            .and_then(|&c| offsets.get(c))
+            .copied()
+            .unwrap_or(text.len())
+    };
+
+    let mut start = 0;
+    lines
+        .iter()
+        .map(|l| {
+            let (begin, end) = (byte_offset(start), byte_offset(l.break_at));
+            start = l.break_at + 1;
+            &text[begin.min(end)..end]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap() {
+        let text = "  The quick  brown fox\tjumps over the lazy dog.\n";
+        assert_eq!(
+            wrap_monospace(text, 20),
+            ["The quick  brown fox", "jumps over the lazy", "dog."]
+        );
+        assert_eq!(
+            wrap_monospace("supercalifragilistic is long", 10),
+            ["supercalifragilistic", "is long"]
+        );
+        assert!(wrap_monospace(" \n ", 10).is_empty());
+    }
+
+    #[test]
+    fn lines_to_strings() {
+        // "ﬁ" is rendered as two items, and the trailing glue and penalty have no characters.
+        let text = "ﬁne day today";
+        let mut items = Vec::new();
+        let mut item_char_indices = Vec::new();
+        for (i, c) in text.chars().enumerate() {
+            let widths: &[f32] = if c == 'ﬁ' { &[1.0, 1.0] } else { &[1.0] };
+            for &width in widths {
+                items.push(if c == ' ' {
+                    Item::glue(width, 1.0, 0.0, ())
+                } else {
+                    Item::Box { width, data: () }
+                });
+                item_char_indices.push(i);
+            }
+        }
+        items.push(Item::glue(0.0, f32::INFINITY, 0.0, ()));
+        items.push(Item::flagged_penalty(0.0, f32::NEG_INFINITY, true, ()));
+
+        let breaks = KnuthPlass::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&items, 9.0);
+        assert_eq!(
+            super::lines_to_strings(text, &item_char_indices, &breaks),
+            ["ﬁne day", "today"]
+        );
+    }
+}
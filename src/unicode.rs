@@ -0,0 +1,196 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use unicode_linebreak::{break_property, linebreaks, BreakClass, BreakOpportunity};
+
+use crate::math::Num;
+use crate::Item;
+
+/// Measures the characters of a string as it is converted into items.
+pub trait CharMetrics<N> {
+    /// Returns the width of the given character, which is not whitespace.
+    fn width(&self, c: char) -> N;
+
+    /// Returns the width, stretch, and shrink of the glue that represents the given whitespace
+    /// character.
+    fn space(&self, c: char) -> (N, N, N);
+
+    /// Returns true if a line may break after the given character, which is not whitespace, even
+    /// if the Unicode line breaking algorithm does not allow it. Such breaks are typically hard
+    /// hyphens, e.g. in "well-known", and should not end consecutive lines. Defaults to true for
+    /// hyphens and dashes.
+    fn breaks_after(&self, c: char) -> bool {
+        matches!(c, '-' | '\u{2010}' | '\u{2013}' | '\u{2014}')
+    }
+}
+
+/// Converts a string into items whose break opportunities are determined by the Unicode line
+/// breaking algorithm (UAX #14), e.g. at spaces, after hyphens, and between CJK ideographs.
+///
+/// Each character other than whitespace becomes a box. Whitespace becomes glue, which is only a
+/// legal breakpoint if the algorithm allows a break after it; other glue, e.g. a no-break space,
+/// is preceded by a forbidden break. An allowed break that does not follow whitespace becomes a
+/// zero-width penalty with no cost. So does a break after each character for which
+/// `CharMetrics::breaks_after` returns true, unless it is followed by whitespace or a mandatory
+/// break; these penalties are flagged with `PenaltyFlags::AVOID_CONSECUTIVE`. Each mandatory
+/// break, including the end of the string, becomes finishing glue followed by a forced break, and
+/// the characters that cause mandatory breaks are dropped.
+///
+/// Returns an empty Vec if the string is empty.
+pub fn items_from_str_uax14<N: Num>(
+    s: &str,
+    metrics: &impl CharMetrics<N>,
+) -> Vec<Item<(), (), (), N>> {
+    let mut items = Vec::new();
+    if s.is_empty() {
+        return items;
+    }
+
+    let mut start = 0;
+    for (end, opportunity) in linebreaks(s) {
+        // Breaks are not allowed within a segment, save after its trailing whitespace.
+        let segment = &s[start..end];
+        let trailing = segment.trim_end_matches(char::is_whitespace).len();
+        for (i, c) in segment.char_indices() {
+            if matches!(
+                break_property(c as u32),
+                BreakClass::Mandatory
+                    | BreakClass::CarriageReturn
+                    | BreakClass::LineFeed
+                    | BreakClass::NextLine
+            ) {
+                continue;
+            }
+
+            if c.is_whitespace() {
+                if i < trailing && matches!(items.last(), Some(Item::Box { .. })) {
+                    items.push(Item::forbidden_break(()));
+                }
+                let (width, stretch, shrink) = metrics.space(c);
+                items.push(Item::glue(width, stretch, shrink, ()));
+            } else {
+                items.push(Item::Box {
+                    width: metrics.width(c),
+                    data: (),
+                });
+                if metrics.breaks_after(c) && i + c.len_utf8() < trailing {
+                    items.push(Item::flagged_penalty(N::from(0), N::from(0), true, ()));
+                }
+            }
+        }
+
+        match opportunity {
+            BreakOpportunity::Mandatory => {
+                items.push(Item::glue(N::from(0), N::INFINITY, N::from(0), ()));
+                items.push(Item::forced_break(()));
+            }
+            BreakOpportunity::Allowed if trailing == segment.len() => {
+                let flagged = segment
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| metrics.breaks_after(c));
+                items.push(Item::flagged_penalty(N::from(0), N::from(0), flagged, ()));
+            }
+            BreakOpportunity::Allowed => {}
+        }
+        start = end;
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FirstFit, ParagraphLayout, PenaltyFlags};
+
+    struct Monospace;
+
+    impl CharMetrics<f32> for Monospace {
+        fn width(&self, _c: char) -> f32 {
+            1.0
+        }
+
+        fn space(&self, _c: char) -> (f32, f32, f32) {
+            (1.0, 1.0, 0.0)
+        }
+    }
+
+    /// Describes items as a string: boxes are `x`, glue is a space, forced breaks are `|`,
+    /// forbidden breaks are `~`, and other penalties are `/`.
+    fn describe(items: &[Item]) -> alloc::string::String {
+        items
+            .iter()
+            .map(|item| match item {
+                Item::Box { .. } => 'x',
+                Item::Glue { .. } => ' ',
+                Item::Penalty { .. } if item.is_forced() => '|',
+                Item::Penalty { .. } if item.is_forbidden() => '~',
+                _ => '/',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn uax14() {
+        let items = items_from_str_uax14("well-known words", &Monospace);
+        assert_eq!(describe(&items), "xxxxx/xxxxx xxxxx |");
+        assert_eq!(
+            describe(&items_from_str_uax14("漢字かな", &Monospace)),
+            "x/x/x/x |"
+        );
+        assert_eq!(
+            describe(&items_from_str_uax14("a\u{a0}b c\nd", &Monospace)),
+            "x~ x x |x |"
+        );
+        assert!(items_from_str_uax14("", &Monospace).is_empty());
+
+        let lines = FirstFit::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&items, 8.0);
+        assert_eq!(
+            lines.iter().map(|l| l.break_at).collect::<Vec<_>>(),
+            [5, 11, 18]
+        );
+    }
+
+    #[test]
+    fn breaks_after() {
+        // The break after the hyphen is flagged.
+        let items = items_from_str_uax14("well-known", &Monospace);
+        assert!(matches!(
+            items[5],
+            Item::Penalty { cost: 0.0, flags, .. } if flags == PenaltyFlags::AVOID_CONSECUTIVE
+        ));
+        let lines = FirstFit::new()
+            .with_threshold(f32::INFINITY)
+            .layout_paragraph(&items, 6.0);
+        assert_eq!(lines[0].break_at, 5);
+
+        // Breaks may be induced where the Unicode algorithm does not allow them.
+        struct Underscores;
+
+        impl CharMetrics<f32> for Underscores {
+            fn width(&self, _c: char) -> f32 {
+                1.0
+            }
+
+            fn space(&self, _c: char) -> (f32, f32, f32) {
+                (1.0, 1.0, 0.0)
+            }
+
+            fn breaks_after(&self, c: char) -> bool {
+                c == '_'
+            }
+        }
+
+        assert_eq!(describe(&items_from_str_uax14("a_b", &Monospace)), "xxx |");
+        assert_eq!(
+            describe(&items_from_str_uax14("a_b", &Underscores)),
+            "xx/x |"
+        );
+        assert_eq!(
+            describe(&items_from_str_uax14("a_ b", &Underscores)),
+            "xx x |"
+        );
+    }
+}